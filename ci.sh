@@ -20,10 +20,23 @@ cargo check \
     --all-features \
     --profile ci
 
+# Firmware helpers
+cargo test \
+    --manifest-path crates/poststation-fw-util/Cargo.toml \
+    --profile ci
+cargo check \
+    --manifest-path crates/poststation-fw-util/Cargo.toml \
+    --target thumbv6m-none-eabi \
+    --profile ci
+
 # SDK crate
 cargo check \
     --manifest-path tools/poststation-sdk/Cargo.toml \
     --profile ci
+cargo check \
+    --manifest-path tools/poststation-sdk/Cargo.toml \
+    --all-features \
+    --profile ci
 
 # CLI tool
 cargo build \
@@ -79,3 +92,8 @@ cargo build \
 cargo build \
     --manifest-path examples/waveshare-rp2040-keyboard-3/demo/Cargo.toml \
     --profile ci
+
+### rest-server
+cargo build \
+    --manifest-path examples/rest-server/Cargo.toml \
+    --profile ci
@@ -0,0 +1,146 @@
+//! Plain data types shared by the postcard-rpc flavored API
+//!
+//! These types only depend on `serde` and `postcard-schema`, and are available
+//! without the `postsock` feature, for tools that only need to work with the
+//! shapes of the data, such as deserializing a captured `Log`. With the
+//! `postsock` feature, they are also re-exported from [`postsock`](crate::postsock).
+
+use core::{fmt, str::FromStr};
+
+use chrono::{DateTime, Local};
+use postcard_schema::Schema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub struct DeviceData {
+    pub serial: u64,
+    pub name: String,
+    pub is_connected: bool,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    /// When poststation last had contact with the device, in milliseconds since
+    /// the unix epoch, or `None` if unknown
+    pub last_seen: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub enum Direction {
+    Before,
+    After,
+}
+
+/// A point in a device's history, used to page through logs and topic messages
+///
+/// As a string (with [`Display`](fmt::Display) and [`FromStr`]), an anchor is a
+/// UUID, a unix millisecond timestamp, or one of `earliest` and `latest`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub enum Anchor {
+    Uuid(Uuidv7),
+    UnixMsTs(u64),
+}
+
+impl Anchor {
+    /// Before all stored items
+    pub const EARLIEST: Self = Anchor::UnixMsTs(0);
+    /// After all stored items
+    pub const LATEST: Self = Anchor::UnixMsTs(u64::MAX);
+}
+
+impl fmt::Display for Anchor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::EARLIEST => f.write_str("earliest"),
+            Self::LATEST => f.write_str("latest"),
+            Anchor::Uuid(uuid) => Uuid::from(uuid).fmt(f),
+            Anchor::UnixMsTs(ts) => ts.fmt(f),
+        }
+    }
+}
+
+impl FromStr for Anchor {
+    type Err = ParseAnchorError;
+
+    /// Parse a UUID, a unix millisecond timestamp, `earliest` or `latest`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("earliest") {
+            return Ok(Self::EARLIEST);
+        }
+        if s.eq_ignore_ascii_case("latest") {
+            return Ok(Self::LATEST);
+        }
+        if let Ok(uuid) = s.parse::<Uuid>() {
+            return Ok(Anchor::Uuid(uuid.into()));
+        }
+        if let Ok(ts) = s.parse::<u64>() {
+            return Ok(Anchor::UnixMsTs(ts));
+        }
+        Err(ParseAnchorError(s.to_string()))
+    }
+}
+
+/// The error returned when parsing an [`Anchor`] fails
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseAnchorError(String);
+
+impl ParseAnchorError {
+    /// No anchor was given at all
+    #[cfg(feature = "rest-api")]
+    pub(crate) fn missing() -> Self {
+        Self(String::new())
+    }
+}
+
+impl fmt::Display for ParseAnchorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("one of 'anchor', 'uuid' or 'unix_ms_ts' is required");
+        }
+        write!(
+            f,
+            "'{}' is neither a UUID, a unix millisecond timestamp, 'earliest' nor 'latest'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseAnchorError {}
+
+// TODO: now that postcard-schema has a Schema impl for Uuid we might
+// not actually need this anymore
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Hash, Schema)]
+pub struct Uuidv7(pub [u8; 16]);
+
+impl From<Uuid> for Uuidv7 {
+    fn from(value: Uuid) -> Self {
+        Self(value.into_bytes())
+    }
+}
+
+impl From<Uuidv7> for Uuid {
+    fn from(val: Uuidv7) -> Self {
+        Uuid::from_bytes(val.0)
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub struct Log {
+    pub uuidv7: Uuidv7,
+    pub msg: String,
+}
+
+impl Uuidv7 {
+    pub fn id_to_time(&self) -> DateTime<Local> {
+        let uuid = Uuid::from_bytes(self.0);
+        let ts = uuid.get_timestamp().unwrap();
+        let (a, b) = ts.to_unix();
+        DateTime::from_timestamp(a as i64, b).unwrap().into()
+    }
+
+    /// The unix millisecond timestamp held in the first 48 bits of the UUID
+    pub fn unix_ms(&self) -> u64 {
+        let [a, b, c, d, e, f, ..] = self.0;
+        u64::from_be_bytes([0, 0, a, b, c, d, e, f])
+    }
+}
@@ -13,9 +13,24 @@
 //! format used in either may vary based on the realities of the interfaces. As an example, the
 //! REST API may contain some data in the query parameters, rather than in the body of the request,
 //! and may prefer encoding serial numbers as hex strings instead of a numerical `u64`, due to
-//! the use of floating point numbers in JS itself as well as many JSON libraries.
+//! the use of floating point numbers in JS itself as well as many JSON libraries. The [`Serial`]
+//! type handles this automatically, encoding as a `u64` for postcard and a hex string for JSON.
+//!
+//! ## Features
+//!
+//! * `postsock` (default): the endpoints and topics of the postcard-rpc flavored API,
+//!   in [`postsock`]. This pulls in `postcard-rpc`.
+//! * `rest-api` (default): the types of the REST flavored API, in `rest`. Requires `postsock`.
+//!
+//! Without any features, only the plain data types in [`data`] and [`Serial`] are
+//! available, which only depend on `serde` and `postcard-schema`.
 
+pub mod data;
+#[cfg(feature = "postsock")]
 pub mod postsock;
+pub mod serial;
+
+pub use serial::{serial_from_hex, Serial};
 
 #[cfg(feature = "rest-api")]
 pub mod rest;
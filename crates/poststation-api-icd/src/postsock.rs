@@ -4,65 +4,322 @@
 //! crate instead, which gives you concrete interfaces. Consider this the "raw" definition of
 //! available endpoints and types.
 
-use chrono::{DateTime, Local};
+use std::collections::HashSet;
+
 use postcard_rpc::{
     endpoints, host_client::SchemaReport, standard_icd::WireError, topics, Key, TopicDirection,
 };
 use postcard_schema::Schema;
 use serde::{Deserialize, Serialize};
-use uuid::Uuid;
+
+pub use crate::data::{Anchor, DeviceData, Direction, Log, ParseAnchorError, Uuidv7};
 
 pub type DeviceDatas = Vec<DeviceData>;
 pub type OptSchemaReport = Option<SchemaReport>;
 pub type OptVecLog = Option<Vec<Log>>;
 pub type OptVecTopicMsg = Option<Vec<TopicMsg>>;
+pub type OptVecTopicRun = Option<Vec<TopicRun>>;
+pub type OptVecActiveTopic = Option<Vec<ActiveTopic>>;
+pub type OptU64 = Option<u64>;
+pub type OptTopicPaths = Option<TopicPaths>;
+pub type OptVecString = Option<Vec<String>>;
+pub type OptUsbInfo = Option<UsbInfo>;
+pub type SerialSchemas = Vec<SerialSchema>;
+pub type Serials = Vec<u64>;
+pub type SetNameResults = Vec<SetNameResult>;
+/// The PEM encoded CA certificate of the server, see [`GetCaCertEndpoint`]
+///
+/// This is meant to be fetched over an insecure connection, to bootstrap TLS
+/// connections to a remote server. The certificate can not be verified this way.
+pub type CaCertPem = Vec<u8>;
+/// The current time of the server, in milliseconds since the unix epoch
+pub type UnixMs = u64;
+
+/// The maximum number of serials that may be requested in a single
+/// [`GetSchemasMultiEndpoint`] request
+///
+/// Schema reports can be large, so this keeps the response within a reasonable
+/// frame size. The server only responds to the first `MAX_SCHEMAS_PER_REQUEST`
+/// serials of a request.
+pub const MAX_SCHEMAS_PER_REQUEST: usize = 16;
 
 endpoints! {
     list = RACK_ENDPOINTS;
-    | EndpointTy            | RequestTy             | ResponseTy        | Path                          |
-    | ----------            | ---------             | ----------        | ----                          |
-    | GetDevicesEndpoint    | ()                    | DeviceDatas       | "rack/devices/get"            |
-    | GetSchemasEndpoint    | u64                   | OptSchemaReport   | "rack/devices/schemas/get"    |
-    | GetLogsEndpoint       | LogRequest            | OptVecLog         | "rack/devices/logs/get"       |
-    | GetLogsRangeEndpoint  | LogRangeRequest       | OptVecLog         | "rack/devices/logs/range/get" |
-    | GetTopicsEndpoint     | TopicRequest          | OptVecTopicMsg    | "rack/devices/topics/get"     |
-    | ProxyEndpoint         | ProxyRequest          | ProxyResponse     | "rack/devices/proxy"          |
-    | PublishEndpoint       | PublishRequest        | PublishResponse   | "rack/devices/publish"        |
-    | StartStreamEndpoint   | TopicStreamRequest    | TopicStreamResult | "rack/devices/stream/start"   |
-    | StopStreamEndpoint    | Uuidv7                | ()                | "rack/devices/stream/stop"    |
+    | EndpointTy                     | RequestTy           | ResponseTy         | Path                                |
+    | ----------                     | ---------           | ----------         | ----                                |
+    | GetCapabilitiesEndpoint        | ()                  | Capabilities       | "rack/capabilities/get"             |
+    | GetCaCertEndpoint              | ()                  | CaCertPem          | "rack/ca-cert/get"                  |
+    | GetServerTimeEndpoint          | ()                  | UnixMs             | "rack/time/get"                     |
+    | GetDevicesEndpoint             | ()                  | DeviceDatas        | "rack/devices/get"                  |
+    | GetDeviceSerialsEndpoint       | ()                  | Serials            | "rack/devices/serials/get"          |
+    | GetSchemasEndpoint             | u64                 | OptSchemaReport    | "rack/devices/schemas/get"          |
+    | GetSchemasMultiEndpoint        | Serials             | SerialSchemas      | "rack/devices/schemas/multi/get"    |
+    | GetTopicPathsEndpoint          | u64                 | OptTopicPaths      | "rack/devices/paths/get"            |
+    | GetActiveTopicsEndpoint        | u64                 | OptVecActiveTopic  | "rack/devices/topics/active/get"    |
+    | GetLogsEndpoint                | LogRequest          | OptVecLog          | "rack/devices/logs/get"             |
+    | GetLogsRangeEndpoint           | LogRangeRequest     | OptVecLog          | "rack/devices/logs/range/get"       |
+    | GetTopicsEndpoint              | TopicRequest        | OptVecTopicMsg     | "rack/devices/topics/get"           |
+    | GetTopicRunsEndpoint           | TopicRequest        | OptVecTopicRun     | "rack/devices/topics/runs/get"      |
+    | ProxyEndpoint                  | ProxyRequest        | ProxyResponse      | "rack/devices/proxy"                |
+    | ProxyStreamEndpoint            | ProxyRequest        | ProxyStreamResult  | "rack/devices/proxy/stream/start"   |
+    | PublishEndpoint                | PublishRequest      | PublishResponse    | "rack/devices/publish"              |
+    | StartStreamEndpoint            | TopicStreamRequest  | TopicStreamResult  | "rack/devices/stream/start"         |
+    | StartPatternStreamEndpoint     | TopicPatternRequest | TopicPatternResult | "rack/devices/stream/pattern/start" |
+    | StopStreamEndpoint             | Uuidv7              | ()                 | "rack/devices/stream/stop"          |
+    | StartDeviceEventStreamEndpoint | DeviceEventFilter   | Uuidv7             | "rack/devices/events/stream/start"  |
+    | DeleteLogsEndpoint             | DeleteLogsRequest   | OptU64             | "rack/devices/logs/delete"          |
+    | DeleteTopicsEndpoint           | DeleteTopicsRequest | OptU64             | "rack/devices/topics/delete"        |
+    | GetDeviceTagsEndpoint          | u64                 | OptVecString       | "rack/devices/tags/get"             |
+    | SetDeviceTagsEndpoint          | SetTagsRequest      | bool               | "rack/devices/tags/set"             |
+    | SetDeviceNamesEndpoint         | SetNamesRequest     | SetNameResults     | "rack/devices/names/set"            |
+    | GetDevicesByTagEndpoint        | String              | DeviceDatas        | "rack/devices/by-tag/get"           |
+    | GetTopicStatsEndpoint          | TopicStatsRequest   | TopicStatsResult   | "rack/devices/topics/stats/get"     |
+    | GetDeviceUsbInfoEndpoint       | u64                 | OptUsbInfo         | "rack/devices/usb/get"              |
+    | GetStorageReportEndpoint       | ()                  | StorageReport      | "rack/storage/get"                  |
 }
 
 topics! {
     list = RACK_TOPICS_IN;
     direction = TopicDirection::ToServer;
-    | TopicTy               | MessageTy         | Path                      |
-    | -------               | ---------         | ----                      |
+    | TopicTy                | MessageTy            | Path                           |
+    | -------                | ---------            | ----                           |
 }
 
 topics! {
     list = RACK_TOPICS_OUT;
     direction = TopicDirection::ToClient;
-    | TopicTy               | MessageTy         | Path                      |
-    | -------               | ---------         | ----                      |
-    | SubscribeTopic        | TopicStreamMsg    | "rack/devices/stream"     |
+    | TopicTy                | MessageTy            | Path                           |
+    | -------                | ---------            | ----                           |
+    | SubscribeTopic         | TopicStreamMsg       | "rack/devices/stream"          |
+    | SchemaChangedTopic     | SchemaChange         | "rack/devices/schemas/changed" |
+    | ProxyChunkTopic        | ProxyChunk           | "rack/devices/proxy/stream"    |
+    | DeviceEventTopic       | DeviceEvent          | "rack/devices/events"          |
+    | DeviceEventStreamTopic | DeviceEventStreamMsg | "rack/devices/events/stream"   |
+}
+
+/// The protocol version and optional features supported by a server
+///
+/// Servers that predate [`GetCapabilitiesEndpoint`] are treated as having a
+/// `protocol_version` of zero, and no `features`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub struct Capabilities {
+    pub protocol_version: u32,
+    /// The names of supported optional features, see [`features`]
+    pub features: Vec<String>,
+}
+
+impl Capabilities {
+    /// Does the server support the given feature?
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+}
+
+/// Names of optional features reported in [`Capabilities`]
+pub mod features {
+    /// [`GetSchemasMultiEndpoint`](super::GetSchemasMultiEndpoint) is supported
+    pub const SCHEMAS_MULTI: &str = "schemas-multi";
+    /// [`ProxyStreamEndpoint`](super::ProxyStreamEndpoint) is supported
+    pub const PROXY_STREAM: &str = "proxy-stream";
+    /// [`DeviceEventTopic`](super::DeviceEventTopic) is published
+    pub const DEVICE_EVENTS: &str = "device-events";
+    /// [`StartPatternStreamEndpoint`](super::StartPatternStreamEndpoint) is supported
+    pub const STREAM_PATTERNS: &str = "stream-patterns";
+    /// [`GetStorageReportEndpoint`](super::GetStorageReportEndpoint) is supported
+    pub const STORAGE_REPORT: &str = "storage-report";
+    /// [`GetTopicRunsEndpoint`](super::GetTopicRunsEndpoint) is supported
+    pub const TOPIC_RUNS: &str = "topic-runs";
+    /// [`GetDeviceSerialsEndpoint`](super::GetDeviceSerialsEndpoint) is supported
+    pub const DEVICE_SERIALS: &str = "device-serials";
+    /// [`GetActiveTopicsEndpoint`](super::GetActiveTopicsEndpoint) is supported
+    pub const ACTIVE_TOPICS: &str = "active-topics";
+    /// [`SetDeviceNamesEndpoint`](super::SetDeviceNamesEndpoint) is supported
+    pub const DEVICE_NAMES: &str = "device-names";
+    /// [`GetCaCertEndpoint`](super::GetCaCertEndpoint) is supported
+    pub const CA_CERT: &str = "ca-cert";
+    /// [`GetServerTimeEndpoint`](super::GetServerTimeEndpoint) is supported
+    pub const SERVER_TIME: &str = "server-time";
+    /// [`StartDeviceEventStreamEndpoint`](super::StartDeviceEventStreamEndpoint) is supported
+    pub const DEVICE_EVENT_FILTER: &str = "device-event-filter";
+}
+
+/// The USB descriptor details poststation observed when the device enumerated
+///
+/// The string descriptors are `None` if the device did not provide them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub struct UsbInfo {
+    pub vid: u16,
+    pub pid: u16,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_string: Option<String>,
+}
+
+/// The disk space used by the poststation database
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub struct StorageReport {
+    /// The size of the whole database, in bytes
+    pub total_bytes: u64,
+    /// The history stored for each known device
+    pub devices: Vec<DeviceStorage>,
+}
+
+/// The history stored for one device, see [`StorageReport`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub struct DeviceStorage {
+    pub serial: u64,
+    pub log_count: u64,
+    /// The size of the stored logs, in bytes
+    pub log_bytes: u64,
+    pub topic_msg_count: u64,
+    /// The size of the stored topic messages, in bytes
+    pub topic_bytes: u64,
+}
+
+impl DeviceStorage {
+    /// The size of all history stored for the device, in bytes
+    pub fn total_bytes(&self) -> u64 {
+        self.log_bytes + self.topic_bytes
+    }
 }
 
+/// Replace the tags of a device
+///
+/// Responds with `false` if the device is not known.
 #[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
-pub struct DeviceData {
+#[non_exhaustive]
+pub struct SetTagsRequest {
+    pub serial: u64,
+    pub tags: Vec<String>,
+}
+
+impl SetTagsRequest {
+    pub fn new(serial: u64, tags: Vec<String>) -> Self {
+        Self { serial, tags }
+    }
+}
+
+/// Rename one or more devices
+///
+/// Each device is renamed independently. The response holds the outcome of every
+/// rename, in the order of `names`.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
+#[non_exhaustive]
+pub struct SetNamesRequest {
+    pub names: Vec<DeviceName>,
+}
+
+impl SetNamesRequest {
+    pub fn new(names: Vec<DeviceName>) -> Self {
+        Self { names }
+    }
+}
+
+/// A new name for a device, see [`SetNamesRequest`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub struct DeviceName {
     pub serial: u64,
     pub name: String,
-    pub is_connected: bool,
-    pub manufacturer: Option<String>,
-    pub product: Option<String>,
 }
 
+/// The outcome of renaming one device, as returned by [`SetDeviceNamesEndpoint`]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub struct SetNameResult {
+    pub serial: u64,
+    pub outcome: SetNameOutcome,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub enum SetNameOutcome {
+    Renamed,
+    NoDeviceKnown,
+    /// The server rejected the name, e.g. because it is empty
+    InvalidName(String),
+}
+
+/// The schema of one device, as returned by [`GetSchemasMultiEndpoint`]
+///
+/// `schema` is `None` if the device is not known.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub struct SerialSchema {
+    pub serial: u64,
+    pub schema: Option<SchemaReport>,
+}
+
+/// The paths offered by a device, without the schemas of their types
+#[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub struct TopicPaths {
+    pub topics_in: Vec<String>,
+    pub topics_out: Vec<String>,
+    pub endpoints: Vec<String>,
+}
+
+/// Sent to all clients when poststation observes that the schema of a device has
+/// changed, for example when it reconnects after being flashed with new firmware
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub struct SchemaChange {
+    pub serial: u64,
+    pub schema: SchemaReport,
+}
+
+/// A device connecting to or disconnecting from poststation
 #[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub enum DeviceEvent {
+    Connected(DeviceData),
+    Disconnected(u64),
+}
+
+impl DeviceEvent {
+    /// The serial of the device the event is about
+    pub fn serial(&self) -> u64 {
+        match self {
+            DeviceEvent::Connected(dev) => dev.serial,
+            DeviceEvent::Disconnected(serial) => *serial,
+        }
+    }
+}
+
+/// Selects the devices a [`StartDeviceEventStreamEndpoint`] stream sends events for
+///
+/// A device matches if its serial is one of `serials`, and it has at least one of
+/// the `tags`. A field left as `None` matches all devices.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, Schema)]
+#[non_exhaustive]
+pub struct DeviceEventFilter {
+    pub serials: Option<HashSet<u64>>,
+    pub tags: Option<Vec<String>>,
+}
+
+impl DeviceEventFilter {
+    pub fn new(serials: Option<HashSet<u64>>, tags: Option<Vec<String>>) -> Self {
+        Self { serials, tags }
+    }
+}
+
+/// A [`DeviceEvent`] sent for a stream started with [`StartDeviceEventStreamEndpoint`]
+///
+/// The stream is stopped with [`StopStreamEndpoint`].
+#[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub struct DeviceEventStreamMsg {
+    pub stream_id: Uuidv7,
+    pub event: DeviceEvent,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
+#[non_exhaustive]
 pub struct LogRequest {
     pub serial: u64,
     pub count: u32,
 }
 
+impl LogRequest {
+    pub fn new(serial: u64, count: u32) -> Self {
+        Self { serial, count }
+    }
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
+#[non_exhaustive]
 pub struct LogRangeRequest {
     pub serial: u64,
     pub anchor: Anchor,
@@ -70,74 +327,175 @@ pub struct LogRangeRequest {
     pub count: u32,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
-pub enum Direction {
-    Before,
-    After,
+impl LogRangeRequest {
+    pub fn new(serial: u64, anchor: Anchor, direction: Direction, count: u32) -> Self {
+        Self {
+            serial,
+            anchor,
+            direction,
+            count,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
-pub enum Anchor {
-    Uuid(Uuidv7),
-    UnixMsTs(u64),
+#[non_exhaustive]
+pub struct TopicRequest {
+    pub serial: u64,
+    pub path: String,
+    pub key: Key,
+    pub count: u32,
+}
+
+impl TopicRequest {
+    pub fn new(serial: u64, path: String, key: Key, count: u32) -> Self {
+        Self {
+            serial,
+            path,
+            key,
+            count,
+        }
+    }
 }
 
-// TODO: now that postcard-schema has a Schema impl for Uuid we might
-// not actually need this anymore
+/// Delete all logs of a device stored before the given anchor
+///
+/// Responds with the number of deleted logs, or `None` if the device is not known.
 #[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
-pub struct Uuidv7(pub [u8; 16]);
+#[non_exhaustive]
+pub struct DeleteLogsRequest {
+    pub serial: u64,
+    pub before: Anchor,
+}
 
-impl From<Uuid> for Uuidv7 {
-    fn from(value: Uuid) -> Self {
-        Self(value.into_bytes())
+impl DeleteLogsRequest {
+    pub fn new(serial: u64, before: Anchor) -> Self {
+        Self { serial, before }
     }
 }
 
-impl From<Uuidv7> for Uuid {
-    fn from(val: Uuidv7) -> Self {
-        Uuid::from_bytes(val.0)
+/// Delete all stored messages of a device's topic before the given anchor
+///
+/// Responds with the number of deleted messages, or `None` if the device is not known.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
+#[non_exhaustive]
+pub struct DeleteTopicsRequest {
+    pub serial: u64,
+    pub path: String,
+    pub key: Key,
+    pub before: Anchor,
+}
+
+impl DeleteTopicsRequest {
+    pub fn new(serial: u64, path: String, key: Key, before: Anchor) -> Self {
+        Self {
+            serial,
+            path,
+            key,
+            before,
+        }
     }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
-pub struct Log {
+pub struct TopicMsg {
     pub uuidv7: Uuidv7,
-    pub msg: String,
+    pub msg: Vec<u8>,
 }
 
-impl Uuidv7 {
-    pub fn id_to_time(&self) -> DateTime<Local> {
-        let uuid = Uuid::from_bytes(self.0);
-        let ts = uuid.get_timestamp().unwrap();
-        let (a, b) = ts.to_unix();
-        DateTime::from_timestamp(a as i64, b).unwrap().into()
-    }
+/// A run of consecutive messages of a topic with identical payloads
+///
+/// Returned by [`GetTopicRunsEndpoint`], which covers the same `count` messages
+/// as [`GetTopicsEndpoint`], but sends each run of repeated payloads only once.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub struct TopicRun {
+    pub msg: Vec<u8>,
+    /// The number of messages in the run, at least one
+    pub count: u32,
+    pub first_uuid: Uuidv7,
+    pub last_uuid: Uuidv7,
+}
+
+/// The stored history of one "topic-out" path of a device
+///
+/// Returned by [`GetActiveTopicsEndpoint`]. Unlike the paths in the device's schema,
+/// this reflects the messages poststation has actually received, including those of
+/// topics the device no longer declares.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub struct ActiveTopic {
+    pub path: String,
+    /// The ID of the most recent stored message, `None` if no messages are stored
+    pub last_msg: Option<Uuidv7>,
+    /// The number of stored messages
+    pub count: u64,
 }
 
+/// Request aggregate statistics over the stored messages of a numeric topic
 #[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
-pub struct TopicRequest {
+#[non_exhaustive]
+pub struct TopicStatsRequest {
     pub serial: u64,
     pub path: String,
     pub key: Key,
-    pub count: u32,
+    pub range: StatsRange,
 }
 
+impl TopicStatsRequest {
+    pub fn new(serial: u64, path: String, key: Key, range: StatsRange) -> Self {
+        Self {
+            serial,
+            path,
+            key,
+            range,
+        }
+    }
+}
+
+/// The messages used for a [`TopicStatsRequest`]
 #[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
-pub struct TopicMsg {
-    pub uuidv7: Uuidv7,
-    pub msg: Vec<u8>,
+pub enum StatsRange {
+    /// The most recent N messages
+    Last(u32),
+    /// All messages after the given anchor
+    Since(Anchor),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub struct TopicStats {
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub enum TopicStatsResult {
+    Stats(TopicStats),
+    NoDeviceKnown,
+    NoSuchTopic,
+    /// The topic's message type is not a single numeric value
+    NotNumeric,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
+#[non_exhaustive]
 pub struct TopicStreamRequest {
     pub serial: u64,
     pub path: String,
     pub key: Key,
 }
 
+impl TopicStreamRequest {
+    pub fn new(serial: u64, path: String, key: Key) -> Self {
+        Self { serial, path, key }
+    }
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
 pub struct TopicStreamMsg {
     pub stream_id: Uuidv7,
+    /// The ID of this message, as stored in the topic history
+    pub uuidv7: Uuidv7,
     pub msg: Vec<u8>,
 }
 
@@ -149,7 +507,72 @@ pub enum TopicStreamResult {
     NoSuchTopic,
 }
 
+/// Start streaming all topic-out paths of a device that match a pattern
+///
+/// See [`topic_pattern_matches`] for the pattern syntax. The pattern is matched
+/// against the paths of the device once, when the streams are started.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
+#[non_exhaustive]
+pub struct TopicPatternRequest {
+    pub serial: u64,
+    pub pattern: String,
+}
+
+impl TopicPatternRequest {
+    pub fn new(serial: u64, pattern: String) -> Self {
+        Self { serial, pattern }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub enum TopicPatternResult {
+    /// One stream was started for each matching path, possibly none. Messages are
+    /// sent on the [`SubscribeTopic`] like for [`StartStreamEndpoint`].
+    Started(Vec<PatternStream>),
+    NoDeviceKnown,
+    DeviceDisconnected,
+}
+
+/// A stream started for one path matching a [`TopicPatternRequest`]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub struct PatternStream {
+    pub stream_id: Uuidv7,
+    pub path: String,
+    pub key: Key,
+}
+
+/// Does the topic `path` match the `pattern`?
+///
+/// Patterns and paths are split into segments at each `/`. A `*` segment matches
+/// exactly one segment of the path, and a `**` segment matches any number of
+/// segments, including none. All other segments must be equal. For example:
+///
+/// * `sensors/*/temperature` matches `sensors/left/temperature`, but not
+///   `sensors/temperature` or `sensors/left/inner/temperature`
+/// * `sensors/**` matches `sensors`, `sensors/left` and `sensors/left/temperature`
+/// * `*` matches any path with a single segment
+///
+/// `*` is only special as a whole segment, `sensors/temp*` only matches the path
+/// `sensors/temp*`.
+pub fn topic_pattern_matches(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.split('/').collect::<Vec<_>>();
+    let path = path.split('/').collect::<Vec<_>>();
+    segments_match(&pattern, &path)
+}
+
+fn segments_match(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| segments_match(rest, &path[skip..])),
+        Some((seg, rest)) => match path.split_first() {
+            Some((p, path_rest)) => (*seg == "*" || seg == p) && segments_match(rest, path_rest),
+            None => false,
+        },
+    }
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+#[non_exhaustive]
 pub struct ProxyRequest {
     pub serial: u64,
     pub path: String,
@@ -157,6 +580,36 @@ pub struct ProxyRequest {
     pub resp_key: Key,
     pub seq_no: u32,
     pub req_body: Vec<u8>,
+    /// If set, the server cancels the request to the device if no response has been
+    /// received within this many milliseconds, responding with [`ProxyResponse::Timeout`]
+    pub deadline_ms: Option<u32>,
+}
+
+impl ProxyRequest {
+    pub fn new(
+        serial: u64,
+        path: String,
+        req_key: Key,
+        resp_key: Key,
+        seq_no: u32,
+        req_body: Vec<u8>,
+    ) -> Self {
+        Self {
+            serial,
+            path,
+            req_key,
+            resp_key,
+            seq_no,
+            req_body,
+            deadline_ms: None,
+        }
+    }
+
+    /// Cancel the request if the device has not responded within `deadline_ms` milliseconds
+    pub fn deadline_ms(mut self, deadline_ms: u32) -> Self {
+        self.deadline_ms = Some(deadline_ms);
+        self
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
@@ -165,6 +618,9 @@ pub enum ProxyResponse {
         resp_key: Key,
         seq_no: u32,
         body: Vec<u8>,
+        /// The time between poststation sending the request to the device and
+        /// receiving its response, in microseconds, if measured
+        device_latency_us: Option<u32>,
     },
     WireErr {
         resp_key: Key,
@@ -172,9 +628,45 @@ pub enum ProxyResponse {
         body: WireError,
     },
     OtherErr(String),
+    /// The device did not respond within the `deadline_ms` of the request
+    Timeout,
+}
+
+/// The result of starting a [`ProxyStreamEndpoint`] request
+///
+/// Once started, the response of the device is sent as one or more [`ProxyChunk`]s
+/// on the [`ProxyChunkTopic`], which allows responses larger than a single frame.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Hash, Schema)]
+pub enum ProxyStreamResult {
+    Started(Uuidv7),
+    NoDeviceKnown,
+    DeviceDisconnected,
+    NoSuchEndpoint,
+}
+
+/// One part of the response to a [`ProxyStreamEndpoint`] request
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub struct ProxyChunk {
+    pub stream_id: Uuidv7,
+    /// The position of this chunk in the response, starting at zero
+    pub index: u32,
+    pub kind: ProxyChunkKind,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub enum ProxyChunkKind {
+    /// The next part of the postcard encoded response body
+    Data(Vec<u8>),
+    /// The response is complete, no more chunks will be sent
+    Done,
+    WireErr(WireError),
+    OtherErr(String),
+    /// The device did not respond within the `deadline_ms` of the request
+    Timeout,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+#[non_exhaustive]
 pub struct PublishRequest {
     pub serial: u64,
     pub path: String,
@@ -183,8 +675,86 @@ pub struct PublishRequest {
     pub topic_body: Vec<u8>,
 }
 
+impl PublishRequest {
+    pub fn new(
+        serial: u64,
+        path: String,
+        topic_key: Key,
+        seq_no: u32,
+        topic_body: Vec<u8>,
+    ) -> Self {
+        Self {
+            serial,
+            path,
+            topic_key,
+            seq_no,
+            topic_body,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
 pub enum PublishResponse {
     Sent,
     OtherErr(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::topic_pattern_matches;
+
+    #[test]
+    fn pattern_matching() {
+        let cases = [
+            // Literal segments
+            ("sensors/temperature", "sensors/temperature", true),
+            ("sensors/temperature", "sensors/humidity", false),
+            ("sensors/temperature", "sensors", false),
+            ("sensors", "sensors/temperature", false),
+            // `*` matches exactly one segment
+            ("sensors/*/temperature", "sensors/left/temperature", true),
+            ("sensors/*/temperature", "sensors/temperature", false),
+            (
+                "sensors/*/temperature",
+                "sensors/left/inner/temperature",
+                false,
+            ),
+            ("*", "sensors", true),
+            ("*", "sensors/left", false),
+            ("*/*", "sensors/left", true),
+            // `*` also matches an empty segment
+            ("*", "", true),
+            ("sensors/*", "sensors/", true),
+            ("**/*", "", true),
+            // `**` matches any number of segments, including none
+            ("sensors/**", "sensors", true),
+            ("sensors/**", "sensors/left", true),
+            ("sensors/**", "sensors/left/temperature", true),
+            ("sensors/**", "other/left", false),
+            ("**", "", true),
+            ("**", "a/b/c", true),
+            ("**/temperature", "temperature", true),
+            ("**/temperature", "sensors/left/temperature", true),
+            ("**/temperature", "sensors/left/humidity", false),
+            ("sensors/**/temperature", "sensors/temperature", true),
+            ("sensors/**/temperature", "sensors/a/b/temperature", true),
+            ("**/**", "a", true),
+            ("**/*", "a/b", true),
+            // Wildcards are only special as whole segments
+            ("sensors/temp*", "sensors/temp*", true),
+            ("sensors/temp*", "sensors/temperature", false),
+            ("sensors/**x", "sensors/a/x", false),
+            // Empty patterns and paths
+            ("", "", true),
+            ("", "sensors", false),
+            ("sensors", "", false),
+        ];
+        for (pattern, path, expected) in cases {
+            assert_eq!(
+                topic_pattern_matches(pattern, path),
+                expected,
+                "pattern {pattern:?}, path {path:?}"
+            );
+        }
+    }
+}
@@ -5,6 +5,12 @@
 //!
 //! At some point in the future we will publish an OpenAPI spec for all available requests.
 //! For now, here is a listing of all endpoints and an example CURL request for each of them.
+//! The same listing is available in machine-readable form from `/api/schema`, see
+//! [`ApiDescriptor`].
+//!
+//! The routes returning message bodies ("Get Topics", "Proxy an endpoint request", and
+//! the topic stream) take an `int_encoding` parameter. With `int_encoding=String`,
+//! integers too large for a JavaScript number are sent as strings, see [`IntEncoding`].
 //!
 //! ## "Get Devices"
 //!
@@ -19,18 +25,28 @@
 //!     "name": "XRAY-013",
 //!     "is_connected": false,
 //!     "manufacturer": "OneVariable",
-//!     "product": "poststation-pico"
+//!     "product": "poststation-pico",
+//!     "last_seen": 1732485767497
 //!   },
 //!   {
 //!     "serial": "6E43B25479AC185C",
 //!     "name": "YACHTY-312",
 //!     "is_connected": true,
 //!     "manufacturer": "Simulator",
-//!     "product": "Product"
+//!     "product": "Product",
+//!     "last_seen": 1732486112045
 //!   },
 //! ]
 //! ```
 //!
+//! The list can be filtered and sorted with the optional query parameters of
+//! [`DeviceQuery`]:
+//!
+//! ```sh
+//! curl 'http://localhost:4444/api/devices?connected=true&name_contains=yachty&sort=name' \
+//!     -q -H "Accept: application/json"
+//! ```
+//!
 //! ## "Get Schemas"
 //!
 //! ```sh
@@ -65,6 +81,9 @@
 //! or the UUIDv7 of a log item as the "anchor" of the request, and then request N logs "Before" or "After" the
 //! anchor (excluding the anchor itself).
 //!
+//! Instead of `uuid` or `unix_ms_ts`, the anchor may also be given as `anchor`, which
+//! takes either of them, or `earliest` or `latest`.
+//!
 //! ### Using a UUIDv7 of a log entry as the anchor
 //!
 //! ```sh
@@ -195,6 +214,41 @@
 //! {}
 //! ```
 //!
+//! ## "Get API Schema"
+//!
+//! Returns an [`ApiDescriptor`], listing all routes of the REST API along with the
+//! JSON Schemas of their query parameters, request bodies, and responses.
+//!
+//! ```sh
+//! curl http://localhost:4444/api/schema -q -H "Accept: application/json"
+//! ```
+//!
+//! ```json
+//! {
+//!   "routes": [
+//!     {
+//!       "method": "Get",
+//!       "path": "/api/devices",
+//!       "query": {
+//!         "$schema": "http://json-schema.org/draft-07/schema#",
+//!         "title": "DeviceQuery",
+//!         "type": "object",
+//!         ...
+//!       },
+//!       "request": null,
+//!       "response": {
+//!         "$schema": "http://json-schema.org/draft-07/schema#",
+//!         "title": "Array_of_DeviceData",
+//!         "type": "array",
+//!         ...
+//!       },
+//!       "error": null
+//!     },
+//!     ...
+//!   ]
+//! }
+//! ```
+//!
 //! # "Subscribe to a stream of topic_out messages"
 //!
 //! This is a **WebSocket** endpoint, which gives you a live feed of a specific topic from a
@@ -204,38 +258,122 @@
 //! websocat "ws://localhost:4444/api/devices/CA9FF06E058FF9A6/listen?path=simulator/temperature&key=583A352440D70716" | jq
 //! ```
 //!
+//! Each message is a [`TopicStreamMsg`], tagged with the path it was published on, so
+//! messages of several streams can be told apart without tracking their stream IDs.
+//!
 //! ```json
 //! {
+//!   "stream_id": "01938e07-a1c4-7d60-a3b1-6e2fb7c9a7d1",
+//!   "path": "simulator/temperature",
+//!   "uuidv7": "01938e07-a5e2-7be1-8a57-2d1ac08c1b9e",
 //!   "msg": {
 //!     "temp": 2726.9024233159403
-//!   },
-//!   "seq_no": 868
+//!   }
 //! }
 //! {
+//!   "stream_id": "01938e07-a1c4-7d60-a3b1-6e2fb7c9a7d1",
+//!   "path": "simulator/temperature",
+//!   "uuidv7": "01938e07-a9ca-7f12-b4e0-93c5d0a5f267",
 //!   "msg": {
 //!     "temp": 2730.0440159695304
-//!   },
-//!   "seq_no": 869
-//! }
-//! {
-//!   "msg": {
-//!     "temp": 2733.18560862312
-//!   },
-//!   "seq_no": 870
+//!   }
 //! }
 //! ```
 
+use core::cmp::Ordering;
+
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::{
+    data::{Anchor, ParseAnchorError},
+    Serial,
+};
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Hash, JsonSchema)]
 pub struct DeviceData {
-    pub serial: String,
+    pub serial: Serial,
     pub name: String,
     pub is_connected: bool,
     pub manufacturer: Option<String>,
     pub product: Option<String>,
+    /// When poststation last had contact with the device, in milliseconds since
+    /// the unix epoch, or `None` if unknown
+    pub last_seen: Option<u64>,
+}
+
+/// The optional query parameters of the "Get Devices" route
+///
+/// Unset filters match all devices. Without a `sort`, devices are listed in the
+/// order reported by poststation.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Hash, JsonSchema)]
+pub struct DeviceQuery {
+    /// Only list devices that are, or are not, connected
+    #[serde(default)]
+    pub connected: Option<bool>,
+    /// Only list devices whose name contains this text, ignoring case
+    #[serde(default)]
+    pub name_contains: Option<String>,
+    #[serde(default)]
+    pub sort: Option<DeviceSort>,
+}
+
+impl DeviceQuery {
+    /// Does the device pass the filters of the query?
+    pub fn matches(&self, dev: &DeviceData) -> bool {
+        let connected = self.connected.is_none_or(|c| c == dev.is_connected);
+        let name = self
+            .name_contains
+            .as_ref()
+            .is_none_or(|n| dev.name.to_lowercase().contains(&n.to_lowercase()));
+        connected && name
+    }
+
+    /// Remove the devices that don't pass the filters, and sort the rest
+    ///
+    /// Ties are broken by serial number.
+    pub fn apply(&self, devices: &mut Vec<DeviceData>) {
+        devices.retain(|d| self.matches(d));
+        if let Some(sort) = self.sort {
+            sort.sort(devices, |d| (&d.name, d.serial.0, d.is_connected));
+        }
+    }
+}
+
+/// The order of the devices listed by the "Get Devices" route
+///
+/// In query parameters these are lowercase, e.g. `sort=name`.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Hash, JsonSchema, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceSort {
+    /// Sort by device name
+    Name,
+    /// Sort by serial number
+    Serial,
+    /// Sort connected devices first, then by device name
+    Connected,
+}
+
+impl DeviceSort {
+    /// Sort a list of devices, given the name, serial number and connection state of each
+    ///
+    /// Ties are broken by serial number, so the resulting order is stable across calls
+    /// regardless of the order devices were reported in.
+    pub fn sort<T>(self, devices: &mut [T], key: impl Fn(&T) -> (&String, u64, bool)) {
+        devices.sort_by(|a, b| {
+            let (a_name, a_serial, a_connected) = key(a);
+            let (b_name, b_serial, b_connected) = key(b);
+            match self {
+                DeviceSort::Name => a_name.cmp(b_name),
+                DeviceSort::Serial => Ordering::Equal,
+                DeviceSort::Connected => b_connected
+                    .cmp(&a_connected)
+                    .then_with(|| a_name.cmp(b_name)),
+            }
+            .then_with(|| a_serial.cmp(&b_serial))
+        });
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Hash, JsonSchema)]
@@ -253,10 +391,27 @@ pub struct Log {
 pub struct LogRangeRequest {
     pub uuid: Option<Uuid>,
     pub unix_ms_ts: Option<u64>,
+    /// The anchor in its string form, an alternative to `uuid` and `unix_ms_ts`
+    ///
+    /// See [`Anchor`] for the accepted values.
+    #[serde(default)]
+    pub anchor: Option<String>,
     pub direction: Direction,
     pub count: u32,
 }
 
+impl LogRangeRequest {
+    /// The anchor of the request, from whichever of `anchor`, `uuid` or `unix_ms_ts` is set
+    pub fn anchor(&self) -> Result<Anchor, ParseAnchorError> {
+        match (&self.anchor, self.uuid, self.unix_ms_ts) {
+            (Some(anchor), _, _) => anchor.parse(),
+            (None, Some(uuid), _) => Ok(Anchor::Uuid(uuid.into())),
+            (None, None, Some(ts)) => Ok(Anchor::UnixMsTs(ts)),
+            (None, None, None) => Err(ParseAnchorError::missing()),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Hash, JsonSchema)]
 pub enum Direction {
     Before,
@@ -268,6 +423,8 @@ pub struct TopicRequest {
     pub path: String,
     pub key: foreign::Key,
     pub count: u32,
+    #[serde(default)]
+    pub int_encoding: IntEncoding,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Hash, JsonSchema)]
@@ -280,11 +437,18 @@ pub struct TopicMsg {
 pub struct TopicStreamRequest {
     pub path: String,
     pub key: foreign::Key,
+    #[serde(default)]
+    pub int_encoding: IntEncoding,
 }
 
+/// A single message of a topic stream, sent over the WebSocket of the "listen" route
 #[derive(Debug, PartialEq, Serialize, Deserialize, Hash, JsonSchema)]
 pub struct TopicStreamMsg {
     pub stream_id: Uuid,
+    /// The "topic-out" path the message was published on
+    pub path: String,
+    /// The ID of the message, ordered by when poststation received it
+    pub uuidv7: Uuid,
     pub msg: serde_json::Value,
 }
 
@@ -303,6 +467,13 @@ pub struct ProxyRequest {
     pub resp_key: foreign::Key,
     pub seq_no: u32,
     pub body: serde_json::Value,
+    /// If set, the server cancels the request to the device if no response has been
+    /// received within this many milliseconds, responding with [`ProxyResponseError::Timeout`]
+    #[serde(default)]
+    pub deadline_ms: Option<u32>,
+    /// How integers in the body of the response are encoded
+    #[serde(default)]
+    pub int_encoding: IntEncoding,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
@@ -310,6 +481,10 @@ pub struct ProxyResponseOk {
     pub resp_key: foreign::Key,
     pub seq_no: u32,
     pub body: serde_json::Value,
+    /// The time between poststation sending the request to the device and
+    /// receiving its response, in microseconds, if measured
+    #[serde(default)]
+    pub device_latency_us: Option<u32>,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
@@ -320,6 +495,8 @@ pub enum ProxyResponseError {
         body: foreign::WireError,
     },
     OtherErr(String),
+    /// The device did not respond within the `deadline_ms` of the request
+    Timeout,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
@@ -330,6 +507,152 @@ pub struct PublishRequest {
     pub body: serde_json::Value,
 }
 
+/// How integers in message bodies are encoded as JSON
+///
+/// JavaScript numbers are doubles, so integers beyond [`MAX_SAFE_INTEGER`] (for
+/// example most `u64` values) silently lose precision when parsed as numbers.
+/// Like serials, these can be requested as strings instead, see [`encode_ints`].
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Hash, JsonSchema, Clone, Copy)]
+pub enum IntEncoding {
+    /// All integers are JSON numbers
+    #[default]
+    Number,
+    /// Integers beyond [`MAX_SAFE_INTEGER`] are decimal strings, others are numbers
+    String,
+}
+
+/// The largest integer that a JavaScript number represents exactly, `2^53 - 1`
+pub const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// Re-encode the integers of a message body in place, according to `encoding`
+pub fn encode_ints(value: &mut serde_json::Value, encoding: IntEncoding) {
+    if encoding == IntEncoding::Number {
+        return;
+    }
+    match value {
+        serde_json::Value::Number(n) => {
+            let wide = match (n.as_u64(), n.as_i64()) {
+                (Some(u), _) => u > MAX_SAFE_INTEGER,
+                (None, Some(i)) => i.unsigned_abs() > MAX_SAFE_INTEGER,
+                // Floats are already approximate
+                (None, None) => false,
+            };
+            if wide {
+                *value = serde_json::Value::String(n.to_string());
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                encode_ints(item, encoding);
+            }
+        }
+        serde_json::Value::Object(fields) => {
+            for field in fields.values_mut() {
+                encode_ints(field, encoding);
+            }
+        }
+        serde_json::Value::Null | serde_json::Value::Bool(_) | serde_json::Value::String(_) => {}
+    }
+}
+
+/// A machine-readable listing of the REST API, as returned by `GET /api/schema`
+#[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ApiDescriptor {
+    pub routes: Vec<RouteDescriptor>,
+}
+
+/// A single route of the REST API
+///
+/// All schemas are JSON Schema documents, as generated by `schemars`.
+#[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RouteDescriptor {
+    pub method: RouteMethod,
+    /// The path of the route, with `{serial}` standing in for a device serial
+    pub path: String,
+    /// The schema of the query parameters, if any
+    pub query: Option<serde_json::Value>,
+    /// The schema of the request body, if any
+    pub request: Option<serde_json::Value>,
+    /// The schema of a successful response, or of each message for WebSocket routes
+    pub response: Option<serde_json::Value>,
+    /// The schema of an error response body, if the route has one
+    pub error: Option<serde_json::Value>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema, Clone, Copy)]
+pub enum RouteMethod {
+    Get,
+    Post,
+    /// A WebSocket upgrade, started with a `GET` request
+    WebSocket,
+}
+
+impl ApiDescriptor {
+    /// The listing of all routes described by this module
+    pub fn current() -> Self {
+        let route = |method, path: &str| RouteDescriptor {
+            method,
+            path: path.to_string(),
+            query: None,
+            request: None,
+            response: None,
+            error: None,
+        };
+
+        Self {
+            routes: vec![
+                RouteDescriptor {
+                    query: Some(schema_of::<DeviceQuery>()),
+                    response: Some(schema_of::<Vec<DeviceData>>()),
+                    ..route(RouteMethod::Get, "/api/devices")
+                },
+                RouteDescriptor {
+                    response: Some(schema_of::<foreign::SchemaReport>()),
+                    ..route(RouteMethod::Get, "/api/devices/{serial}/schemas")
+                },
+                RouteDescriptor {
+                    query: Some(schema_of::<LogRequest>()),
+                    response: Some(schema_of::<Vec<Log>>()),
+                    ..route(RouteMethod::Get, "/api/devices/{serial}/logs")
+                },
+                RouteDescriptor {
+                    query: Some(schema_of::<LogRangeRequest>()),
+                    response: Some(schema_of::<Vec<Log>>()),
+                    ..route(RouteMethod::Get, "/api/devices/{serial}/logs/range")
+                },
+                RouteDescriptor {
+                    query: Some(schema_of::<TopicRequest>()),
+                    response: Some(schema_of::<Vec<TopicMsg>>()),
+                    ..route(RouteMethod::Get, "/api/devices/{serial}/topics")
+                },
+                RouteDescriptor {
+                    request: Some(schema_of::<ProxyRequest>()),
+                    response: Some(schema_of::<ProxyResponseOk>()),
+                    error: Some(schema_of::<ProxyResponseError>()),
+                    ..route(RouteMethod::Post, "/api/devices/{serial}/proxy")
+                },
+                RouteDescriptor {
+                    request: Some(schema_of::<PublishRequest>()),
+                    ..route(RouteMethod::Post, "/api/devices/{serial}/publish")
+                },
+                RouteDescriptor {
+                    query: Some(schema_of::<TopicStreamRequest>()),
+                    response: Some(schema_of::<TopicStreamMsg>()),
+                    ..route(RouteMethod::WebSocket, "/api/devices/{serial}/listen")
+                },
+                RouteDescriptor {
+                    response: Some(schema_of::<ApiDescriptor>()),
+                    ..route(RouteMethod::Get, "/api/schema")
+                },
+            ],
+        }
+    }
+}
+
+fn schema_of<T: JsonSchema>() -> serde_json::Value {
+    serde_json::to_value(schemars::schema_for!(T)).unwrap_or_default()
+}
+
 /// These are types from other crates I'm pasting here just so I can impl JsonSchema on it
 pub mod foreign {
     use std::collections::HashSet;
@@ -359,6 +682,42 @@ pub mod foreign {
     )]
     pub struct Key(String);
 
+    impl From<postcard_rpc::standard_icd::WireError> for WireError {
+        fn from(value: postcard_rpc::standard_icd::WireError) -> Self {
+            use postcard_rpc::standard_icd::WireError as W;
+            match value {
+                W::FrameTooLong(e) => Self::FrameTooLong(FrameTooLong {
+                    len: e.len,
+                    max: e.max,
+                }),
+                W::FrameTooShort(e) => Self::FrameTooShort(FrameTooShort { len: e.len }),
+                W::DeserFailed => Self::DeserFailed,
+                W::SerFailed => Self::SerFailed,
+                W::UnknownKey => Self::UnknownKey,
+                W::FailedToSpawn => Self::FailedToSpawn,
+                W::KeyTooSmall => Self::KeyTooSmall,
+            }
+        }
+    }
+
+    impl From<WireError> for postcard_rpc::standard_icd::WireError {
+        fn from(value: WireError) -> Self {
+            use postcard_rpc::standard_icd::{FrameTooLong as Long, FrameTooShort as Short};
+            match value {
+                WireError::FrameTooLong(e) => Self::FrameTooLong(Long {
+                    len: e.len,
+                    max: e.max,
+                }),
+                WireError::FrameTooShort(e) => Self::FrameTooShort(Short { len: e.len }),
+                WireError::DeserFailed => Self::DeserFailed,
+                WireError::SerFailed => Self::SerFailed,
+                WireError::UnknownKey => Self::UnknownKey,
+                WireError::FailedToSpawn => Self::FailedToSpawn,
+                WireError::KeyTooSmall => Self::KeyTooSmall,
+            }
+        }
+    }
+
     /// The given frame was too long
     #[derive(Serialize, Deserialize, Debug, PartialEq, JsonSchema)]
     pub struct FrameTooLong {
@@ -0,0 +1,113 @@
+//! A device serial number, usable by both API flavors
+
+use core::{fmt, num::ParseIntError, str::FromStr};
+
+use postcard_schema::{schema::NamedType, Schema};
+use serde::{de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
+
+/// The serial number of a device
+///
+/// When serialized with a "human readable" format (like JSON), this is encoded
+/// as a 16 character uppercase hex string, e.g. `"E66350865F164926"`, to avoid
+/// the loss of precision of large integers in JS and many JSON libraries.
+///
+/// When serialized with a binary format (like postcard), this is encoded exactly
+/// like a `u64`, and has the same postcard schema as a `u64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Serial(pub u64);
+
+impl From<u64> for Serial {
+    fn from(value: u64) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Serial> for u64 {
+    fn from(value: Serial) -> Self {
+        value.0
+    }
+}
+
+impl fmt::Display for Serial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016X}", self.0)
+    }
+}
+
+impl FromStr for Serial {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serial_from_hex(s).map(Self)
+    }
+}
+
+/// Parse a serial number from hex, e.g. `E66350865F164926`
+///
+/// An optional `0x` or `0X` prefix and `_` separators are accepted, so that serials
+/// copied from Rust literals like `0xE663_5086_5F16_4926` can be used as-is.
+pub fn serial_from_hex(s: &str) -> Result<u64, ParseIntError> {
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    if !s.contains('_') {
+        return u64::from_str_radix(s, 16);
+    }
+    let digits = s.chars().filter(|c| *c != '_').collect::<String>();
+    u64::from_str_radix(&digits, 16)
+}
+
+impl Schema for Serial {
+    const SCHEMA: &'static NamedType = u64::SCHEMA;
+}
+
+impl Serialize for Serial {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_u64(self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Serial {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SerialVisitor;
+
+        impl Visitor<'_> for SerialVisitor {
+            type Value = Serial;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a hex string or u64 serial number")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(Serial(v))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse()
+                    .map_err(|_| E::invalid_value(serde::de::Unexpected::Str(v), &self))
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(SerialVisitor)
+        } else {
+            deserializer.deserialize_u64(SerialVisitor)
+        }
+    }
+}
+
+#[cfg(feature = "schemars-v0_8")]
+impl schemars::JsonSchema for Serial {
+    fn schema_name() -> String {
+        "Serial".into()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        String::json_schema(gen)
+    }
+}
@@ -0,0 +1,47 @@
+//! Small helpers shared by poststation firmware
+//!
+//! These have no dependencies and are `no_std`, so that they can be used by the
+//! firmware templates and examples of any target.
+
+#![cfg_attr(not(test), no_std)]
+
+/// Format a serial number as 16 uppercase hex digits, e.g. `E66350865F164926`
+///
+/// This is the form poststation shows serials in, and is suitable for the USB
+/// serial number string of a device.
+pub fn serial_hex(serial: u64) -> [u8; 16] {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    let mut buf = [0u8; 16];
+    for (i, c) in buf.iter_mut().enumerate() {
+        let nibble = (serial >> (60 - 4 * i)) & 0xF;
+        *c = HEX[nibble as usize];
+    }
+    buf
+}
+
+/// Format a serial number like [`serial_hex`] into `buf`, and return it as a `str`
+///
+/// `buf` is usually a `static`, as USB descriptors need a `&'static str`.
+pub fn format_serial_hex(serial: u64, buf: &mut [u8; 16]) -> &str {
+    *buf = serial_hex(serial);
+    // The digits are always ASCII, the fallback only avoids a panic path
+    core::str::from_utf8(buf).unwrap_or("0000000000000000")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_sixteen_uppercase_digits() {
+        let mut buf = [0; 16];
+        assert_eq!(format_serial_hex(0, &mut buf), "0000000000000000");
+        assert_eq!(format_serial_hex(u64::MAX, &mut buf), "FFFFFFFFFFFFFFFF");
+        assert_eq!(
+            format_serial_hex(0xE663_5086_5F16_4926, &mut buf),
+            "E66350865F164926"
+        );
+        assert_eq!(format_serial_hex(0x0A, &mut buf), "000000000000000A");
+        assert_eq!(&serial_hex(0x0123_4567_89AB_CDEF), b"0123456789ABCDEF");
+    }
+}
@@ -0,0 +1,273 @@
+//! A reference implementation of the poststation REST API
+//!
+//! This serves the routes described in [`poststation_api_icd::rest`], using the
+//! JSON types of that module, and handles each request by making the matching
+//! request to a poststation server with a [`PoststationClient`].
+//!
+//! Usage: `cargo run -- [LISTEN_ADDR]`, listening on `127.0.0.1:4445` by default.
+
+use std::time::Duration;
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        Path, Query, State, WebSocketUpgrade,
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use poststation_api_icd::{
+    postsock::Direction,
+    rest::{
+        encode_ints, foreign::SchemaReport, ApiDescriptor, DeviceData, DeviceQuery,
+        Direction as RestDirection, IntEncoding, Log, LogRangeRequest, LogRequest, ProxyRequest,
+        ProxyResponseError, ProxyResponseOk, PublishRequest, TopicMsg, TopicRequest,
+        TopicStreamMsg, TopicStreamRequest,
+    },
+    Serial,
+};
+use poststation_sdk::{connect_localhost, ClientError, JsonStreamListener, PoststationClient};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:4445".to_string());
+    let client = connect_localhost().await?;
+
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    println!("Serving the REST API on http://{addr}");
+    axum::serve(listener, router(client)).await?;
+    Ok(())
+}
+
+/// All routes listed by [`ApiDescriptor::current`]
+fn router(client: PoststationClient) -> Router {
+    Router::new()
+        .route("/api/devices", get(get_devices))
+        .route("/api/devices/{serial}/schemas", get(get_schemas))
+        .route("/api/devices/{serial}/logs", get(get_logs))
+        .route("/api/devices/{serial}/logs/range", get(get_logs_range))
+        .route("/api/devices/{serial}/topics", get(get_topics))
+        .route("/api/devices/{serial}/proxy", post(proxy))
+        .route("/api/devices/{serial}/publish", post(publish))
+        .route("/api/devices/{serial}/listen", get(listen))
+        .route("/api/schema", get(get_api_schema))
+        .with_state(client)
+}
+
+/// An error response, with a plain text body
+struct ApiError(StatusCode, String);
+
+impl From<ClientError> for ApiError {
+    fn from(value: ClientError) -> Self {
+        let status = match &value {
+            ClientError::ConnectionClosed | ClientError::Remote(_) | ClientError::RemoteWire(_) => {
+                StatusCode::BAD_GATEWAY
+            }
+            ClientError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ClientError::Dynamic(_) => StatusCode::BAD_REQUEST,
+            ClientError::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError(status, value.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, self.1).into_response()
+    }
+}
+
+fn not_found() -> ApiError {
+    ApiError(StatusCode::NOT_FOUND, "No Device Known".into())
+}
+
+async fn get_devices(
+    State(client): State<PoststationClient>,
+    Query(query): Query<DeviceQuery>,
+) -> Result<Json<Vec<DeviceData>>, ApiError> {
+    let devices = client.get_devices().await?;
+    let mut devices = devices
+        .into_iter()
+        .map(|d| DeviceData {
+            serial: d.serial.into(),
+            name: d.name,
+            is_connected: d.is_connected,
+            manufacturer: d.manufacturer,
+            product: d.product,
+            last_seen: d.last_seen,
+        })
+        .collect();
+    query.apply(&mut devices);
+    Ok(Json(devices))
+}
+
+async fn get_schemas(
+    State(client): State<PoststationClient>,
+    Path(serial): Path<Serial>,
+) -> Result<Json<SchemaReport>, ApiError> {
+    let report = client.get_device_schemas(serial.0).await?;
+    let report = report.ok_or_else(not_found)?;
+    Ok(Json(report.into()))
+}
+
+async fn get_logs(
+    State(client): State<PoststationClient>,
+    Path(serial): Path<Serial>,
+    Query(req): Query<LogRequest>,
+) -> Result<Json<Vec<Log>>, ApiError> {
+    let logs = client.get_device_logs(serial.0, req.count).await?;
+    let logs = logs.ok_or_else(not_found)?;
+    Ok(Json(logs.into_iter().map(rest_log).collect()))
+}
+
+async fn get_logs_range(
+    State(client): State<PoststationClient>,
+    Path(serial): Path<Serial>,
+    Query(req): Query<LogRangeRequest>,
+) -> Result<Json<Vec<Log>>, ApiError> {
+    let anchor = req
+        .anchor()
+        .map_err(|e| ApiError(StatusCode::BAD_REQUEST, e.to_string()))?;
+    let dir = match req.direction {
+        RestDirection::Before => Direction::Before,
+        RestDirection::After => Direction::After,
+    };
+    let logs = client
+        .get_device_logs_range(serial.0, req.count, dir, anchor)
+        .await?;
+    let logs = logs.ok_or_else(not_found)?;
+    Ok(Json(logs.into_iter().map(rest_log).collect()))
+}
+
+fn rest_log(log: poststation_api_icd::postsock::Log) -> Log {
+    Log {
+        uuidv7: log.uuidv7.into(),
+        msg: log.msg,
+    }
+}
+
+/// The key of the request is not checked, the SDK looks up the key of the path
+async fn get_topics(
+    State(client): State<PoststationClient>,
+    Path(serial): Path<Serial>,
+    Query(req): Query<TopicRequest>,
+) -> Result<Json<Vec<TopicMsg>>, ApiError> {
+    let msgs = client
+        .get_device_topics_out_by_path_json(serial.0, &req.path, req.count)
+        .await?;
+    let msgs = msgs.ok_or_else(not_found)?;
+    let msgs = msgs
+        .into_iter()
+        .map(|(uuidv7, mut msg)| {
+            encode_ints(&mut msg, req.int_encoding);
+            TopicMsg {
+                uuidv7: uuidv7.into(),
+                msg,
+            }
+        })
+        .collect();
+    Ok(Json(msgs))
+}
+
+async fn proxy(
+    State(client): State<PoststationClient>,
+    Path(serial): Path<Serial>,
+    Json(req): Json<ProxyRequest>,
+) -> Result<Json<ProxyResponseOk>, (StatusCode, Json<ProxyResponseError>)> {
+    let fut = client.proxy_endpoint_json(serial.0, &req.path, req.seq_no, req.body);
+    let res = match req.deadline_ms {
+        Some(ms) => tokio::time::timeout(Duration::from_millis(ms.into()), fut)
+            .await
+            .unwrap_or(Err(ClientError::Timeout)),
+        None => fut.await,
+    };
+
+    match res {
+        Ok(mut body) => {
+            encode_ints(&mut body, req.int_encoding);
+            Ok(Json(ProxyResponseOk {
+                resp_key: req.resp_key,
+                seq_no: req.seq_no,
+                body,
+                device_latency_us: None,
+            }))
+        }
+        Err(ClientError::Timeout) => Err((
+            StatusCode::GATEWAY_TIMEOUT,
+            Json(ProxyResponseError::Timeout),
+        )),
+        Err(ClientError::RemoteWire(body)) => Err((
+            StatusCode::BAD_GATEWAY,
+            Json(ProxyResponseError::WireErr {
+                resp_key: req.resp_key,
+                seq_no: req.seq_no,
+                body: body.into(),
+            }),
+        )),
+        Err(e) => Err((
+            StatusCode::BAD_GATEWAY,
+            Json(ProxyResponseError::OtherErr(e.to_string())),
+        )),
+    }
+}
+
+async fn publish(
+    State(client): State<PoststationClient>,
+    Path(serial): Path<Serial>,
+    Json(req): Json<PublishRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    client
+        .publish_topic_json(serial.0, &req.path, req.seq_no, req.body)
+        .await?;
+    Ok(Json(serde_json::json!({})))
+}
+
+/// The key of the request is not checked, the SDK looks up the key of the path
+async fn listen(
+    State(client): State<PoststationClient>,
+    Path(serial): Path<Serial>,
+    Query(req): Query<TopicStreamRequest>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    // Start the stream before upgrading, so failures are reported as an error response
+    let sub = client.stream_topic_json(serial.0, &req.path).await?;
+    Ok(ws.on_upgrade(move |socket| forward_stream(sub, req.int_encoding, socket)))
+}
+
+async fn forward_stream(
+    mut sub: JsonStreamListener,
+    int_encoding: IntEncoding,
+    mut socket: WebSocket,
+) {
+    let stream_id = sub.stream_id().into();
+    let path = sub.path().to_string();
+    while let Some(mut msg) = sub.recv().await {
+        encode_ints(&mut msg, int_encoding);
+        // Set by receiving the message
+        let Some(uuidv7) = sub.last_uuid() else {
+            continue;
+        };
+        let msg = TopicStreamMsg {
+            stream_id,
+            path: path.clone(),
+            uuidv7: uuidv7.into(),
+            msg,
+        };
+        let Ok(text) = serde_json::to_string(&msg) else {
+            continue;
+        };
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            // The client has gone away
+            return;
+        }
+    }
+}
+
+async fn get_api_schema() -> Json<ApiDescriptor> {
+    Json(ApiDescriptor::current())
+}
@@ -1,31 +1,19 @@
-use std::sync::atomic::{AtomicU32, Ordering};
-
 use keyboard_3_icd::{Position, Rgb8, SetRgbLed, SetRgbLedEndpoint, SwitchStateTopic};
-use poststation_sdk::{connect, SquadClient, StreamListener};
+use poststation_sdk::{connect_localhost, ClientError, PoststationClient, StreamListener};
 use rand::Rng;
 use smart_leds::hsv::{hsv2rgb, Hsv};
 
 struct Keyboard {
     serial: u64,
-    client: SquadClient,
-    ctr: AtomicU32,
+    client: PoststationClient,
 }
 
 impl Keyboard {
-    pub fn new(client: SquadClient, serial: u64) -> Self {
-        Self {
-            serial,
-            client,
-            ctr: AtomicU32::new(0),
-        }
-    }
-
-    #[inline(always)]
-    fn ctr(&self) -> u32 {
-        self.ctr.fetch_add(1, Ordering::Relaxed)
+    pub fn new(client: PoststationClient, serial: u64) -> Self {
+        Self { serial, client }
     }
 
-    async fn all_black(&self) -> Result<(), String> {
+    async fn all_black(&self) -> Result<(), ClientError> {
         const ALL_POS: [Position; 3] = [Position::One, Position::Two, Position::Three];
         for pos in ALL_POS {
             self.set_black(pos).await?;
@@ -33,11 +21,10 @@ impl Keyboard {
         Ok(())
     }
 
-    async fn set_black(&self, position: Position) -> Result<(), String> {
+    async fn set_black(&self, position: Position) -> Result<(), ClientError> {
         self.client
-            .proxy_endpoint::<SetRgbLedEndpoint>(
+            .proxy_endpoint_seq::<SetRgbLedEndpoint>(
                 self.serial,
-                self.ctr(),
                 &SetRgbLed {
                     position,
                     color: Rgb8 { r: 0, g: 0, b: 0 },
@@ -46,7 +33,7 @@ impl Keyboard {
             .await
     }
 
-    async fn set_random_color(&self, position: Position) -> Result<(), String> {
+    async fn set_random_color(&self, position: Position) -> Result<(), ClientError> {
         let mut rng = rand::thread_rng();
         let hue = rng.gen::<u8>();
         // bias saturation closer to 1.0 to pick more colors than white
@@ -54,9 +41,8 @@ impl Keyboard {
         let sat = (255.0f32 * sat).round() as u8;
         let color = hsv2rgb(Hsv { hue, sat, val: 255 });
         self.client
-            .proxy_endpoint::<SetRgbLedEndpoint>(
+            .proxy_endpoint_seq::<SetRgbLedEndpoint>(
                 self.serial,
-                self.ctr(),
                 &SetRgbLed {
                     position,
                     color: Rgb8 {
@@ -69,7 +55,7 @@ impl Keyboard {
             .await
     }
 
-    async fn subscribe_switches(&self) -> Result<StreamListener<SwitchStateTopic>, String> {
+    async fn subscribe_switches(&self) -> Result<StreamListener<SwitchStateTopic>, ClientError> {
         self.client
             .stream_topic::<SwitchStateTopic>(self.serial)
             .await
@@ -77,9 +63,9 @@ impl Keyboard {
 }
 
 #[tokio::main]
-async fn main() -> Result<(), String> {
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
     const SERIAL: u64 = 0xE66350865F164926;
-    let client = connect("localhost:51837").await;
+    let client = connect_localhost().await?;
     let keyboard = Keyboard::new(client, SERIAL);
     keyboard.all_black().await?;
 
@@ -18,6 +18,7 @@ use postcard_rpc::{
     sender_fmt,
     server::{Dispatch, Sender, Server},
 };
+use poststation_fw_util::format_serial_hex;
 use static_cell::StaticCell;
 
 bind_interrupts!(pub struct Irqs {
@@ -57,25 +58,7 @@ async fn main(spawner: Spawner) {
     let unique_id = get_unique_id();
 
     static SERIAL_STRING: StaticCell<[u8; 16]> = StaticCell::new();
-    let mut ser_buf = [b' '; 16];
-    // This is a simple number-to-hex formatting
-    unique_id
-        .to_be_bytes()
-        .iter()
-        .zip(ser_buf.chunks_exact_mut(2))
-        .for_each(|(b, chs)| {
-            let mut b = *b;
-            for c in chs {
-                *c = match b >> 4 {
-                    v @ 0..10 => b'0' + v,
-                    v @ 10..16 => b'A' + (v - 10),
-                    _ => b'X',
-                };
-                b <<= 4;
-            }
-        });
-    let ser_buf = SERIAL_STRING.init(ser_buf);
-    let ser_buf = core::str::from_utf8(ser_buf.as_slice()).unwrap();
+    let ser_buf = format_serial_hex(unique_id, SERIAL_STRING.init([0; 16]));
 
     // USB/RPC INIT
     let driver = usb::Driver::new(p.USBD, Irqs, HardwareVbusDetect::new(Irqs));
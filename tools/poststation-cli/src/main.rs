@@ -1,20 +1,36 @@
-use std::{collections::HashSet, net::SocketAddr, path::PathBuf, time::Instant};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    fmt::Display,
+    io::{IsTerminal, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use anyhow::bail;
-use clap::{command, Args, Parser, Subcommand};
+use anyhow::{bail, Context};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
 use directories::ProjectDirs;
 use postcard_rpc::host_client::{EndpointReport, SchemaReport};
-use poststation_api_icd::postsock::Direction;
+use poststation_api_icd::{
+    postsock::{Anchor, Direction, Log, StatsRange},
+    serial_from_hex,
+};
 use poststation_sdk::{
     connect, connect_insecure,
     schema::schema::{
         fmt::{discover_tys, is_prim},
-        owned::{OwnedDataModelType, OwnedNamedType},
+        owned::{
+            OwnedDataModelType, OwnedDataModelVariant, OwnedNamedType, OwnedNamedValue,
+            OwnedNamedVariant,
+        },
     },
-    PoststationClient,
+    sort_devices, ClientError, ConnectError, DeviceSort, PoststationClient, SchemaReportExt,
+    CA_CERT_ENV, DEFAULT_SERVER_ADDR,
 };
 use serde_json::json;
-use uuid::Uuid;
 
 /// The Poststation CLI
 #[derive(Parser)]
@@ -25,6 +41,8 @@ struct Cli {
     server: Option<SocketAddr>,
 
     /// When set, a plaintext connection will be made with the server
+    ///
+    /// Only available for servers on localhost.
     #[arg(long)]
     insecure: bool,
 
@@ -33,12 +51,29 @@ struct Cli {
 
     #[arg(long)]
     timings: bool,
+
+    /// Pretty-print the output of `listen` and `proxy` for reading, the default when
+    /// stdout is a terminal
+    #[arg(long, global = true, conflicts_with = "compact")]
+    pretty: bool,
+
+    /// Print the output of `listen` and `proxy` as compact JSON, one value per line,
+    /// the default when stdout is not a terminal
+    #[arg(long, global = true)]
+    compact: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// List devices
-    Ls,
+    Ls {
+        /// The order devices are listed in
+        #[arg(long, value_enum, default_value_t = LsSort::Connected)]
+        sort: LsSort,
+        /// Only list devices with the given tag
+        #[arg(long)]
+        tag: Option<String>,
+    },
 
     /// Endpoints of a given device
     Endpoints { serial: Option<String> },
@@ -47,6 +82,33 @@ enum Commands {
     /// the CA certificate for external usage
     Folder,
 
+    /// Show the disk space used by the database, and by the history of each device
+    Storage,
+
+    /// Rename devices from a file of `serial,name` lines
+    ///
+    /// Empty lines, lines starting with `#`, and a leading `serial,name` header are
+    /// ignored. Serials are given in hex, as shown by `ls`.
+    Provision {
+        #[arg(long, value_name = "CSV")]
+        from: PathBuf,
+    },
+
+    /// Write the schema, logs, and stored topic history of a device to a directory
+    ///
+    /// The schema is written to `schema.json`, the logs to `logs.*`, and the history
+    /// of each "topic-out" path to a file in `topics/`, named after the path with `/`
+    /// replaced by `.`. At most 100000 messages are exported per topic.
+    Export {
+        serial: String,
+        /// The directory to write to, created if missing
+        #[arg(long, value_name = "DIR")]
+        out: PathBuf,
+        /// The format of the log and topic files
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+    },
+
     /// Get information about a device
     Device(Device),
     /// Proxy an endpoint request/response through the server
@@ -73,6 +135,57 @@ enum Commands {
         #[arg(short, long, value_name = "PATH")]
         path: String,
     },
+    /// Print a shell completion script, e.g. `poststation-cli completions bash`
+    ///
+    /// For bash and fish, device serials and names are completed using the devices
+    /// currently known by the server.
+    Completions { shell: Shell },
+    /// Print the serials and names of all devices, used by shell completions
+    #[command(name = "__device-names", hide = true)]
+    DeviceNames,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LogDirection {
+    Before,
+    After,
+}
+
+impl From<LogDirection> for Direction {
+    fn from(value: LogDirection) -> Self {
+        match value {
+            LogDirection::Before => Direction::Before,
+            LogDirection::After => Direction::After,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    /// One JSON object per line
+    Json,
+    /// Comma separated values, with a header line
+    Csv,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LsSort {
+    /// By device name
+    Name,
+    /// By serial number
+    Serial,
+    /// Connected devices first, then by name
+    Connected,
+}
+
+impl From<LsSort> for DeviceSort {
+    fn from(value: LsSort) -> Self {
+        match value {
+            LsSort::Name => DeviceSort::Name,
+            LsSort::Serial => DeviceSort::Serial,
+            LsSort::Connected => DeviceSort::Connected,
+        }
+    }
 }
 
 #[derive(Args)]
@@ -93,74 +206,258 @@ enum DeviceCommands {
     /// View all topics handled by a given device
     TopicsIn,
     /// View the most recent logs from a given device
-    Logs { count: Option<u32> },
+    Logs {
+        count: Option<u32>,
+        /// Truncate messages to this many characters, defaults to the terminal
+        /// width. 0 disables truncation
+        #[arg(long)]
+        max_len: Option<usize>,
+    },
     /// View the most recent logs from a given device
     LogsRange {
+        /// The number of logs to show, defaults to 8
+        #[arg(short, long)]
         count: Option<u32>,
-        start: String,
-        direction: String,
+        /// The log to start from, as a UUID, a unix millisecond timestamp,
+        /// `earliest` or `latest`
+        start: Anchor,
+        /// Whether to show logs before or after `start`
+        #[arg(value_enum, ignore_case = true)]
+        direction: LogDirection,
+        /// Truncate messages to this many characters, defaults to the terminal
+        /// width. 0 disables truncation
+        #[arg(long)]
+        max_len: Option<usize>,
     },
+    /// View the full message of a single log from a given device
+    Log { uuid: uuid::Uuid },
     /// Takes a guess at which endpoint you want to proxy and sends a message to it if you provide one
     SmartProxy {
         command: String,
         message: Option<String>,
+        /// For endpoints taking an enum, the variant to send instead of a message
+        #[arg(long, conflicts_with = "message")]
+        variant: Option<String>,
+        /// A field of `--variant`, as `NAME=JSON` for struct variants, or `JSON`
+        /// for tuple variants, given in order
+        #[arg(long = "field", value_name = "FIELD", requires = "variant")]
+        fields: Vec<String>,
+        /// Send a "zero value" of the request type instead of a message
+        #[arg(long, conflicts_with_all = ["message", "variant"])]
+        example: bool,
+    },
+    /// View statistics over the recent messages of a numeric "topic-out" path
+    Stats {
+        path: String,
+        /// The number of recent messages to include
+        #[arg(long, default_value_t = 100)]
+        count: u32,
     },
+    /// View or modify the tags of a given device
+    Tag {
+        #[command(subcommand)]
+        command: TagCommands,
+    },
+    /// Delete the logs (or a topic's history) stored before a given point in time
+    Prune {
+        /// A log or message UUID, a unix millisecond timestamp, or `latest`
+        #[arg(long)]
+        before: Anchor,
+        /// Prune the history of this "topic-out" path instead of the logs
+        #[arg(long, value_name = "PATH")]
+        topic: Option<String>,
+    },
+    /// Wait until a given device is connected
+    Wait {
+        /// The number of seconds to wait before giving up
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagCommands {
+    /// List the tags of the device
+    List,
+    /// Add a tag to the device
+    Add { tag: String },
+    /// Remove a tag from the device
+    Remove { tag: String },
+}
+
+/// A failure with a specific exit code, see [`exit_code`]
+#[derive(Debug)]
+enum Failure {
+    NotFound(String),
+    /// The device is known, but poststation has not captured its schema
+    NoSchema(u64),
+    Connection(String),
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Failure::NotFound(msg) => f.write_str(msg),
+            Failure::NoSchema(serial) => write!(
+                f,
+                "Device {serial:016X} has no known schema yet — is it connected and responsive?"
+            ),
+            Failure::Connection(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for Failure {}
+
+fn not_found(msg: impl Into<String>) -> anyhow::Error {
+    Failure::NotFound(msg.into()).into()
+}
+
+/// The CA certificate used when [`CA_CERT_ENV`] is not set, in the data folder of poststation
+fn default_ca_cert_path() -> Option<PathBuf> {
+    let dirs = ProjectDirs::from("com.onevariable", "onevariable", "poststation")?;
+    Some(dirs.data_dir().join("ca-cert.pem"))
+}
+
+fn no_schema(serial: u64) -> anyhow::Error {
+    Failure::NoSchema(serial).into()
+}
+
+/// The exit code for an error
+///
+/// * 1: Any other error
+/// * 2: A device, endpoint, or topic was not found, or the device has no known schema
+/// * 3: The device reported an error, or did not respond
+/// * 4: The connection to the server failed
+fn exit_code(err: &anyhow::Error) -> u8 {
+    if let Some(f) = err.downcast_ref::<Failure>() {
+        return match f {
+            Failure::NotFound(_) | Failure::NoSchema(_) => 2,
+            Failure::Connection(_) => 4,
+        };
+    }
+    match err.downcast_ref::<ClientError>() {
+        Some(ClientError::Remote(_) | ClientError::RemoteWire(_) | ClientError::Timeout) => 3,
+        Some(ClientError::ConnectionClosed | ClientError::Protocol) => 4,
+        Some(ClientError::Server(msg)) if is_not_found(msg) => 2,
+        _ => 1,
+    }
+}
+
+/// Does an error reported by the server mean something was not found?
+fn is_not_found(msg: &str) -> bool {
+    let msg = msg.to_lowercase();
+    ["not found", "no device known", "no such"]
+        .iter()
+        .any(|m| msg.contains(m))
 }
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
     let start = Instant::now();
     let timings = cli.timings;
-    inner_main(cli).await?;
+    if let Err(e) = inner_main(cli).await {
+        eprintln!("Error: {e:#}");
+        return ExitCode::from(exit_code(&e));
+    }
     if timings {
         println!("{:?}", start.elapsed());
     }
-    Ok(())
+    ExitCode::SUCCESS
 }
 
 async fn inner_main(cli: Cli) -> anyhow::Result<()> {
-    let server = cli
-        .server
-        .unwrap_or_else(|| "127.0.0.1:51837".parse().unwrap());
+    let server = cli.server.unwrap_or(DEFAULT_SERVER_ADDR);
+    let pretty = cli.pretty || (!cli.compact && std::io::stdout().is_terminal());
 
     let Some(command) = cli.command else {
         return Ok(());
     };
+
+    // Commands that don't need a connection to the server
+    if let Commands::Completions { shell } = command {
+        print_completions(shell);
+        return Ok(());
+    }
+
+    // Insecure connections are always made to localhost, don't silently ignore the address
+    if cli.insecure && !server.ip().is_loopback() {
+        bail!(
+            "Insecure mode is only available for servers on localhost, but '{server}' was given. \
+            Remove `--insecure` to connect with TLS."
+        );
+    }
+
     let client = if cli.insecure {
         connect_insecure(server.port()).await
     } else {
         connect(server).await
     }
-    .unwrap();
+    .map_err(|e| {
+        let hint = match e {
+            ConnectError::TlsHandshake(_) => match std::env::var_os(CA_CERT_ENV) {
+                Some(path) => format!(
+                    "\nThe server's certificate is not trusted by the CA certificate used. \
+                    Is {CA_CERT_ENV} ({}) the `ca-cert.pem` of this server?",
+                    Path::new(&path).display()
+                ),
+                None => {
+                    let used = default_ca_cert_path()
+                        .map(|p| format!(" at {}", p.display()))
+                        .unwrap_or_default();
+                    format!(
+                        "\nThe server's certificate is not trusted by the default CA certificate{used}. \
+                        If the server is not the one running on this machine, set {CA_CERT_ENV} \
+                        to the path of its `ca-cert.pem`."
+                    )
+                }
+            },
+            ConnectError::CaCertificate(_) => {
+                format!("\nSet {CA_CERT_ENV} to the path of the server's `ca-cert.pem`.")
+            }
+            _ => String::new(),
+        };
+        Failure::Connection(format!("Failed to connect to {server}: {e}{hint}"))
+    })?;
 
     match command {
-        Commands::Ls => {
-            let devices = client
-                .get_devices()
-                .await
-                .expect("expected to be able to get devices from server");
+        Commands::Ls { sort, tag } => {
+            let mut devices = match tag {
+                Some(tag) => client.get_devices_by_tag(&tag).await,
+                None => client.get_devices().await,
+            }
+            .context("Failed to get devices from server")?;
+            sort_devices(&mut devices, sort.into());
             println!();
             println!("# Devices");
             println!();
-            println!("| serial           | name       | interface | connected |");
-            println!("| :--------------- | ---------: | :-------- | :-------- |");
+            println!("| serial           | name       | interface | connected | last seen  |");
+            println!("| :--------------- | ---------: | :-------- | :-------- | :--------- |");
             for dev in devices.iter() {
                 let ser = format!("{:016X}", dev.serial);
                 let conn = if dev.is_connected { "yes" } else { "no " };
-                println!("| {ser} | {:>10} | {:<9} | {conn:<9} |", dev.name, "usb");
+                let seen = match dev.last_seen {
+                    _ if dev.is_connected => "now".to_string(),
+                    Some(ms) => format_ago(ms),
+                    None => "unknown".to_string(),
+                };
+                println!(
+                    "| {ser} | {:>10} | {:<9} | {conn:<9} | {seen:<10} |",
+                    dev.name, "usb"
+                );
             }
             println!();
             Ok(())
         }
-        Commands::Device(d) => device_cmds(client, &d).await,
+        Commands::Device(d) => device_cmds(client, &d, pretty).await,
         Commands::Proxy {
             serial,
             message,
             path,
         } => {
             let serial = guess_serial(serial.as_deref(), &client).await?;
-            device_proxy(client, serial, path, message).await
+            device_proxy(client, serial, path, message, pretty).await
         }
         Commands::Publish {
             serial,
@@ -174,8 +471,8 @@ async fn inner_main(cli: Cli) -> anyhow::Result<()> {
             let schema = client
                 .get_device_schemas(serial_num)
                 .await
-                .expect("expected to be able to get schemas for device")
-                .expect("expected device to have schemas known by the server");
+                .context("Failed to get schemas for device")?
+                .ok_or_else(|| no_schema(serial_num))?;
 
             println!();
             println!("# Endpoints for {serial_num:016X}");
@@ -183,7 +480,13 @@ async fn inner_main(cli: Cli) -> anyhow::Result<()> {
             println!("## By path");
             println!();
 
-            let longest_ep = schema.endpoints.iter().map(|e| e.path.len()).max().unwrap();
+            let longest_ep = schema
+                .endpoints
+                .iter()
+                .map(|e| e.path.len())
+                .max()
+                .unwrap_or(0)
+                .max("path".len());
             let longest_req = schema
                 .endpoints
                 .iter()
@@ -241,17 +544,103 @@ async fn inner_main(cli: Cli) -> anyhow::Result<()> {
         }
         Commands::Listen { serial, path } => {
             let serial_num = guess_serial(Some(&serial), &client).await?;
-            let mut sub = match client.stream_topic_json(serial_num, &path).await {
-                Ok(s) => s,
-                Err(e) => bail!("{e}"),
-            };
+            let mut sub = client.stream_topic_json(serial_num, &path).await?;
 
             while let Some(m) = sub.recv().await {
-                println!("{serial_num:016X}:'{path}':{m}");
+                if pretty {
+                    println!("{serial_num:016X}:'{path}':");
+                    println!("{}", serde_json::to_string_pretty(&m)?);
+                } else {
+                    println!("{}", serde_json::to_string(&m)?);
+                }
+            }
+            if pretty {
+                println!("Closed");
+            }
+            Ok(())
+        }
+        Commands::Completions { .. } => unreachable!("handled before connecting"),
+        Commands::DeviceNames => {
+            let devices = client
+                .get_devices()
+                .await
+                .context("Failed to get devices from server")?;
+            for dev in devices.iter() {
+                println!("{:016X}", dev.serial);
+                println!("{}", dev.name);
+            }
+            Ok(())
+        }
+        Commands::Storage => {
+            let report = client
+                .get_storage_report()
+                .await
+                .context("Failed to get storage report from server")?;
+            let devices = client
+                .get_devices()
+                .await
+                .context("Failed to get devices from server")?;
+            let mut rows = report.devices;
+            rows.sort_by_key(|d| std::cmp::Reverse(d.total_bytes()));
+
+            println!();
+            println!("# Storage");
+            println!();
+            println!("Database size: {}", format_bytes(report.total_bytes));
+            println!();
+            println!("| serial           | name       | logs       | topics     | total      |");
+            println!("| :--------------- | ---------: | ---------: | ---------: | ---------: |");
+            for row in rows.iter() {
+                let name = devices
+                    .iter()
+                    .find(|d| d.serial == row.serial)
+                    .map_or("unknown", |d| d.name.as_str());
+                println!(
+                    "| {:016X} | {name:>10} | {:>10} | {:>10} | {:>10} |",
+                    row.serial,
+                    format_bytes(row.log_bytes),
+                    format_bytes(row.topic_bytes),
+                    format_bytes(row.total_bytes()),
+                );
+            }
+            println!();
+            Ok(())
+        }
+        Commands::Provision { from } => {
+            let contents = std::fs::read_to_string(&from)
+                .with_context(|| format!("Failed to read {}", from.display()))?;
+            let names = parse_names(&contents)?;
+            let count = names.len();
+            let results = client
+                .set_device_names(names)
+                .await
+                .context("Failed to rename devices")?;
+
+            println!();
+            let mut failed = 0;
+            for (serial, res) in results {
+                match res {
+                    Ok(()) => println!("{serial:016X}: renamed"),
+                    Err(e) => {
+                        failed += 1;
+                        println!("{serial:016X}: failed, {e}");
+                    }
+                }
+            }
+            println!();
+            if failed != 0 {
+                bail!("{failed} of {count} renames failed");
             }
-            println!("Closed");
             Ok(())
         }
+        Commands::Export {
+            serial,
+            out,
+            format,
+        } => {
+            let serial = guess_serial(Some(&serial), &client).await?;
+            export_device(&client, serial, &out, format).await
+        }
         Commands::Folder => {
             let Some(dirs) = ProjectDirs::from("com.onevariable", "onevariable", "poststation") else {
                 bail!("Failed to get working directory!");
@@ -279,20 +668,33 @@ async fn device_proxy(
     serial: u64,
     path: String,
     message: String,
+    pretty: bool,
 ) -> anyhow::Result<()> {
-    let msg = match message.parse() {
-        Ok(m) => m,
-        Err(_) => {
-            //Attempting to just parse value as a string if it fails
-            json!(message)
-        }
+    let msg = parse_json_arg(&message);
+
+    // Only used for formatting, so a failure to get it must not fail the request
+    let resp_ty = if pretty {
+        client
+            .get_device_schemas(serial)
+            .await
+            .ok()
+            .and_then(|s| s.known())
+            .and_then(|s| s.endpoints.into_iter().find(|e| e.path == path))
+            .map(|e| e.resp_ty)
+    } else {
+        None
     };
 
     let res = client.proxy_endpoint_json(serial, &path, 0, msg).await;
 
-    match res {
-        Ok(v) => println!("Response: '{v}'"),
-        Err(e) => println!("Error: '{e}'"),
+    let v = res?;
+    if !pretty {
+        println!("{}", serde_json::to_string(&v)?);
+        return Ok(());
+    }
+    match resp_ty {
+        Some(ty) => println!("Response: {}", fmt_value(&ty, &v, 0)),
+        None => println!("Response: {}", serde_json::to_string_pretty(&v)?),
     }
 
     Ok(())
@@ -304,26 +706,37 @@ async fn device_publish(
     path: String,
     message: String,
 ) -> anyhow::Result<()> {
-    let serial = u64::from_str_radix(&serial, 16)?;
+    let serial = serial_from_hex(&serial)?;
     let msg = message.parse()?;
 
     let res = client.publish_topic_json(serial, &path, 0, msg).await;
 
-    match res {
-        Ok(()) => println!("Published."),
-        Err(e) => println!("Error: '{e}'"),
-    }
+    res?;
+    println!("Published.");
 
     Ok(())
 }
 
-async fn device_cmds(client: PoststationClient, device: &Device) -> anyhow::Result<()> {
+async fn device_cmds(
+    client: PoststationClient,
+    device: &Device,
+    pretty: bool,
+) -> anyhow::Result<()> {
     let serial = guess_serial(device.serial.as_deref(), &client).await?;
+    // The device may not be connected yet, so it may have no schema
+    if let DeviceCommands::Wait { timeout } = &device.command {
+        let dev = client
+            .wait_for_device(serial, Duration::from_secs(*timeout))
+            .await
+            .context("Device did not connect")?;
+        println!("Device {:016X} ({}) is connected", dev.serial, dev.name);
+        return Ok(());
+    }
     let schema = client
         .get_device_schemas(serial)
         .await
-        .expect("expected to get schemas for device")
-        .expect("expected device to have known schemas");
+        .context("Failed to get schemas for device")?
+        .ok_or_else(|| no_schema(serial))?;
     match &device.command {
         DeviceCommands::Types => {
             println!();
@@ -372,27 +785,18 @@ async fn device_cmds(client: PoststationClient, device: &Device) -> anyhow::Resu
             println!();
             Ok(())
         }
-        DeviceCommands::Logs { count } => {
+        DeviceCommands::Logs { count, max_len } => {
             let count = count.unwrap_or(8);
             let logs = client
                 .get_device_logs(serial, count)
                 .await
-                .expect("expected to be able to get logs for device")
-                .expect("expected device to have known logs");
+                .context("Failed to get logs for device")?
+                .ok_or_else(|| not_found("Device is not known"))?;
 
             println!();
             println!("Logs (last {} messages):", count.min(logs.len() as u32));
             println!();
-            for log in logs {
-                // println!("* {} => {}", log.uuidv7.id_to_time().time(), log.msg);
-                let time = log.uuidv7.id_to_time().time();
-                println!(
-                    "* {} ({}) => {}",
-                    uuid::Uuid::from(log.uuidv7),
-                    time,
-                    log.msg
-                );
-            }
+            print_logs(serial, &logs, *max_len);
             println!();
             Ok(())
         }
@@ -400,50 +804,52 @@ async fn device_cmds(client: PoststationClient, device: &Device) -> anyhow::Resu
             count,
             start,
             direction,
+            max_len,
         } => {
             let count = count.unwrap_or(8);
-            let start = start.parse::<Uuid>()?;
-            let dir = match direction.to_lowercase().as_str() {
-                "after" => Direction::After,
-                "before" => Direction::Before,
-                _ => bail!("Should provide 'after' or 'before' for direction"),
-            };
 
             let logs = client
-                .get_device_logs_range(
-                    serial,
-                    count,
-                    dir,
-                    poststation_api_icd::postsock::Anchor::Uuid(start.into()),
-                )
+                .get_device_logs_range(serial, count, (*direction).into(), *start)
                 .await
-                .expect("expected to be able to get log range for device")
-                .expect("expected device to have known logs");
+                .context("Failed to get log range for device")?
+                .ok_or_else(|| not_found("Device is not known"))?;
 
             println!();
             println!("Logs (last {} messages):", count.min(logs.len() as u32));
             println!();
-            for log in logs {
-                // println!("* {} => {}", log.uuidv7.id_to_time().time(), log.msg);
-                let time = log.uuidv7.id_to_time().time();
-                println!(
-                    "* {} ({}) => {}",
-                    uuid::Uuid::from(log.uuidv7),
-                    time,
-                    log.msg
-                );
-            }
+            print_logs(serial, &logs, *max_len);
+            println!();
+            Ok(())
+        }
+        DeviceCommands::Log { uuid } => {
+            let log = client
+                .get_device_log(serial, (*uuid).into())
+                .await
+                .context("Failed to get log for device")?
+                .ok_or_else(|| not_found("Device is not known"))?
+                .ok_or_else(|| not_found(format!("Device has no log {uuid}")))?;
+
+            println!();
+            println!("Log {uuid} ({}):", log.uuidv7.id_to_time());
+            println!();
+            println!("{}", log.msg);
             println!();
             Ok(())
         }
-        DeviceCommands::SmartProxy { command, message } => {
+        DeviceCommands::SmartProxy {
+            command,
+            message,
+            variant,
+            fields,
+            example,
+        } => {
             let matches = schema
                 .endpoints
                 .iter()
                 .filter(|e| e.path.contains(command))
                 .collect::<Vec<_>>();
             if matches.is_empty() {
-                bail!("No endpoint found matching '{command}'");
+                return Err(not_found(format!("No endpoint found matching '{command}'")));
             } else if matches.len() > 1 {
                 println!("Given '{command}', found:");
                 println!();
@@ -455,18 +861,418 @@ async fn device_cmds(client: PoststationClient, device: &Device) -> anyhow::Resu
             } else {
                 let ep = matches[0];
                 if ep.req_ty.ty == OwnedDataModelType::Unit {
-                    device_proxy(client, serial, ep.path.clone(), "".to_string()).await?;
+                    device_proxy(client, serial, ep.path.clone(), "".to_string(), pretty).await?;
                     return Ok(());
                 }
-                if let Some(message) = message {
-                    device_proxy(client, serial, ep.path.clone(), message.to_owned()).await?;
+                if *example {
+                    let msg = example_value(&ep.req_ty);
+                    if pretty {
+                        println!("Request: {msg}");
+                    }
+                    device_proxy(client, serial, ep.path.clone(), msg.to_string(), pretty).await?;
+                } else if let Some(message) = message {
+                    device_proxy(client, serial, ep.path.clone(), message.to_owned(), pretty)
+                        .await?;
+                } else if let Some(variant) = variant {
+                    let msg = enum_message(&ep.req_ty, variant, fields)?;
+                    device_proxy(client, serial, ep.path.clone(), msg.to_string(), pretty).await?;
+                } else if let OwnedDataModelType::Enum(vars) = &ep.req_ty.ty {
+                    println!(
+                        "Endpoint '{}' takes one of the variants of {}:",
+                        ep.path, ep.req_ty.name
+                    );
+                    println!();
+                    for var in vars {
+                        println!("* {}", fmt_variant(var));
+                    }
+                    println!();
+                    bail!("Choose a variant with `--variant NAME`, and give its fields with `--field`");
                 } else {
                     bail!("Endpoint '{}' requires a message to be sent of the type: async fn({}) -> {}", ep.path, ep.req_ty.name, ep.resp_ty.name);
                 }
             }
             Ok(())
         }
+        DeviceCommands::Stats { path, count } => {
+            let stats = client
+                .get_topic_stats(serial, path, StatsRange::Last(*count))
+                .await?;
+            let Some(stats) = stats else {
+                return Err(not_found(format!(
+                    "Device {serial:016X} has no topic '{path}'"
+                )));
+            };
+
+            println!();
+            println!("Statistics of '{path}' (last {} messages):", stats.count);
+            println!();
+            println!("* min:  {}", stats.min);
+            println!("* max:  {}", stats.max);
+            println!("* mean: {}", stats.mean);
+            println!();
+            Ok(())
+        }
+        DeviceCommands::Tag { command } => {
+            let mut tags = client
+                .get_device_tags(serial)
+                .await
+                .context("Failed to get tags for device")?
+                .ok_or_else(|| not_found("Device is not known"))?;
+            match command {
+                TagCommands::List => {}
+                TagCommands::Add { tag } => {
+                    if !tags.contains(tag) {
+                        tags.push(tag.clone());
+                        client.set_device_tags(serial, tags.clone()).await?;
+                    }
+                }
+                TagCommands::Remove { tag } => {
+                    tags.retain(|t| t != tag);
+                    client.set_device_tags(serial, tags.clone()).await?;
+                }
+            }
+
+            println!();
+            println!("Tags of device {serial:016X}:");
+            println!();
+            for tag in tags {
+                println!("* {tag}");
+            }
+            println!();
+            Ok(())
+        }
+        DeviceCommands::Prune { before, topic } => {
+            let before = *before;
+            let deleted = match topic {
+                Some(path) => client
+                    .delete_device_topic_history_before(serial, path, before)
+                    .await
+                    .context("Failed to delete topic history for device")?
+                    .ok_or_else(|| not_found("Device has no such topic"))?,
+                None => client
+                    .delete_device_logs_before(serial, before)
+                    .await
+                    .context("Failed to delete logs for device")?
+                    .ok_or_else(|| not_found("Device is not known"))?,
+            };
+
+            println!();
+            println!("Deleted {deleted} messages.");
+            println!();
+            Ok(())
+        }
+        DeviceCommands::Wait { .. } => unreachable!("handled before fetching the schema"),
+    }
+}
+
+/// Completes device serials and names, wrapping the generated `_poststation__cli` function
+const BASH_DYNAMIC: &str = r#"
+_poststation__cli_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    local prev="${COMP_WORDS[COMP_CWORD-1]}"
+    local serial=0
+    case "${prev}" in
+        device|endpoints|--serial)
+            serial=1
+            ;;
+        -s)
+            # Before a subcommand, -s is --server
+            local word
+            for word in "${COMP_WORDS[@]:1:COMP_CWORD-1}"; do
+                case "${word}" in
+                    proxy|publish|listen) serial=1 ;;
+                esac
+            done
+            ;;
+    esac
+    if [[ "${serial}" == 1 ]]; then
+        COMPREPLY=( $(compgen -W "$(poststation-cli __device-names 2>/dev/null)" -- "${cur}") )
+        return 0
+    fi
+    _poststation__cli "$@"
+}
+complete -F _poststation__cli_dynamic -o nosort -o bashdefault -o default poststation-cli
+"#;
+
+/// Completes device serials and names, in addition to the generated completions
+const FISH_DYNAMIC: &str = r#"
+complete -c poststation-cli -n "__fish_seen_subcommand_from device endpoints" -f -a "(poststation-cli __device-names 2>/dev/null)"
+complete -c poststation-cli -n "__fish_seen_subcommand_from proxy publish listen" -s s -l serial -f -a "(poststation-cli __device-names 2>/dev/null)"
+"#;
+
+/// The most messages of a single topic written by [`export_device`]
+const EXPORT_TOPIC_LIMIT: u32 = 100_000;
+
+/// Write everything stored about a device to `out`, see [`Commands::Export`]
+async fn export_device(
+    client: &PoststationClient,
+    serial: u64,
+    out: &Path,
+    format: ExportFormat,
+) -> anyhow::Result<()> {
+    let topics_dir = out.join("topics");
+    std::fs::create_dir_all(&topics_dir)
+        .with_context(|| format!("Failed to create {}", topics_dir.display()))?;
+    let ext = match format {
+        ExportFormat::Json => "ndjson",
+        ExportFormat::Csv => "csv",
+    };
+
+    let schema = client
+        .get_device_schemas(serial)
+        .await
+        .context("Failed to get schemas for device")?
+        .ok_or_else(|| no_schema(serial))?;
+    let path = out.join("schema.json");
+    let json = serde_json::to_string_pretty(&schema.to_rest())?;
+    std::fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Wrote the schema to {}", path.display());
+
+    let path = out.join(format!("logs.{ext}"));
+    let mut file = ExportFile::create(&path, format, "uuidv7,time,msg")?;
+    let mut logs = client.replay_device_logs(serial, Anchor::EARLIEST, f32::INFINITY);
+    let mut count = 0u64;
+    while let Some(log) = logs.recv().await {
+        let log = log.context("Failed to get logs for device")?;
+        let uuid = uuid::Uuid::from(log.uuidv7);
+        let time = log.uuidv7.id_to_time().to_rfc3339();
+        match format {
+            ExportFormat::Json => {
+                file.write_line(&json!({ "uuidv7": uuid, "time": time, "msg": log.msg }))?
+            }
+            ExportFormat::Csv => {
+                file.write_line(&format!("{uuid},{time},{}", csv_field(&log.msg)))?
+            }
+        }
+        count += 1;
+        if count.is_multiple_of(10_000) {
+            println!("  {count} logs...");
+        }
+    }
+    file.finish()?;
+    println!("Wrote {count} logs to {}", path.display());
+
+    // Older servers can't list the topics with stored messages, try all of them
+    let topics: Vec<(String, Option<u64>)> = match client.get_active_topics(serial).await {
+        Ok(topics) => topics
+            .ok_or_else(|| not_found("Device is not known"))?
+            .into_iter()
+            .map(|t| (t.path, Some(t.count)))
+            .collect(),
+        Err(ClientError::Unsupported(_)) => schema
+            .topics_out
+            .iter()
+            .map(|t| (t.path.clone(), None))
+            .collect(),
+        Err(e) => return Err(e).context("Failed to get topics of device"),
+    };
+    for (topic, stored) in topics {
+        let Some(mut msgs) = client
+            .get_device_topics_out_by_path_json(serial, &topic, EXPORT_TOPIC_LIMIT)
+            .await
+            .with_context(|| format!("Failed to get messages of '{topic}'"))?
+        else {
+            println!("Skipped '{topic}', it is not in the schema");
+            continue;
+        };
+        msgs.sort_by_key(|(uuid, _)| uuid.0);
+        match stored {
+            Some(stored) if stored > msgs.len() as u64 => println!(
+                "Warning: only exporting {} of the {stored} messages of '{topic}'",
+                msgs.len()
+            ),
+            None if msgs.len() as u64 == u64::from(EXPORT_TOPIC_LIMIT) => println!(
+                "Warning: '{topic}' may have more than the {EXPORT_TOPIC_LIMIT} exported messages"
+            ),
+            _ => {}
+        }
+
+        let name = topic_file_name(&topic);
+        let path = topics_dir.join(format!("{name}.{ext}"));
+        let mut file = ExportFile::create(&path, format, "uuidv7,time,msg")?;
+        for (uuidv7, msg) in &msgs {
+            let uuid = uuid::Uuid::from(*uuidv7);
+            let time = uuidv7.id_to_time().to_rfc3339();
+            match format {
+                ExportFormat::Json => {
+                    file.write_line(&json!({ "uuidv7": uuid, "time": time, "msg": msg }))?
+                }
+                ExportFormat::Csv => {
+                    file.write_line(&format!("{uuid},{time},{}", csv_field(&msg.to_string())))?
+                }
+            }
+        }
+        file.finish()?;
+        println!(
+            "Wrote {} messages of '{topic}' to {}",
+            msgs.len(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// The file name of the history of a topic, e.g. `sensors.temp` for `sensors/temp`
+///
+/// Slashes become dots, and bytes other than ASCII letters, digits, `-` and `_` are
+/// percent encoded, so that different paths never share a file.
+fn topic_file_name(path: &str) -> String {
+    let mut name = String::with_capacity(path.len());
+    for b in path.bytes() {
+        match b {
+            b'/' => name.push('.'),
+            b if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' => name.push(char::from(b)),
+            b => name.push_str(&format!("%{b:02X}")),
+        }
+    }
+    name
+}
+
+/// A buffered file written by [`export_device`]
+struct ExportFile<'a> {
+    path: &'a Path,
+    out: std::io::BufWriter<std::fs::File>,
+}
+
+impl<'a> ExportFile<'a> {
+    /// Create the file, starting with `csv_header` for CSV files
+    fn create(path: &'a Path, format: ExportFormat, csv_header: &str) -> anyhow::Result<Self> {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut this = Self {
+            path,
+            out: std::io::BufWriter::new(file),
+        };
+        if let ExportFormat::Csv = format {
+            this.write_line(&csv_header)?;
+        }
+        Ok(this)
+    }
+
+    fn write_line(&mut self, line: &dyn Display) -> anyhow::Result<()> {
+        writeln!(self.out, "{line}")
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+
+    fn finish(mut self) -> anyhow::Result<()> {
+        self.out
+            .flush()
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+/// Quote a CSV field if needed
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+fn print_completions(shell: Shell) {
+    let mut cmd = Cli::command();
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(shell, &mut cmd, name, &mut std::io::stdout());
+    match shell {
+        Shell::Bash => print!("{BASH_DYNAMIC}"),
+        Shell::Fish => print!("{FISH_DYNAMIC}"),
+        _ => {}
+    }
+}
+
+/// Print one line per log, truncating messages to `max_len` characters, or to
+/// fit the terminal if not given
+fn print_logs(serial: u64, logs: &[Log], max_len: Option<usize>) {
+    // Don't truncate output that isn't shown in a terminal
+    let width = match std::io::stdout().is_terminal() {
+        true => crossterm::terminal::size()
+            .ok()
+            .map(|(w, _)| usize::from(w)),
+        false => None,
+    };
+    let mut truncated = false;
+    for log in logs {
+        let time = log.uuidv7.id_to_time().time();
+        let prefix = format!("* {} ({}) => ", uuid::Uuid::from(log.uuidv7), time);
+        let max_len = match (max_len, width) {
+            (Some(0), _) | (None, None) => usize::MAX,
+            (Some(n), _) => n,
+            // Leave room for at least a little of the message on narrow terminals
+            (None, Some(w)) => w.saturating_sub(prefix.chars().count()).max(16),
+        };
+        let msg = truncate_msg(&log.msg, max_len);
+        truncated |= msg.len() != log.msg.len();
+        println!("{prefix}{msg}");
+    }
+    if truncated {
+        println!();
+        println!("Use `device {serial:016X} log <UUID>` to view a full message.");
+    }
+}
+
+/// Shorten `msg` to at most `max_len` characters, ending with an ellipsis if shortened
+fn truncate_msg(msg: &str, max_len: usize) -> Cow<'_, str> {
+    if msg.chars().count() <= max_len {
+        return Cow::Borrowed(msg);
+    }
+    let kept: String = msg.chars().take(max_len.saturating_sub(1)).collect();
+    Cow::Owned(format!("{kept}…"))
+}
+
+/// Format a unix millisecond timestamp relative to now, e.g. "3h ago"
+fn format_ago(unix_ms: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let secs = now.saturating_sub(unix_ms) / 1000;
+    match secs {
+        0..60 => format!("{secs}s ago"),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+/// Format a number of bytes with a binary unit, e.g. "1.5 MiB"
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut val = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next in &UNITS[1..] {
+        if val < 1024.0 {
+            break;
+        }
+        val /= 1024.0;
+        unit = next;
+    }
+    format!("{val:.1} {unit}")
+}
+
+/// Parse the `serial,name` lines of a provisioning file
+fn parse_names(contents: &str) -> anyhow::Result<Vec<(u64, String)>> {
+    let mut names = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((serial, name)) = line.split_once(',') else {
+            bail!("Line {}: expected `serial,name`, got '{line}'", i + 1);
+        };
+        let (serial, name) = (serial.trim(), name.trim());
+        if names.is_empty() && serial.eq_ignore_ascii_case("serial") {
+            continue;
+        }
+        let serial = serial_from_hex(serial)
+            .with_context(|| format!("Line {}: invalid serial '{serial}'", i + 1))?;
+        names.push((serial, name.to_string()));
     }
+    Ok(names)
 }
 
 async fn guess_serial(serial: Option<&str>, client: &PoststationClient) -> anyhow::Result<u64> {
@@ -485,8 +1291,11 @@ async fn guess_serial(serial: Option<&str>, client: &PoststationClient) -> anyho
     let mut serial_num = None;
     let mut serial_fragment = false;
 
-    if let Ok(ser) = u64::from_str_radix(&serial, 16) {
-        if serial.len() == 16 {
+    if let Ok(ser) = serial_from_hex(&serial) {
+        // Prefixed (uppercased to `0X`) or separated serials are copied from Rust
+        // literals, and are never fragments
+        let is_literal = serial.starts_with("0X") || serial.contains('_');
+        if is_literal || serial.len() == 16 {
             serial_num = Some(ser);
         } else {
             serial_fragment = true;
@@ -497,7 +1306,7 @@ async fn guess_serial(serial: Option<&str>, client: &PoststationClient) -> anyho
         let devices = client
             .get_devices()
             .await
-            .expect("expected to be able to get devices");
+            .context("Failed to get devices")?;
         let uppy = serial.to_uppercase();
         let matches = devices
             .iter()
@@ -511,7 +1320,9 @@ async fn guess_serial(serial: Option<&str>, client: &PoststationClient) -> anyho
             .collect::<Vec<_>>();
 
         if matches.is_empty() {
-            bail!("Failed to find device matching '{serial}'");
+            return Err(not_found(format!(
+                "Failed to find device matching '{serial}'"
+            )));
         } else if matches.len() > 1 {
             println!("Given '{serial}', found:");
             println!();
@@ -531,6 +1342,224 @@ async fn guess_serial(serial: Option<&str>, client: &PoststationClient) -> anyho
     Ok(serial_num)
 }
 
+/// Build the JSON message for a variant of an enum type, from `--field` arguments
+fn enum_message(
+    ty: &OwnedNamedType,
+    variant: &str,
+    fields: &[String],
+) -> anyhow::Result<serde_json::Value> {
+    let OwnedDataModelType::Enum(vars) = &ty.ty else {
+        bail!("'{}' is not an enum, provide a message instead", ty.name);
+    };
+    let Some(var) = vars.iter().find(|v| v.name == variant) else {
+        bail!("'{}' has no variant '{variant}'", ty.name);
+    };
+
+    let body = match &var.ty {
+        OwnedDataModelVariant::UnitVariant => {
+            if !fields.is_empty() {
+                bail!("'{variant}' has no fields");
+            }
+            return Ok(json!(variant));
+        }
+        OwnedDataModelVariant::NewtypeVariant(_) => {
+            let [field] = fields else {
+                bail!("'{}' takes exactly one field", fmt_variant(var));
+            };
+            parse_json_arg(field)
+        }
+        OwnedDataModelVariant::TupleVariant(tys) => {
+            if fields.len() != tys.len() {
+                bail!("'{}' takes {} fields", fmt_variant(var), tys.len());
+            }
+            fields.iter().map(|f| parse_json_arg(f)).collect()
+        }
+        OwnedDataModelVariant::StructVariant(vals) => {
+            let mut map = serde_json::Map::new();
+            for field in fields {
+                let Some((name, val)) = field.split_once('=') else {
+                    bail!("Fields of '{variant}' must be given as `NAME=JSON`");
+                };
+                if !vals.iter().any(|v| v.name == name) {
+                    bail!("'{}' has no field '{name}'", fmt_variant(var));
+                }
+                map.insert(name.to_string(), parse_json_arg(val));
+            }
+            if let Some(missing) = vals.iter().find(|v| !map.contains_key(&v.name)) {
+                bail!("Missing field '{}' of '{}'", missing.name, fmt_variant(var));
+            }
+            map.into()
+        }
+    };
+    Ok(json!({ variant: body }))
+}
+
+/// Build a "zero value" of a type: zero, empty, or the first variant
+fn example_value(ty: &OwnedNamedType) -> serde_json::Value {
+    let fields = |vals: &[OwnedNamedValue]| {
+        vals.iter()
+            .map(|v| (v.name.clone(), example_value(&v.ty)))
+            .collect::<serde_json::Map<_, _>>()
+    };
+    let items = |tys: &[OwnedNamedType]| tys.iter().map(example_value).collect::<Vec<_>>();
+
+    match &ty.ty {
+        OwnedDataModelType::Bool => json!(false),
+        OwnedDataModelType::I8
+        | OwnedDataModelType::U8
+        | OwnedDataModelType::I16
+        | OwnedDataModelType::I32
+        | OwnedDataModelType::I64
+        | OwnedDataModelType::I128
+        | OwnedDataModelType::U16
+        | OwnedDataModelType::U32
+        | OwnedDataModelType::U64
+        | OwnedDataModelType::U128
+        | OwnedDataModelType::Usize
+        | OwnedDataModelType::Isize => json!(0),
+        OwnedDataModelType::F32 | OwnedDataModelType::F64 => json!(0.0),
+        OwnedDataModelType::Char => json!("\0"),
+        OwnedDataModelType::String => json!(""),
+        OwnedDataModelType::ByteArray | OwnedDataModelType::Seq(_) => json!([]),
+        OwnedDataModelType::Map { .. } => json!({}),
+        OwnedDataModelType::Option(_)
+        | OwnedDataModelType::Unit
+        | OwnedDataModelType::UnitStruct
+        | OwnedDataModelType::Schema => json!(null),
+        OwnedDataModelType::NewtypeStruct(inner) => example_value(inner),
+        OwnedDataModelType::Tuple(tys) | OwnedDataModelType::TupleStruct(tys) => json!(items(tys)),
+        OwnedDataModelType::Struct(vals) => json!(fields(vals)),
+        OwnedDataModelType::Enum(vars) => {
+            let Some(var) = vars.first() else {
+                return json!(null);
+            };
+            let body = match &var.ty {
+                OwnedDataModelVariant::UnitVariant => return json!(var.name),
+                OwnedDataModelVariant::NewtypeVariant(inner) => example_value(inner),
+                OwnedDataModelVariant::TupleVariant(tys) => json!(items(tys)),
+                OwnedDataModelVariant::StructVariant(vals) => json!(fields(vals)),
+            };
+            json!({ &var.name: body })
+        }
+    }
+}
+
+/// Parse a JSON argument, treating it as a plain string if it is not valid JSON
+fn parse_json_arg(arg: &str) -> serde_json::Value {
+    arg.parse().unwrap_or_else(|_| json!(arg))
+}
+
+/// Format an enum variant along with the types of its fields
+fn fmt_variant(var: &OwnedNamedVariant) -> String {
+    match &var.ty {
+        OwnedDataModelVariant::UnitVariant => var.name.clone(),
+        OwnedDataModelVariant::NewtypeVariant(ty) => format!("{}({})", var.name, ty.name),
+        OwnedDataModelVariant::TupleVariant(tys) => {
+            let tys = tys.iter().map(|t| t.name.as_str()).collect::<Vec<_>>();
+            format!("{}({})", var.name, tys.join(", "))
+        }
+        OwnedDataModelVariant::StructVariant(vals) => {
+            let fields = vals
+                .iter()
+                .map(|v| format!("{}: {}", v.name, v.ty.name))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{} {{ {fields} }}", var.name)
+        }
+    }
+}
+
+/// Format a value as indented pseudo-Rust, labeled with the names of its type
+fn fmt_value(ty: &OwnedNamedType, val: &serde_json::Value, indent: usize) -> String {
+    use serde_json::Value;
+
+    let pad = "    ".repeat(indent + 1);
+    let end = "    ".repeat(indent);
+    match (&ty.ty, val) {
+        (OwnedDataModelType::Option(_), Value::Null) => "None".to_string(),
+        (OwnedDataModelType::Option(inner), v) => format!("Some({})", fmt_value(inner, v, indent)),
+        (OwnedDataModelType::UnitStruct, _) => ty.name.clone(),
+        (OwnedDataModelType::NewtypeStruct(inner), v) => {
+            format!("{}({})", ty.name, fmt_value(inner, v, indent))
+        }
+        (OwnedDataModelType::Tuple(tys), Value::Array(items)) => {
+            format!("({})", fmt_items(tys, items, indent))
+        }
+        (OwnedDataModelType::TupleStruct(tys), Value::Array(items)) => {
+            format!("{}({})", ty.name, fmt_items(tys, items, indent))
+        }
+        (OwnedDataModelType::Struct(vals), Value::Object(map)) => {
+            fmt_fields(&ty.name, vals, map, indent)
+        }
+        (OwnedDataModelType::Seq(_), Value::Array(items)) if items.is_empty() => "[]".to_string(),
+        (OwnedDataModelType::Seq(inner), Value::Array(items)) => {
+            let mut out = "[\n".to_string();
+            for item in items {
+                out += &format!("{pad}{},\n", fmt_value(inner, item, indent + 1));
+            }
+            out + &end + "]"
+        }
+        (OwnedDataModelType::Map { val: vty, .. }, Value::Object(map)) if !map.is_empty() => {
+            let mut out = "{\n".to_string();
+            for (k, v) in map {
+                out += &format!("{pad}{k}: {},\n", fmt_value(vty, v, indent + 1));
+            }
+            out + &end + "}"
+        }
+        (OwnedDataModelType::Enum(_), Value::String(var)) => format!("{}::{var}", ty.name),
+        (OwnedDataModelType::Enum(vars), Value::Object(map)) if map.len() == 1 => {
+            let (name, body) = map.iter().next().unwrap();
+            let Some(var) = vars.iter().find(|v| &v.name == name) else {
+                return val.to_string();
+            };
+            let name = format!("{}::{name}", ty.name);
+            match (&var.ty, body) {
+                (OwnedDataModelVariant::NewtypeVariant(inner), v) => {
+                    format!("{name}({})", fmt_value(inner, v, indent))
+                }
+                (OwnedDataModelVariant::TupleVariant(tys), Value::Array(items)) => {
+                    format!("{name}({})", fmt_items(tys, items, indent))
+                }
+                (OwnedDataModelVariant::StructVariant(vals), Value::Object(map)) => {
+                    fmt_fields(&name, vals, map, indent)
+                }
+                _ => val.to_string(),
+            }
+        }
+        _ => val.to_string(),
+    }
+}
+
+/// Format the fields of a struct, one per line
+fn fmt_fields(
+    name: &str,
+    vals: &[OwnedNamedValue],
+    map: &serde_json::Map<String, serde_json::Value>,
+    indent: usize,
+) -> String {
+    let pad = "    ".repeat(indent + 1);
+    let mut out = format!("{name} {{\n");
+    for field in vals {
+        if let Some(v) = map.get(&field.name) {
+            out += &format!(
+                "{pad}{}: {},\n",
+                field.name,
+                fmt_value(&field.ty, v, indent + 1)
+            );
+        }
+    }
+    out + &"    ".repeat(indent) + "}"
+}
+
+/// Format the items of a tuple, separated by commas
+fn fmt_items(tys: &[OwnedNamedType], items: &[serde_json::Value], indent: usize) -> String {
+    tys.iter()
+        .zip(items)
+        .map(|(t, v)| fmt_value(t, v, indent))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 fn print_endpoint(ep: &EndpointReport) {
     if ep.resp_ty.ty == OwnedDataModelType::Unit {
         println!("* '{}' => async fn({})", ep.path, ep.req_ty.name);
@@ -541,3 +1570,70 @@ fn print_endpoint(ep: &EndpointReport) {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_names, topic_file_name};
+
+    #[test]
+    fn names_with_header_comments_and_whitespace() {
+        let contents = "\
+# Bench devices
+serial,name
+
+  E66350865F164926 , left sensor  
+0x0000_0000_0000_00FF,right sensor
+# done
+";
+        let names = parse_names(contents).unwrap();
+        assert_eq!(
+            names,
+            vec![
+                (0xE66350865F164926, "left sensor".to_string()),
+                (0xFF, "right sensor".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn header_is_only_skipped_before_any_names() {
+        let err = parse_names("01,first\nserial,name\n").unwrap_err();
+        assert!(format!("{err:#}").contains("Line 2: invalid serial 'serial'"));
+    }
+
+    #[test]
+    fn names_may_contain_commas() {
+        let names = parse_names("01,a, b").unwrap();
+        assert_eq!(names, vec![(1, "a, b".to_string())]);
+    }
+
+    #[test]
+    fn empty_input() {
+        assert!(parse_names("").unwrap().is_empty());
+        assert!(parse_names("\n  \n# nothing\n").unwrap().is_empty());
+        assert!(parse_names("serial,name\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn bad_lines_are_errors() {
+        let err = parse_names("01,ok\nE663XYZ,bad\n").unwrap_err();
+        assert!(format!("{err:#}").contains("Line 2: invalid serial 'E663XYZ'"));
+
+        let err = parse_names("01,ok\n\n02\n").unwrap_err();
+        assert!(format!("{err:#}").contains("Line 3: expected `serial,name`, got '02'"));
+    }
+
+    #[test]
+    fn topic_file_names_are_distinct() {
+        assert_eq!(topic_file_name("sensors/temp-1_a"), "sensors.temp-1_a");
+        assert_eq!(topic_file_name("a.b"), "a%2Eb");
+        assert_eq!(topic_file_name("a%2Eb"), "a%252Eb");
+        assert_eq!(topic_file_name("ä"), "%C3%A4");
+
+        let paths = ["a/b", "a.b", "a_b", "a b", "a%2Fb", "a.b/c", "a/b.c"];
+        let mut names: Vec<_> = paths.iter().map(|p| topic_file_name(p)).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), paths.len());
+    }
+}
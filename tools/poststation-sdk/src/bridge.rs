@@ -0,0 +1,147 @@
+//! Forwarding the messages of one device's topic to another device
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use postcard_dyn::Value;
+use poststation_api_icd::postsock::{features, DeviceEvent};
+use tokio::task::AbortHandle;
+
+use crate::{ClientError, DeviceEventListener, JsonStreamListener, PoststationClient};
+
+/// A running bridge between two topics
+///
+/// Created by [`PoststationClient::bridge_topic`]. The bridge runs in a background
+/// task until it is stopped, the handle is dropped, or the connection is closed.
+#[must_use = "the bridge is stopped when the handle is dropped"]
+pub struct BridgeHandle {
+    task: AbortHandle,
+    stats: Arc<BridgeStats>,
+}
+
+#[derive(Default)]
+struct BridgeStats {
+    forwarded: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl BridgeHandle {
+    /// Stop forwarding messages
+    pub fn stop(self) {
+        // Dropping the handle aborts the task
+    }
+
+    /// Is the bridge still forwarding messages?
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+
+    /// The number of messages published to the destination so far
+    pub fn forwarded(&self) -> u64 {
+        self.stats.forwarded.load(Ordering::Relaxed)
+    }
+
+    /// The number of messages that could not be published to the destination
+    pub fn failed(&self) -> u64 {
+        self.stats.failed.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for BridgeHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+enum Event {
+    Msg(Option<Value>),
+    Device(Option<DeviceEvent>),
+}
+
+pub(crate) struct Bridge<F> {
+    pub(crate) client: PoststationClient,
+    pub(crate) from: (u64, String),
+    pub(crate) to: (u64, String),
+    pub(crate) transform: F,
+}
+
+impl<F> Bridge<F>
+where
+    F: Fn(Value) -> Option<Value> + Send + Sync + 'static,
+{
+    /// Start streaming the source topic, and spawn the forwarding task
+    pub(crate) async fn spawn(self) -> Result<BridgeHandle, ClientError> {
+        // Restart the stream when the source device reconnects, if the server can tell us
+        let events = match self.client.server_capabilities() {
+            Some(caps) if caps.supports(features::DEVICE_EVENTS) => {
+                Some(self.client.subscribe_device_events().await?)
+            }
+            _ => None,
+        };
+        let sub = self
+            .client
+            .stream_topic_json(self.from.0, &self.from.1)
+            .await?;
+
+        let stats = Arc::new(BridgeStats::default());
+        let task = tokio::spawn(self.run(sub, events, stats.clone()));
+        Ok(BridgeHandle {
+            task: task.abort_handle(),
+            stats,
+        })
+    }
+
+    async fn run(
+        self,
+        mut sub: JsonStreamListener,
+        mut events: Option<DeviceEventListener>,
+        stats: Arc<BridgeStats>,
+    ) {
+        loop {
+            let event = match &mut events {
+                Some(events) => tokio::select! {
+                    msg = sub.recv() => Event::Msg(msg),
+                    ev = events.recv() => Event::Device(ev),
+                },
+                None => Event::Msg(sub.recv().await),
+            };
+
+            match event {
+                Event::Msg(Some(msg)) => {
+                    let Some(msg) = (self.transform)(msg) else {
+                        continue;
+                    };
+                    let seq_no = self.client.next_seq_no(self.to.0);
+                    let res = self
+                        .client
+                        .publish_topic_json(self.to.0, &self.to.1, seq_no, msg)
+                        .await;
+                    match res {
+                        Ok(()) => stats.forwarded.fetch_add(1, Ordering::Relaxed),
+                        Err(e) => {
+                            tracing::warn!(serial = self.to.0, path = self.to.1, error = ?e, "Failed to forward message");
+                            stats.failed.fetch_add(1, Ordering::Relaxed)
+                        }
+                    };
+                }
+                Event::Device(Some(DeviceEvent::Connected(dev))) if dev.serial == self.from.0 => {
+                    match self
+                        .client
+                        .stream_topic_json(self.from.0, &self.from.1)
+                        .await
+                    {
+                        Ok(new) => sub = new,
+                        Err(e) => {
+                            tracing::warn!(serial = self.from.0, path = self.from.1, error = ?e, "Failed to restart stream");
+                        }
+                    }
+                }
+                Event::Device(Some(_)) => {}
+                // The connection has been closed
+                Event::Msg(None) | Event::Device(None) => return,
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Sharing one in-flight schema request between concurrent callers
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use postcard_rpc::host_client::SchemaReport;
+use tokio::sync::watch;
+
+type Fetched = Option<Option<SchemaReport>>;
+
+/// The schema requests currently in flight, by serial
+///
+/// The first caller for a serial makes the request and shares the result, later
+/// callers wait for it instead of making their own. Errors are not shared: if the
+/// request fails or is cancelled, the waiting callers make their own requests.
+#[derive(Default)]
+pub(crate) struct SchemaFetches {
+    in_flight: Arc<Mutex<HashMap<u64, watch::Receiver<Fetched>>>>,
+}
+
+pub(crate) enum Fetch {
+    /// No request is in flight, the caller must make one and share the result
+    Lead(FetchLead),
+    /// Another caller is making the request
+    Join(watch::Receiver<Fetched>),
+}
+
+impl SchemaFetches {
+    pub(crate) fn start(&self, serial: u64) -> Fetch {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if let Some(rx) = in_flight.get(&serial) {
+            return Fetch::Join(rx.clone());
+        }
+        let (tx, rx) = watch::channel(None);
+        in_flight.insert(serial, rx);
+        Fetch::Lead(FetchLead {
+            in_flight: self.in_flight.clone(),
+            serial,
+            tx,
+        })
+    }
+}
+
+/// Wait for the result of another caller's request
+///
+/// Returns None if that request failed or was cancelled.
+pub(crate) async fn join(mut rx: watch::Receiver<Fetched>) -> Fetched {
+    let res = rx.wait_for(Option::is_some).await.ok()?;
+    res.clone()
+}
+
+/// Removes the request from the in flight list when dropped
+pub(crate) struct FetchLead {
+    in_flight: Arc<Mutex<HashMap<u64, watch::Receiver<Fetched>>>>,
+    serial: u64,
+    tx: watch::Sender<Fetched>,
+}
+
+impl FetchLead {
+    pub(crate) fn finish(self, res: &Option<SchemaReport>) {
+        self.tx.send_replace(Some(res.clone()));
+    }
+}
+
+impl Drop for FetchLead {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(&self.serial);
+    }
+}
@@ -0,0 +1,602 @@
+//! Decoding postcard messages to JSON values with a prepared schema
+
+use std::str::from_utf8;
+
+use postcard_dyn::Value;
+use postcard_schema::schema::owned::{
+    OwnedDataModelType, OwnedDataModelVariant, OwnedNamedType, OwnedNamedValue,
+};
+use serde_json::{Map, Number};
+
+use crate::{transcode, ClientError, ValueFormat};
+
+/// Decodes raw, postcard encoded messages of one type to JSON values
+///
+/// The values, and the kinds of errors, are the same as those of
+/// `postcard_dyn::from_slice_dyn`, but the schema is prepared once instead of for
+/// every message: wrappers such as newtypes and single item tuples are resolved, and
+/// the fields of enum variants are not copied for each decoded variant. This makes
+/// decoding many messages of the same type cheaper, and is used by the JSON topic
+/// streams and [`decode_topic_raw`](crate::decode_topic_raw).
+///
+/// Unlike `postcard_dyn`, which panics, `char`s and schemas are rejected with an
+/// error.
+#[derive(Debug, Clone)]
+pub struct DynDecoder {
+    node: Node,
+}
+
+impl DynDecoder {
+    pub fn new(ty: &OwnedNamedType) -> Self {
+        Self {
+            node: Node::new(&ty.ty),
+        }
+    }
+
+    /// Decode a single message
+    pub fn decode(&self, bytes: &[u8]) -> Result<Value, ClientError> {
+        self.decode_inner(bytes).map_err(|_| ClientError::Encoding)
+    }
+
+    /// Encode a single message to the given format
+    ///
+    /// See [`encode_topic_raw`](crate::encode_topic_raw) for how the output differs
+    /// from the values of [`Self::decode`].
+    pub fn encode(&self, bytes: &[u8], format: ValueFormat) -> Result<Vec<u8>, ClientError> {
+        transcode::encode(&self.node, bytes, format)
+    }
+
+    pub(crate) fn decode_inner(&self, bytes: &[u8]) -> Result<Value, DecodeError> {
+        self.node.decode(bytes).map(|(val, _rest)| val)
+    }
+}
+
+/// Why a message could not be decoded, named like the errors of `postcard_dyn`
+#[derive(Debug, PartialEq)]
+pub(crate) enum DecodeError {
+    UnexpectedEndOfData,
+    ShouldSupportButDont,
+    SchemaMismatch,
+}
+
+/// A prepared schema, also walked by the encoder in `transcode.rs`
+///
+/// This describes every type of the data model, including the ones that can't be
+/// decoded to JSON values, such as maps with non-string keys.
+#[derive(Debug, Clone)]
+pub(crate) enum Node {
+    Bool,
+    I8,
+    U8,
+    I16,
+    I32,
+    I64,
+    I128,
+    U16,
+    U32,
+    U64,
+    U128,
+    Usize,
+    Isize,
+    F32,
+    F64,
+    Char,
+    String,
+    ByteArray,
+    Option(Box<Node>),
+    /// Units, unit structs and empty tuples, which have no data
+    Unit,
+    Seq(Box<Node>),
+    Tuple(Vec<Node>),
+    Map {
+        key: Box<Node>,
+        val: Box<Node>,
+    },
+    Struct(Vec<(String, Node)>),
+    /// The name of each variant, and the type of its fields unless it is a unit variant
+    Enum(Vec<(String, Option<Node>)>),
+    Schema,
+}
+
+impl Node {
+    fn new(ty: &OwnedDataModelType) -> Self {
+        match ty {
+            OwnedDataModelType::Bool => Node::Bool,
+            OwnedDataModelType::I8 => Node::I8,
+            OwnedDataModelType::U8 => Node::U8,
+            OwnedDataModelType::I16 => Node::I16,
+            OwnedDataModelType::I32 => Node::I32,
+            OwnedDataModelType::I64 => Node::I64,
+            OwnedDataModelType::I128 => Node::I128,
+            OwnedDataModelType::U16 => Node::U16,
+            OwnedDataModelType::U32 => Node::U32,
+            OwnedDataModelType::U64 => Node::U64,
+            OwnedDataModelType::U128 => Node::U128,
+            OwnedDataModelType::Usize => Node::Usize,
+            OwnedDataModelType::Isize => Node::Isize,
+            OwnedDataModelType::F32 => Node::F32,
+            OwnedDataModelType::F64 => Node::F64,
+            OwnedDataModelType::Char => Node::Char,
+            OwnedDataModelType::String => Node::String,
+            OwnedDataModelType::ByteArray => Node::ByteArray,
+            OwnedDataModelType::Option(nt) => Node::Option(Box::new(Node::new(&nt.ty))),
+            OwnedDataModelType::Unit | OwnedDataModelType::UnitStruct => Node::Unit,
+            OwnedDataModelType::NewtypeStruct(nt) => Node::new(&nt.ty),
+            OwnedDataModelType::Seq(nt) => Node::Seq(Box::new(Node::new(&nt.ty))),
+            OwnedDataModelType::Tuple(nts) | OwnedDataModelType::TupleStruct(nts) => {
+                Node::tuple(nts)
+            }
+            OwnedDataModelType::Map { key, val } => Node::Map {
+                key: Box::new(Node::new(&key.ty)),
+                val: Box::new(Node::new(&val.ty)),
+            },
+            OwnedDataModelType::Struct(nvs) => Node::fields(nvs),
+            OwnedDataModelType::Enum(nvars) => Node::Enum(
+                nvars
+                    .iter()
+                    .map(|var| {
+                        let node = match &var.ty {
+                            OwnedDataModelVariant::UnitVariant => None,
+                            OwnedDataModelVariant::NewtypeVariant(nt) => Some(Node::new(&nt.ty)),
+                            OwnedDataModelVariant::TupleVariant(nts) => Some(Node::tuple(nts)),
+                            OwnedDataModelVariant::StructVariant(nvs) => Some(Node::fields(nvs)),
+                        };
+                        (var.name.clone(), node)
+                    })
+                    .collect(),
+            ),
+            OwnedDataModelType::Schema => Node::Schema,
+        }
+    }
+
+    fn fields(nvs: &[OwnedNamedValue]) -> Self {
+        Node::Struct(
+            nvs.iter()
+                .map(|nv| (nv.name.clone(), Node::new(&nv.ty.ty)))
+                .collect(),
+        )
+    }
+
+    fn tuple(nts: &[OwnedNamedType]) -> Self {
+        match nts {
+            [] => Node::Unit,
+            // A single item is not wrapped in an array
+            [nt] => Node::new(&nt.ty),
+            multi => Node::Tuple(multi.iter().map(|nt| Node::new(&nt.ty)).collect()),
+        }
+    }
+
+    /// The fewest bytes a value of this type can be encoded in
+    pub(crate) fn min_size(&self) -> usize {
+        match self {
+            Node::Unit => 0,
+            Node::Tuple(nodes) => nodes.iter().map(Node::min_size).sum(),
+            Node::Struct(fields) => fields.iter().map(|(_, node)| node.min_size()).sum(),
+            Node::F32 => 4,
+            Node::F64 => 8,
+            _ => 1,
+        }
+    }
+
+    fn decode<'a>(&self, data: &'a [u8]) -> Result<(Value, &'a [u8]), DecodeError> {
+        let (val, rest) = match self {
+            Node::Bool => match take_one(data)? {
+                (0, rest) => (Value::Bool(false), rest),
+                (1, rest) => (Value::Bool(true), rest),
+                _ => return Err(DecodeError::SchemaMismatch),
+            },
+            Node::I8 => {
+                let (val, rest) = take_one(data)?;
+                (Value::from(val as i8), rest)
+            }
+            Node::U8 => {
+                let (val, rest) = take_one(data)?;
+                (Value::from(val), rest)
+            }
+            Node::I16 => {
+                let (val, rest) = take_varint(data, 16)?;
+                (Value::from(zig_zag(val) as i16), rest)
+            }
+            Node::I32 => {
+                let (val, rest) = take_varint(data, 32)?;
+                (Value::from(zig_zag(val) as i32), rest)
+            }
+            Node::I64 => {
+                let (val, rest) = take_varint(data, 64)?;
+                (Value::from(zig_zag(val) as i64), rest)
+            }
+            Node::Isize => {
+                let (val, rest) = take_varint(data, usize::BITS)?;
+                (Value::from(zig_zag(val) as i64), rest)
+            }
+            Node::I128 => {
+                let (val, rest) = take_varint(data, 128)?;
+                let val =
+                    i64::try_from(zig_zag(val)).map_err(|_| DecodeError::ShouldSupportButDont)?;
+                (Value::from(val), rest)
+            }
+            Node::U16 | Node::U32 | Node::U64 | Node::Usize => {
+                let bits = match self {
+                    Node::U16 => 16,
+                    Node::U32 => 32,
+                    Node::U64 => 64,
+                    _ => usize::BITS,
+                };
+                let (val, rest) = take_varint(data, bits)?;
+                (Value::from(val as u64), rest)
+            }
+            Node::U128 => {
+                let (val, rest) = take_varint(data, 128)?;
+                let val = u64::try_from(val).map_err(|_| DecodeError::ShouldSupportButDont)?;
+                (Value::from(val), rest)
+            }
+            Node::F32 => {
+                let (bytes, rest) = take_n(data, 4)?;
+                let val = f32::from_le_bytes(bytes.try_into().unwrap());
+                (float(val.into())?, rest)
+            }
+            Node::F64 => {
+                let (bytes, rest) = take_n(data, 8)?;
+                let val = f64::from_le_bytes(bytes.try_into().unwrap());
+                (float(val)?, rest)
+            }
+            Node::String => {
+                let (s, rest) = take_str(data)?;
+                (Value::String(s.to_string()), rest)
+            }
+            Node::ByteArray => {
+                let (len, rest) = take_len(data)?;
+                let (bytes, rest) = take_n(rest, len)?;
+                (bytes.iter().copied().map(Value::from).collect(), rest)
+            }
+            Node::Option(node) => match take_one(data)? {
+                (0, rest) => (Value::Null, rest),
+                (1, rest) => node.decode(rest)?,
+                _ => return Err(DecodeError::SchemaMismatch),
+            },
+            Node::Unit => (Value::Null, data),
+            Node::Seq(node) => {
+                let (len, mut rest) = take_seq_len(data, node.min_size())?;
+                let mut items = vec![];
+                for _ in 0..len {
+                    let (item, irest) = node.decode(rest)?;
+                    rest = irest;
+                    items.push(item);
+                }
+                (Value::Array(items), rest)
+            }
+            Node::Tuple(nodes) => {
+                let mut rest = data;
+                let mut items = Vec::with_capacity(nodes.len());
+                for node in nodes {
+                    let (item, irest) = node.decode(rest)?;
+                    rest = irest;
+                    items.push(item);
+                }
+                (Value::Array(items), rest)
+            }
+            Node::Map { key, val: node } => {
+                // JSON objects only have string keys
+                if !matches!(**key, Node::String) {
+                    return Err(DecodeError::ShouldSupportButDont);
+                }
+                let (len, mut rest) = take_seq_len(data, key.min_size() + node.min_size())?;
+                let mut map = Map::new();
+                for _ in 0..len {
+                    let (key, irest) = take_str(rest)?;
+                    let (val, irest) = node.decode(irest)?;
+                    rest = irest;
+                    map.insert(key.to_string(), val);
+                }
+                (Value::Object(map), rest)
+            }
+            Node::Struct(fields) => {
+                let mut rest = data;
+                let mut map = Map::new();
+                for (name, node) in fields {
+                    let (val, irest) = node.decode(rest)?;
+                    rest = irest;
+                    map.insert(name.clone(), val);
+                }
+                (Value::Object(map), rest)
+            }
+            Node::Enum(variants) => {
+                let (idx, rest) = take_len(data)?;
+                let (name, node) = variants.get(idx).ok_or(DecodeError::SchemaMismatch)?;
+                // Unit variants become strings, all others an object with one field
+                let Some(node) = node else {
+                    return Ok((Value::String(name.clone()), rest));
+                };
+                let (val, rest) = node.decode(rest)?;
+                let mut map = Map::new();
+                map.insert(name.clone(), val);
+                (Value::Object(map), rest)
+            }
+            Node::Char | Node::Schema => return Err(DecodeError::ShouldSupportButDont),
+        };
+        Ok((val, rest))
+    }
+}
+
+pub(crate) fn take_one(data: &[u8]) -> Result<(u8, &[u8]), DecodeError> {
+    data.split_first()
+        .map(|(first, rest)| (*first, rest))
+        .ok_or(DecodeError::UnexpectedEndOfData)
+}
+
+pub(crate) fn take_n(data: &[u8], n: usize) -> Result<(&[u8], &[u8]), DecodeError> {
+    if data.len() < n {
+        return Err(DecodeError::UnexpectedEndOfData);
+    }
+    Ok(data.split_at(n))
+}
+
+/// Take a varint of at most `bits` bits
+///
+/// Like postcard, this rejects varints that are longer than needed for `bits` bits,
+/// or whose last byte has more bits set than fit.
+pub(crate) fn take_varint(data: &[u8], bits: u32) -> Result<(u128, &[u8]), DecodeError> {
+    let max_len = bits.div_ceil(7);
+    let max_of_last_byte = (1u8 << (bits % 7)) - 1;
+    let mut rest = data;
+    let mut out = 0u128;
+    for i in 0..max_len {
+        let (byte, later) = take_one(rest)?;
+        rest = later;
+        out |= u128::from(byte & 0x7F) << (i * 7);
+        if byte & 0x80 == 0 {
+            if i == max_len - 1 && byte > max_of_last_byte {
+                return Err(DecodeError::SchemaMismatch);
+            }
+            return Ok((out, rest));
+        }
+    }
+    Err(DecodeError::SchemaMismatch)
+}
+
+pub(crate) fn take_len(data: &[u8]) -> Result<(usize, &[u8]), DecodeError> {
+    let (len, rest) = take_varint(data, usize::BITS)?;
+    Ok((len as usize, rest))
+}
+
+/// The most items of a zero-sized type a sequence may have
+///
+/// Items that take no bytes can't run out of data, so without a limit a crafted
+/// length would keep the decoder busy for as long as it says.
+const MAX_ZERO_SIZED_ITEMS: usize = 1024;
+
+/// Take the length of a sequence or map whose items are at least `item_size` bytes
+///
+/// Lengths that can't fit in the remaining data are rejected up front, instead of
+/// after decoding as many items as there are.
+pub(crate) fn take_seq_len(data: &[u8], item_size: usize) -> Result<(usize, &[u8]), DecodeError> {
+    let (len, rest) = take_len(data)?;
+    if item_size == 0 {
+        if len > MAX_ZERO_SIZED_ITEMS {
+            return Err(DecodeError::ShouldSupportButDont);
+        }
+    } else if len > rest.len() / item_size {
+        return Err(DecodeError::UnexpectedEndOfData);
+    }
+    Ok((len, rest))
+}
+
+pub(crate) fn take_str(data: &[u8]) -> Result<(&str, &[u8]), DecodeError> {
+    let (len, rest) = take_len(data)?;
+    let (bytes, rest) = take_n(rest, len)?;
+    let s = from_utf8(bytes).map_err(|_| DecodeError::SchemaMismatch)?;
+    Ok((s, rest))
+}
+
+pub(crate) fn zig_zag(n: u128) -> i128 {
+    ((n >> 1) as i128) ^ -((n & 1) as i128)
+}
+
+/// Floats that JSON can't represent, like NaN, are a mismatch as in `postcard_dyn`
+fn float(val: f64) -> Result<Value, DecodeError> {
+    Number::from_f64(val)
+        .map(Value::Number)
+        .ok_or(DecodeError::SchemaMismatch)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use postcard_schema::Schema;
+    use serde::Serialize;
+
+    use super::*;
+
+    #[derive(Serialize, Schema)]
+    struct Unit;
+
+    #[derive(Serialize, Schema)]
+    struct Newtype(u32);
+
+    #[derive(Serialize, Schema)]
+    struct TupleStruct(i16, Option<bool>);
+
+    #[derive(Serialize, Schema)]
+    struct Fields {
+        name: String,
+        temps: Vec<f32>,
+        pos: (i32, i32),
+    }
+
+    #[derive(Serialize, Schema)]
+    enum Kinds {
+        Unit,
+        Newtype(u8),
+        Tuple(u8, String),
+        Struct { a: i64, b: Option<u8> },
+    }
+
+    fn ty_of<T: Schema>() -> OwnedNamedType {
+        T::SCHEMA.into()
+    }
+
+    fn ty(ty: OwnedDataModelType) -> OwnedNamedType {
+        OwnedNamedType {
+            name: "test".into(),
+            ty,
+        }
+    }
+
+    /// Decode `bytes` with both decoders, and with every truncation of `bytes`
+    fn check_bytes(ty: &OwnedNamedType, bytes: &[u8]) {
+        let decoder = DynDecoder::new(ty);
+        for len in 0..=bytes.len() {
+            let data = &bytes[..len];
+            let ours = decoder.decode_inner(data).map_err(|e| format!("{e:?}"));
+            let theirs = postcard_dyn::from_slice_dyn(ty, data).map_err(|e| format!("{e:?}"));
+            assert_eq!(ours, theirs, "{} from {data:02X?}", ty.name);
+        }
+    }
+
+    fn check<T: Serialize + Schema>(val: T) {
+        check_bytes(&ty_of::<T>(), &postcard::to_stdvec(&val).unwrap());
+    }
+
+    #[test]
+    fn primitives() {
+        check(true);
+        check(false);
+        check(-5i8);
+        check(200u8);
+        check(i16::MIN);
+        check(u16::MAX);
+        check(-123_456i32);
+        check(u32::MAX);
+        check(i64::MIN);
+        check(u64::MAX);
+        check(-7i128);
+        check(i128::MAX);
+        check(9u128);
+        check(u128::MAX);
+        check(1.5f32);
+        check(f32::NAN);
+        check(-0.25f64);
+        check(f64::INFINITY);
+        check(String::from("hello"));
+        check(String::new());
+
+        // Neither has a schema implementation
+        let bytes = postcard::to_stdvec(&300usize).unwrap();
+        check_bytes(&ty(OwnedDataModelType::Usize), &bytes);
+        let bytes = postcard::to_stdvec(&-300isize).unwrap();
+        check_bytes(&ty(OwnedDataModelType::Isize), &bytes);
+        let bytes = postcard::to_stdvec(&[1u8, 2, 3][..]).unwrap();
+        check_bytes(&ty(OwnedDataModelType::ByteArray), &bytes);
+    }
+
+    #[test]
+    fn wrappers() {
+        check(Some(3u8));
+        check(None::<u8>);
+        check(Some(Some(())));
+        check(());
+        check(Unit);
+        check(Newtype(77));
+        check((5u8,));
+        check((1u8, String::from("two"), false));
+        check(TupleStruct(-2, Some(true)));
+        check(vec![1u16, 300, u16::MAX]);
+        check(Vec::<String>::new());
+    }
+
+    #[test]
+    fn maps_and_structs() {
+        let mut map = BTreeMap::new();
+        map.insert(String::from("b"), 2u32);
+        map.insert(String::from("a"), 1u32);
+        check(map);
+
+        // Only string keys can be decoded
+        let mut map = BTreeMap::new();
+        map.insert(1u8, 2u8);
+        check(map);
+
+        check(Fields {
+            name: "probe".into(),
+            temps: vec![20.5, -3.0],
+            pos: (-1, 1),
+        });
+    }
+
+    #[test]
+    fn enums() {
+        check(Kinds::Unit);
+        check(Kinds::Newtype(4));
+        check(Kinds::Tuple(4, "x".into()));
+        check(Kinds::Struct { a: -9, b: None });
+        check(vec![Kinds::Unit, Kinds::Newtype(1)]);
+
+        // A variant that doesn't exist
+        check_bytes(&ty_of::<Kinds>(), &[4]);
+    }
+
+    #[test]
+    fn invalid_data() {
+        // Bools and options only allow 0 and 1
+        check_bytes(&ty_of::<bool>(), &[2]);
+        check_bytes(&ty_of::<Option<u8>>(), &[2, 0]);
+        // Not UTF-8
+        check_bytes(&ty_of::<String>(), &[2, 0xC3, 0x28]);
+        // A length longer than the data
+        check_bytes(&ty_of::<Vec<u8>>(), &[5, 1, 2]);
+        check_bytes(&ty_of::<Vec<(u8, u16)>>(), &[3, 1, 2, 3, 4, 5]);
+        check_bytes(&ty_of::<Vec<f64>>(), &[2, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        check_bytes(&ty_of::<BTreeMap<String, u8>>(), &[2, 1, b'a', 1]);
+    }
+
+    #[test]
+    fn huge_lengths() {
+        // postcard_dyn would decode items until it runs out of data
+        let len = postcard::to_stdvec(&usize::MAX).unwrap();
+        let decoder = DynDecoder::new(&ty_of::<Vec<u32>>());
+        assert_eq!(
+            decoder.decode_inner(&len),
+            Err(DecodeError::UnexpectedEndOfData)
+        );
+
+        // ... and zero-sized items never run out, so it would spin instead
+        let decoder = DynDecoder::new(&ty_of::<Vec<((), Unit)>>());
+        assert_eq!(
+            decoder.decode_inner(&len),
+            Err(DecodeError::ShouldSupportButDont)
+        );
+        let bytes = postcard::to_stdvec(&vec![(); MAX_ZERO_SIZED_ITEMS]).unwrap();
+        check_bytes(&ty_of::<Vec<()>>(), &bytes);
+    }
+
+    #[test]
+    fn over_long_varints() {
+        // The last byte has more bits than fit
+        check_bytes(&ty_of::<u16>(), &[0xFF, 0xFF, 0x04]);
+        check_bytes(&ty_of::<u32>(), &[0xFF, 0xFF, 0xFF, 0xFF, 0x10]);
+        check_bytes(&ty_of::<u64>(), &[[0xFF; 9].as_slice(), &[0x02]].concat());
+        check_bytes(&ty_of::<u128>(), &[[0xFF; 18].as_slice(), &[0x04]].concat());
+        check_bytes(&ty_of::<i128>(), &[[0xFF; 18].as_slice(), &[0x04]].concat());
+        // More bytes than the type can need
+        check_bytes(&ty_of::<u16>(), &[0x80, 0x80, 0x80, 0x00]);
+        check_bytes(&ty_of::<u128>(), &[[0x80; 19].as_slice(), &[0x00]].concat());
+        check_bytes(&ty_of::<String>(), &[0x80; 10]);
+        // A largest valid value, for comparison
+        check_bytes(&ty_of::<u16>(), &[0xFF, 0xFF, 0x03]);
+    }
+
+    #[test]
+    fn unsupported_types() {
+        // postcard_dyn panics on these
+        let decoder = DynDecoder::new(&ty_of::<char>());
+        let res = decoder.decode_inner(&postcard::to_stdvec(&'x').unwrap());
+        assert_eq!(res, Err(DecodeError::ShouldSupportButDont));
+
+        let decoder = DynDecoder::new(&ty(OwnedDataModelType::Schema));
+        assert_eq!(
+            decoder.decode_inner(&[]),
+            Err(DecodeError::ShouldSupportButDont)
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//! Streaming all, some, or all matching, topics of a device at once
+
+use std::collections::HashMap;
+
+use postcard_dyn::Value;
+use postcard_rpc::host_client::{MultiSubRxError, MultiSubscription, SchemaReport, TopicReport};
+use poststation_api_icd::postsock::{
+    SchemaChange, SchemaChangedTopic, StartPatternStreamEndpoint, SubscribeTopic,
+    TopicPatternRequest, TopicPatternResult, TopicStreamMsg, Uuidv7,
+};
+use serde::de::DeserializeOwned;
+
+use crate::{ClientError, DeviceResult, DynDecoder, PoststationClient, SchemaReportExt};
+
+/// A merged stream of multiple "topic-out" paths of one device
+///
+/// Created by [`PoststationClient::stream_all_topics`], [`PoststationClient::stream_topics`]
+/// or [`PoststationClient::stream_topic_pattern`].
+///
+/// The messages of all paths are received through a single subscription, so they
+/// are yielded in the order they arrived, without favoring any path. All streams
+/// are stopped when the listener is dropped.
+pub struct DeviceTopicsListener {
+    client: PoststationClient,
+    serial: u64,
+    sub: MultiSubscription<TopicStreamMsg>,
+    changes: Option<MultiSubscription<SchemaChange>>,
+    /// The topic of each stream, used to decode its messages
+    streams: HashMap<Uuidv7, Stream>,
+    /// The paths to stream, or `None` for all paths
+    only: Option<Vec<String>>,
+}
+
+enum Event {
+    Msg(TopicStreamMsg),
+    Change(SchemaChange),
+}
+
+impl DeviceTopicsListener {
+    pub(crate) async fn start(
+        client: PoststationClient,
+        serial: u64,
+        follow_changes: bool,
+        only: Option<Vec<String>>,
+    ) -> Result<Self, ClientError> {
+        // Subscribe before starting any streams, so no messages can be missed
+        let raw = client.raw_client();
+        let sub = raw
+            .subscribe_multi::<SubscribeTopic>(64)
+            .await
+            .map_err(|_| ClientError::ConnectionClosed)?;
+        let changes = if follow_changes {
+            let sub = raw
+                .subscribe_multi::<SchemaChangedTopic>(8)
+                .await
+                .map_err(|_| ClientError::ConnectionClosed)?;
+            Some(sub)
+        } else {
+            None
+        };
+
+        let DeviceResult::Known(schemas) = client.get_device_schemas(serial).await? else {
+            return Err(ClientError::Server("No Device Known".into()));
+        };
+
+        let topics = match &only {
+            Some(paths) => {
+                let mut topics = Vec::with_capacity(paths.len());
+                for path in paths {
+                    let Some(topic) = schemas.topic_out_by_path(path) else {
+                        return Err(ClientError::Server("topic not found".into()));
+                    };
+                    topics.push(topic.clone());
+                }
+                topics
+            }
+            None => schemas.topics_out,
+        };
+
+        let mut this = Self {
+            client,
+            serial,
+            sub,
+            changes,
+            streams: HashMap::new(),
+            only,
+        };
+        for topic in topics {
+            let stream_id = this
+                .client
+                .start_stream(serial, &topic.path, topic.key)
+                .await?;
+            this.streams.insert(stream_id, Stream::new(topic));
+        }
+        Ok(this)
+    }
+
+    /// Start one stream on the server for all paths matching `pattern`
+    pub(crate) async fn start_pattern(
+        client: PoststationClient,
+        serial: u64,
+        pattern: &str,
+    ) -> Result<Self, ClientError> {
+        let sub = client
+            .raw_client()
+            .subscribe_multi::<SubscribeTopic>(64)
+            .await
+            .map_err(|_| ClientError::ConnectionClosed)?;
+
+        let DeviceResult::Known(schemas) = client.get_device_schemas(serial).await? else {
+            return Err(ClientError::Server("No Device Known".into()));
+        };
+
+        let req = TopicPatternRequest::new(serial, pattern.to_string());
+        let res = client.send_resp::<StartPatternStreamEndpoint>(&req).await?;
+        let started = match res {
+            TopicPatternResult::Started(started) => started,
+            TopicPatternResult::NoDeviceKnown => {
+                return Err(ClientError::Server("No Device Known".into()))
+            }
+            TopicPatternResult::DeviceDisconnected => {
+                return Err(ClientError::Server("Device Disconnected".into()))
+            }
+        };
+
+        if let Err(e) = client.stream_stopper.reserve(started.len()) {
+            for stream in started {
+                client.stream_stopper.stop_uncounted(stream.stream_id);
+            }
+            return Err(e);
+        }
+
+        // find key, skipping paths the schema does not agree with, as they can't be decoded
+        let mut streams = HashMap::new();
+        for stream in started {
+            let topic = schemas.topic_out_by_path(&stream.path);
+            match topic {
+                Some(topic) if topic.key == stream.key => {
+                    streams.insert(stream.stream_id, Stream::new(topic.clone()));
+                }
+                _ => {
+                    tracing::warn!(serial, path = stream.path, "Skipping unknown topic");
+                    client.stop_stream(stream.stream_id);
+                }
+            }
+        }
+
+        Ok(Self {
+            client,
+            serial,
+            sub,
+            changes: None,
+            streams,
+            only: None,
+        })
+    }
+
+    /// Receive a single message, along with its path and ID
+    ///
+    /// Messages that can not be decoded are skipped.
+    ///
+    /// Returns None if the connection has been closed
+    pub async fn recv(&mut self) -> Option<(String, Uuidv7, Value)> {
+        loop {
+            let event = match &mut self.changes {
+                Some(changes) => tokio::select! {
+                    msg = recv_msg(&mut self.sub) => msg.map(Event::Msg),
+                    change = recv_msg(changes) => change.map(Event::Change),
+                },
+                None => recv_msg(&mut self.sub).await.map(Event::Msg),
+            };
+
+            let msg = match event? {
+                Event::Msg(msg) => msg,
+                Event::Change(change) => {
+                    if change.serial == self.serial {
+                        self.update_schema(change.schema).await;
+                    }
+                    continue;
+                }
+            };
+
+            let Some(Stream { topic, decoder }) = self.streams.get(&msg.stream_id) else {
+                continue;
+            };
+            match decoder.decode_inner(&msg.msg) {
+                Ok(val) => return Some((topic.path.clone(), msg.uuidv7, val)),
+                Err(e) => {
+                    tracing::warn!(
+                        serial = self.serial,
+                        path = topic.path,
+                        error = ?e,
+                        "Discarding undecodable message"
+                    );
+                }
+            }
+        }
+    }
+
+    /// The paths currently being streamed
+    pub fn paths(&self) -> Vec<&str> {
+        self.streams
+            .values()
+            .map(|s| s.topic.path.as_str())
+            .collect()
+    }
+
+    /// Start streaming paths that are new, or whose key has changed
+    async fn update_schema(&mut self, report: SchemaReport) {
+        for topic in report.topics_out {
+            if let Some(only) = &self.only {
+                if !only.contains(&topic.path) {
+                    continue;
+                }
+            }
+            let existing = self
+                .streams
+                .values_mut()
+                .find(|s| s.topic.path == topic.path);
+            if let Some(existing) = existing {
+                if existing.topic.key == topic.key {
+                    *existing = Stream::new(topic);
+                    continue;
+                }
+            }
+
+            match self
+                .client
+                .start_stream(self.serial, &topic.path, topic.key)
+                .await
+            {
+                Ok(stream_id) => {
+                    let client = &self.client;
+                    self.streams.retain(|id, s| {
+                        let replaced = s.topic.path == topic.path;
+                        if replaced {
+                            client.stop_stream(*id);
+                        }
+                        !replaced
+                    });
+                    self.streams.insert(stream_id, Stream::new(topic));
+                }
+                Err(e) => {
+                    tracing::warn!(serial = self.serial, path = topic.path, error = ?e, "Failed to start stream");
+                }
+            }
+        }
+    }
+}
+
+/// A stream of one topic, with the decoder prepared from its schema
+struct Stream {
+    topic: TopicReport,
+    decoder: DynDecoder,
+}
+
+impl Stream {
+    fn new(topic: TopicReport) -> Self {
+        let decoder = DynDecoder::new(&topic.ty);
+        Self { topic, decoder }
+    }
+}
+
+impl Drop for DeviceTopicsListener {
+    fn drop(&mut self) {
+        for stream_id in self.streams.keys() {
+            self.client.stop_stream(*stream_id);
+        }
+    }
+}
+
+/// Receive the next message of a subscription, or None if it has been closed
+async fn recv_msg<T: DeserializeOwned>(sub: &mut MultiSubscription<T>) -> Option<T> {
+    loop {
+        match sub.recv().await {
+            Ok(m) => return Some(m),
+            Err(MultiSubRxError::IoClosed) => return None,
+            Err(MultiSubRxError::Lagged(n)) => {
+                tracing::warn!(lags = n, "Topic stream lagged");
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Notifications of devices connecting to and disconnecting from poststation
+
+use std::{collections::HashSet, future::Future};
+
+use postcard_rpc::host_client::{MultiSubRxError, MultiSubscription};
+use poststation_api_icd::postsock::{DeviceEvent, DeviceEventStreamMsg, Uuidv7};
+use tokio::task::AbortHandle;
+
+use crate::stop::StopOnDrop;
+
+/// A subscription to the connection events of devices
+///
+/// Created by [`PoststationClient::subscribe_device_events`](crate::PoststationClient::subscribe_device_events)
+/// or [`PoststationClient::subscribe_device_events_filtered`](crate::PoststationClient::subscribe_device_events_filtered).
+pub struct DeviceEventListener {
+    source: EventSource,
+}
+
+enum EventSource {
+    /// The events of all devices, only returning those of `serials` if set
+    All {
+        sub: MultiSubscription<DeviceEvent>,
+        serials: Option<HashSet<u64>>,
+    },
+    /// The events of a stream filtered by the server
+    Stream {
+        sub: MultiSubscription<DeviceEventStreamMsg>,
+        stream_id: Uuidv7,
+        _stop: StopOnDrop,
+    },
+}
+
+impl DeviceEventListener {
+    pub(crate) fn all(sub: MultiSubscription<DeviceEvent>, serials: Option<HashSet<u64>>) -> Self {
+        Self {
+            source: EventSource::All { sub, serials },
+        }
+    }
+
+    pub(crate) fn stream(
+        sub: MultiSubscription<DeviceEventStreamMsg>,
+        stream_id: Uuidv7,
+        stop: StopOnDrop,
+    ) -> Self {
+        Self {
+            source: EventSource::Stream {
+                sub,
+                stream_id,
+                _stop: stop,
+            },
+        }
+    }
+
+    /// Receive the next device event
+    ///
+    /// Returns None if the connection has been closed
+    pub async fn recv(&mut self) -> Option<DeviceEvent> {
+        loop {
+            let res = match &mut self.source {
+                EventSource::All { sub, serials } => match sub.recv().await {
+                    Ok(ev) if serials.as_ref().is_none_or(|s| s.contains(&ev.serial())) => Ok(ev),
+                    Ok(_) => continue,
+                    Err(e) => Err(e),
+                },
+                EventSource::Stream { sub, stream_id, .. } => match sub.recv().await {
+                    Ok(msg) if msg.stream_id == *stream_id => Ok(msg.event),
+                    Ok(_) => continue,
+                    Err(e) => Err(e),
+                },
+            };
+            match res {
+                Ok(ev) => return Some(ev),
+                Err(MultiSubRxError::IoClosed) => return None,
+                Err(MultiSubRxError::Lagged(n)) => {
+                    tracing::warn!(lags = n, "Device events lagged");
+                    continue;
+                }
+            }
+        }
+    }
+}
+
+/// Keeps a registered callback alive
+///
+/// The callback is unregistered, and will no longer be called, once the guard
+/// is dropped.
+#[must_use = "the callback is unregistered when the guard is dropped"]
+pub struct CallbackGuard {
+    task: AbortHandle,
+}
+
+impl CallbackGuard {
+    pub(crate) fn spawn<F>(fut: F) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        Self {
+            task: tokio::spawn(fut).abort_handle(),
+        }
+    }
+}
+
+impl Drop for CallbackGuard {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,75 @@
+//! A record of the recent requests made by a client
+
+use std::{collections::VecDeque, sync::Mutex, time::SystemTime};
+
+use crate::ClientError;
+
+/// A request made to a device through the server
+#[derive(Debug, Clone)]
+pub struct RequestRecord {
+    pub serial: u64,
+    pub path: String,
+    /// The sequence number used for the request, if any
+    pub seq_no: Option<u32>,
+    pub kind: RequestKind,
+    /// When the request was completed
+    pub timestamp: SystemTime,
+    /// The error the request failed with, or `None` if it succeeded
+    pub error: Option<String>,
+}
+
+/// The kind of a [`RequestRecord`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestKind {
+    /// An endpoint request proxied to the device
+    Proxy,
+    /// A message published to a "topic-in" path of the device
+    Publish,
+    /// A stream of a "topic-out" path of the device was started
+    StreamStart,
+}
+
+/// A ring buffer of the most recent requests
+pub(crate) struct RequestHistory {
+    capacity: usize,
+    records: Mutex<VecDeque<RequestRecord>>,
+}
+
+impl RequestHistory {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub(crate) fn push<T>(
+        &self,
+        kind: RequestKind,
+        serial: u64,
+        path: &str,
+        seq_no: Option<u32>,
+        res: &Result<T, ClientError>,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        let record = RequestRecord {
+            serial,
+            path: path.to_string(),
+            seq_no,
+            kind,
+            timestamp: SystemTime::now(),
+            error: res.as_ref().err().map(|e| e.to_string()),
+        };
+        let mut records = self.records.lock().unwrap();
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    pub(crate) fn records(&self) -> Vec<RequestRecord> {
+        self.records.lock().unwrap().iter().cloned().collect()
+    }
+}
@@ -0,0 +1,131 @@
+//! A store of the most recent value of a set of topics
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use postcard_dyn::Value;
+use poststation_api_icd::postsock::{features, DeviceEvent, Uuidv7};
+use tokio::task::JoinHandle;
+
+use crate::{ClientError, PoststationClient};
+
+enum Event {
+    Msg(Option<Value>),
+    Device(Option<DeviceEvent>),
+}
+
+type Values = Arc<RwLock<HashMap<(u64, String), (Uuidv7, Value)>>>;
+
+/// Keeps the most recent value of a set of topics, which can be queried synchronously
+///
+/// Each tracked topic is kept up to date by a background task. If the server
+/// supports device events, the task restarts the stream when the device reconnects,
+/// otherwise values stop updating once the device disconnects. All background tasks
+/// are stopped when the store is dropped, or when the connection is closed.
+pub struct LatestValueStore {
+    client: PoststationClient,
+    values: Values,
+    tasks: HashMap<(u64, String), JoinHandle<()>>,
+}
+
+impl LatestValueStore {
+    pub fn new(client: PoststationClient) -> Self {
+        Self {
+            client,
+            values: Default::default(),
+            tasks: HashMap::new(),
+        }
+    }
+
+    /// Start tracking the given topic-out path of a device
+    ///
+    /// The store is seeded with the most recent stored message of the topic, if any.
+    /// Tracking a topic that is already tracked does nothing.
+    pub async fn track(&mut self, serial: u64, path: &str) -> Result<(), ClientError> {
+        let id = (serial, path.to_string());
+        if self.tasks.contains_key(&id) {
+            return Ok(());
+        }
+
+        let last = self
+            .client
+            .get_device_topics_out_by_path_json(serial, path, 1)
+            .await?;
+        if let Some((uuid, val)) = last.and_then(|mut l| l.pop()) {
+            self.values.write().unwrap().insert(id.clone(), (uuid, val));
+        }
+        // Restart the stream when the device reconnects, if the server can tell us
+        let mut events = match self.client.server_capabilities() {
+            Some(caps) if caps.supports(features::DEVICE_EVENTS) => {
+                Some(self.client.subscribe_device_events().await?)
+            }
+            _ => None,
+        };
+        let mut sub = self.client.stream_topic_json(serial, path).await?;
+
+        let client = self.client.clone();
+        let values = self.values.clone();
+        let task_id = id.clone();
+        let hdl = tokio::spawn(async move {
+            loop {
+                let event = match &mut events {
+                    Some(events) => tokio::select! {
+                        val = sub.recv() => Event::Msg(val),
+                        ev = events.recv() => Event::Device(ev),
+                    },
+                    None => Event::Msg(sub.recv().await),
+                };
+
+                match event {
+                    Event::Msg(Some(val)) => {
+                        let Some(uuid) = sub.last_uuid() else {
+                            continue;
+                        };
+                        values.write().unwrap().insert(task_id.clone(), (uuid, val));
+                    }
+                    Event::Device(Some(DeviceEvent::Connected(dev))) if dev.serial == task_id.0 => {
+                        match client.stream_topic_json(task_id.0, &task_id.1).await {
+                            Ok(new) => sub = new,
+                            Err(e) => {
+                                tracing::warn!(serial = task_id.0, path = task_id.1, error = ?e, "Failed to restart stream");
+                            }
+                        }
+                    }
+                    Event::Device(Some(_)) => {}
+                    // The connection has been closed
+                    Event::Msg(None) | Event::Device(None) => return,
+                }
+            }
+        });
+        self.tasks.insert(id, hdl);
+        Ok(())
+    }
+
+    /// Stop tracking the given topic-out path of a device, and forget its latest value
+    pub fn untrack(&mut self, serial: u64, path: &str) {
+        let id = (serial, path.to_string());
+        if let Some(hdl) = self.tasks.remove(&id) {
+            hdl.abort();
+        }
+        self.values.write().unwrap().remove(&id);
+    }
+
+    /// The most recent value of a tracked topic, if any has been received
+    pub fn latest(&self, serial: u64, path: &str) -> Option<(Uuidv7, Value)> {
+        self.values
+            .read()
+            .unwrap()
+            .get(&(serial, path.to_string()))
+            .cloned()
+    }
+}
+
+impl Drop for LatestValueStore {
+    fn drop(&mut self) {
+        for hdl in self.tasks.values() {
+            hdl.abort();
+        }
+    }
+}
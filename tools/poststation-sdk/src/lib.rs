@@ -1,44 +1,109 @@
 use core::fmt::Debug;
 use std::{
+    collections::{HashMap, HashSet},
     error::Error,
     fmt::Display,
     future::Future,
     marker::PhantomData,
     net::{IpAddr, Ipv4Addr, SocketAddr},
+    ops::Range,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+use coalesce::{Fetch, SchemaFetches};
 use directories::ProjectDirs;
+use history::RequestHistory;
 use postcard_dyn::Value;
 use postcard_rpc::{
+    header::VarSeqKind,
     host_client::{
-        HostClient, HostErr, MultiSubRxError, MultiSubscription, SchemaReport, TopicReport, WireRx,
-        WireSpawn, WireTx,
+        EndpointReport, HostClient, HostErr, MultiSubRxError, MultiSubscription, SchemaReport,
+        TopicReport, WireRx, WireSpawn, WireTx,
     },
-    standard_icd::{PingEndpoint, WireError, ERROR_PATH},
-    Endpoint, Topic,
+    standard_icd::{PingEndpoint, ERROR_PATH},
+    Endpoint, Key, Topic,
 };
+use postcard_schema::{schema::owned::OwnedNamedType, Schema};
 use poststation_api_icd::postsock::{
-    Anchor, DeviceData, Direction, GetDevicesEndpoint, GetLogsEndpoint, GetLogsRangeEndpoint,
-    GetSchemasEndpoint, GetTopicsEndpoint, Log, LogRangeRequest, LogRequest, ProxyEndpoint,
-    ProxyRequest, ProxyResponse, PublishEndpoint, PublishRequest, PublishResponse,
-    StartStreamEndpoint, SubscribeTopic, TopicMsg, TopicRequest, TopicStreamMsg,
-    TopicStreamRequest, TopicStreamResult, Uuidv7,
+    features, ActiveTopic, Anchor, Capabilities, DeleteLogsEndpoint, DeleteLogsRequest,
+    DeleteTopicsEndpoint, DeleteTopicsRequest, DeviceData, DeviceEvent, DeviceEventFilter,
+    DeviceEventStreamTopic, DeviceEventTopic, DeviceName, Direction, GetActiveTopicsEndpoint,
+    GetCaCertEndpoint, GetCapabilitiesEndpoint, GetDeviceSerialsEndpoint, GetDeviceTagsEndpoint,
+    GetDeviceUsbInfoEndpoint, GetDevicesByTagEndpoint, GetDevicesEndpoint, GetLogsEndpoint,
+    GetLogsRangeEndpoint, GetSchemasEndpoint, GetSchemasMultiEndpoint, GetServerTimeEndpoint,
+    GetStorageReportEndpoint, GetTopicPathsEndpoint, GetTopicRunsEndpoint, GetTopicStatsEndpoint,
+    GetTopicsEndpoint, Log, LogRangeRequest, LogRequest, ProxyChunk, ProxyChunkKind,
+    ProxyChunkTopic, ProxyEndpoint, ProxyRequest, ProxyResponse, ProxyStreamEndpoint,
+    ProxyStreamResult, PublishEndpoint, PublishRequest, PublishResponse, SchemaChange,
+    SchemaChangedTopic, SetDeviceNamesEndpoint, SetDeviceTagsEndpoint, SetNameOutcome,
+    SetNamesRequest, SetTagsRequest, StartDeviceEventStreamEndpoint, StartStreamEndpoint,
+    StatsRange, StorageReport, SubscribeTopic, TopicMsg, TopicPaths, TopicRequest, TopicStats,
+    TopicStatsRequest, TopicStatsResult, TopicStreamMsg, TopicStreamRequest, TopicStreamResult,
+    UsbInfo, Uuidv7, MAX_SCHEMAS_PER_REQUEST,
 };
+use replay::ResponseRecorder;
 use rustls::{
     pki_types::{pem::PemObject, CertificateDer, ServerName},
     RootCertStore,
 };
 use serde::{de::DeserializeOwned, Serialize};
+use stop::{StopOnDrop, StreamStopper};
 use tokio::{
     io::{split, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf},
     net::TcpStream,
+    sync::{watch, Notify, Semaphore},
+    task::JoinSet,
+    time::error::Elapsed,
 };
 
+pub use postcard_rpc::standard_icd::WireError;
 pub use postcard_schema as schema;
 pub use poststation_api_icd as icd;
+pub use poststation_api_icd::rest::DeviceSort;
+pub use rustls;
+
+#[cfg(feature = "rest-client")]
+pub mod rest;
+
+mod bridge;
+mod coalesce;
+mod decode;
+mod device_topics;
+mod events;
+mod history;
+mod latest;
+mod log_kv;
+mod log_replay;
+mod multi;
+mod rate;
+mod replay;
+mod report;
+mod shared;
+mod stop;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+mod transcode;
+
+pub use bridge::BridgeHandle;
+pub use decode::DynDecoder;
+pub use device_topics::DeviceTopicsListener;
+pub use events::{CallbackGuard, DeviceEventListener};
+pub use history::{RequestKind, RequestRecord};
+pub use latest::LatestValueStore;
+pub use log_kv::{parse_log_kv, LogValue};
+pub use log_replay::LogReplay;
+pub use multi::MultiStreamListener;
+pub use rate::{RateLimitMode, RateLimiter};
+pub use replay::RecordedResponse;
+pub use report::{schema_fingerprint, SchemaReportExt};
+pub use shared::{SharedStream, SharedStreamReceiver, SHARED_STREAM_CAPACITY};
 use tokio_rustls::TlsConnector;
+pub use transcode::{encode_topic_raw, ValueFormat};
 
 // ---
 
@@ -55,8 +120,28 @@ pub enum ClientError {
     Server(String),
     /// An issue occurred between the remote device and the poststation server
     Remote(String),
+    /// The remote device rejected the request with a protocol error
+    ///
+    /// [`WireError::UnknownKey`] usually means that the schema used to make the
+    /// request is stale, for example because the device has been flashed with
+    /// new firmware since.
+    RemoteWire(WireError),
     /// An issue occurred with dynamic serialization/deserialization
     Dynamic(String),
+    /// The remote device did not respond before the deadline of the request
+    Timeout,
+    /// The server does not support the requested feature
+    Unsupported(String),
+    /// The request exceeded the rate limit of the device
+    RateLimited,
+    /// The device has an endpoint at this path, but with different keys
+    ///
+    /// The device runs firmware built with different types than this client. The
+    /// schema is always fetched fresh from poststation, which re-queries it when the
+    /// device reconnects, so retrying will not help until the firmware is updated.
+    SchemaMismatch { path: String },
+    /// Starting the stream would exceed [`ConnectOptions::max_streams`]
+    TooManyStreams,
 }
 
 impl From<HostErr<WireError>> for ClientError {
@@ -78,9 +163,211 @@ impl Display for ClientError {
 
 impl Error for ClientError {}
 
+/// The result of a request about a single device
+///
+/// Unlike an `Option`, this keeps a device that is not known to the server apart
+/// from a known device with an empty result, e.g. a device without any logs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceResult<T> {
+    /// The device is not known to the server
+    Unknown,
+    Known(T),
+}
+
+impl<T> DeviceResult<T> {
+    pub fn is_known(&self) -> bool {
+        matches!(self, DeviceResult::Known(_))
+    }
+
+    /// The result, or `None` if the device is not known
+    pub fn known(self) -> Option<T> {
+        match self {
+            DeviceResult::Unknown => None,
+            DeviceResult::Known(t) => Some(t),
+        }
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> DeviceResult<U> {
+        match self {
+            DeviceResult::Unknown => DeviceResult::Unknown,
+            DeviceResult::Known(t) => DeviceResult::Known(f(t)),
+        }
+    }
+
+    /// The result, or the error returned by `err` if the device is not known
+    pub fn ok_or_else<E>(self, err: impl FnOnce() -> E) -> Result<T, E> {
+        self.known().ok_or_else(err)
+    }
+}
+
+impl<T> From<Option<T>> for DeviceResult<T> {
+    /// Convert a server response where `None` means the device is not known
+    fn from(value: Option<T>) -> Self {
+        match value {
+            None => DeviceResult::Unknown,
+            Some(t) => DeviceResult::Known(t),
+        }
+    }
+}
+
+/// The maximum number of devices queried at once by [`PoststationClient::find_devices_with_endpoint`]
+/// and [`PoststationClient::find_devices_with_topic_out`]
+pub const FIND_DEVICES_CONCURRENCY: usize = 8;
+
+/// The number of logs searched by [`PoststationClient::get_device_log`]
+///
+/// Logs are searched starting from the millisecond the requested log was created
+/// in, so it is only missed if more logs were created within that millisecond.
+pub const LOG_LOOKUP_COUNT: u32 = 32;
+
+/// Sort a list of devices, e.g. as returned by [`PoststationClient::get_devices`]
+///
+/// Ties are broken by serial number, so the resulting order is stable across calls
+/// regardless of the order devices were reported by the server.
+pub fn sort_devices(devices: &mut [DeviceData], sort: DeviceSort) {
+    sort.sort(devices, |d| (&d.name, d.serial, d.is_connected));
+}
+
+/// Decode a raw, postcard encoded topic message using the given type
+///
+/// This does not need a connection to the server, so it can be used to decode
+/// previously stored messages, e.g. from [`PoststationClient::get_device_topics_out_by_path_raw`],
+/// with a saved schema while the device is offline.
+pub fn decode_topic_raw(ty: &OwnedNamedType, bytes: &[u8]) -> Result<Value, ClientError> {
+    DynDecoder::new(ty).decode(bytes)
+}
+
+/// Build a request to the endpoint at `path`, encoding the JSON `body` with the
+/// device's schema
+///
+/// The request can be sent with [`PoststationClient::proxy_raw`]. This is the first
+/// half of [`PoststationClient::proxy_endpoint_json`], for sending many requests
+/// without fetching the schema each time.
+pub fn build_proxy_request(
+    schema: &SchemaReport,
+    serial: u64,
+    path: &str,
+    seq_no: u32,
+    body: &Value,
+) -> Result<ProxyRequest, ClientError> {
+    let Some(schema) = schema.endpoint_by_path(path) else {
+        return Err(ClientError::Server("endpoint not found".into()));
+    };
+    endpoint_request(schema, serial, seq_no, body)
+}
+
+/// Build a request to an endpoint that was already looked up in the device's schema
+fn endpoint_request(
+    schema: &EndpointReport,
+    serial: u64,
+    seq_no: u32,
+    body: &Value,
+) -> Result<ProxyRequest, ClientError> {
+    let Ok(body) = postcard_dyn::to_stdvec_dyn(&schema.req_ty, body) else {
+        return Err(ClientError::Dynamic(
+            "provided JSON does not match the expected schema for this endpoint".into(),
+        ));
+    };
+    Ok(ProxyRequest::new(
+        serial,
+        schema.path.clone(),
+        schema.req_key,
+        schema.resp_key,
+        seq_no,
+        body,
+    ))
+}
+
+/// Build a publish to the "topic-in" path `path`, encoding the JSON `body` with the
+/// device's schema
+///
+/// The request can be sent with [`PoststationClient::publish_raw`], see
+/// [`build_proxy_request`].
+pub fn build_publish_request(
+    schema: &SchemaReport,
+    serial: u64,
+    path: &str,
+    seq_no: u32,
+    body: &Value,
+) -> Result<PublishRequest, ClientError> {
+    let Some(schema) = schema.topic_in_by_path(path) else {
+        return Err(ClientError::Server("topic not found".into()));
+    };
+
+    let Ok(body) = postcard_dyn::to_stdvec_dyn(&schema.ty, body) else {
+        return Err(ClientError::Dynamic(
+            "provided JSON does not match the schema for this topic".into(),
+        ));
+    };
+    Ok(PublishRequest::new(
+        serial,
+        schema.path.clone(),
+        schema.key,
+        seq_no,
+        body,
+    ))
+}
+
+/// Filter `paths` to the ones containing `partial`, prefix matches first
+fn complete_path(mut paths: Vec<String>, partial: &str) -> Vec<String> {
+    paths.retain(|p| p.contains(partial));
+    paths.sort_by(|a, b| {
+        b.starts_with(partial)
+            .cmp(&a.starts_with(partial))
+            .then_with(|| a.cmp(b))
+    });
+    paths
+}
+
+/// A connection to a poststation server
+///
+/// This is the same type however the connection was made: plain TCP with
+/// [`connect_insecure`], TLS with [`connect`] and friends, or in memory with the
+/// `test-util` feature. The transport is erased once connected, so code using a
+/// client does not need to know which one was used.
 #[derive(Clone)]
 pub struct PoststationClient {
     client: HostClient<WireError>,
+    capabilities: Option<Arc<Capabilities>>,
+    history: Option<Arc<RequestHistory>>,
+    responses: Option<Arc<ResponseRecorder>>,
+    /// The next sequence number of each device, see [`Self::proxy_endpoint_seq`]
+    seq_nos: Arc<Mutex<HashMap<u64, u32>>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    state: watch::Receiver<ConnectionState>,
+    in_flight: Arc<InFlight>,
+    /// Schema requests shared by concurrent callers, see [`Self::get_device_schemas`]
+    schema_fetches: Arc<SchemaFetches>,
+    /// See [`Self::set_trust_mode`]
+    trust_mode: Arc<AtomicBool>,
+    stream_stopper: StreamStopper,
+}
+
+/// The number of requests currently awaiting a response, shared by all clones of a
+/// client and by its I/O worker, see [`ClosedOnDrop`]
+#[derive(Default)]
+struct InFlight {
+    count: AtomicUsize,
+    /// Notified when the count drops to zero
+    idle: Notify,
+}
+
+/// Counts a request as in flight until dropped
+struct InFlightGuard(Arc<InFlight>);
+
+impl InFlightGuard {
+    fn new(in_flight: &Arc<InFlight>) -> Self {
+        in_flight.count.fetch_add(1, Ordering::Relaxed);
+        Self(in_flight.clone())
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
 }
 
 impl PoststationClient {
@@ -88,27 +375,480 @@ impl PoststationClient {
         &self.client
     }
 
+    /// The capabilities reported by the server when connecting
+    ///
+    /// This is `None` if the capabilities were not queried, e.g. for clients
+    /// created without connecting to a real server.
+    pub fn server_capabilities(&self) -> Option<&Capabilities> {
+        self.capabilities.as_deref()
+    }
+
+    /// Skip fetching the device's schema in the typed proxy and publish methods
+    ///
+    /// In trust mode, methods like [`Self::proxy_endpoint`] and [`Self::publish_topic`]
+    /// use the path and keys compiled into `E` or `T` as-is, saving a request to the
+    /// server per call. This is meant for hot loops, after checking once that the
+    /// device offers the endpoints and topics used, e.g. with
+    /// [`SchemaReportExt::endpoint_for`]. If it does not, the device rejects the
+    /// request with [`ClientError::RemoteWire`] instead.
+    ///
+    /// The mode is shared by all clones of this client.
+    pub fn set_trust_mode(&self, trust: bool) {
+        self.trust_mode.store(trust, Ordering::Relaxed);
+    }
+
+    /// Is trust mode enabled? See [`Self::set_trust_mode`]
+    pub fn trust_mode(&self) -> bool {
+        self.trust_mode.load(Ordering::Relaxed)
+    }
+
+    /// The number of requests made by this client, and its clones, that are still
+    /// awaiting a response
+    pub fn in_flight_requests(&self) -> usize {
+        self.in_flight.count.load(Ordering::Relaxed)
+    }
+
+    /// Wait until all requests made by this client, and its clones, have completed
+    ///
+    /// Requests made while waiting are also waited for. This can be used before
+    /// exiting, to make sure that everything that was published has reached poststation.
+    pub async fn flush(&self) -> Result<(), ClientError> {
+        let in_flight = &self.in_flight;
+        loop {
+            let idle = in_flight.idle.notified();
+            tokio::pin!(idle);
+            idle.as_mut().enable();
+            if in_flight.count.load(Ordering::Acquire) == 0 {
+                return Ok(());
+            }
+            tokio::select! {
+                _ = idle => {}
+                _ = self.client.wait_closed() => return Err(ClientError::ConnectionClosed),
+            }
+        }
+    }
+
+    /// Send a request to the server, counting it as in flight until it completes
+    async fn send_resp<E>(&self, req: &E::Request) -> Result<E::Response, HostErr<WireError>>
+    where
+        E: Endpoint,
+        E::Request: Serialize + Schema,
+        E::Response: DeserializeOwned + Schema,
+    {
+        let _guard = InFlightGuard::new(&self.in_flight);
+        self.client.send_resp::<E>(req).await
+    }
+
+    /// Watch the state of the connection to the server
+    ///
+    /// The state is updated as soon as the connection is lost, so this can be used
+    /// to show a connection indicator without waiting for a request to fail.
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.clone()
+    }
+
+    /// The most recent proxy, publish, and stream requests made by this client, oldest first
+    ///
+    /// This is only recorded when enabled with [`ConnectOptions::request_history`],
+    /// and is shared by all clones of this client.
+    pub fn request_history(&self) -> Vec<RequestRecord> {
+        self.history
+            .as_ref()
+            .map(|h| h.records())
+            .unwrap_or_default()
+    }
+
+    /// The last raw response of the device to a proxied request with this body
+    ///
+    /// `request` is the postcard encoded request body. Responses are only recorded
+    /// when enabled with [`ConnectOptions::record_responses`], and are shared by all
+    /// clones of this client. Requests that failed are not recorded.
+    pub fn last_response_for(&self, serial: u64, path: &str, request: &[u8]) -> Option<Vec<u8>> {
+        self.responses.as_ref()?.get(serial, path, request)
+    }
+
+    /// All responses recorded by this client, see [`Self::last_response_for`]
+    ///
+    /// The responses are ordered by serial, path, and request, so they can be saved
+    /// and later served by a test server in place of the real device.
+    pub fn recorded_responses(&self) -> Vec<RecordedResponse> {
+        self.responses
+            .as_ref()
+            .map(|r| r.records())
+            .unwrap_or_default()
+    }
+
+    fn record_response(&self, req: &ProxyRequest, resp: &[u8]) {
+        if let Some(responses) = self.responses.as_ref() {
+            responses.push(req.serial, &req.path, &req.req_body, resp);
+        }
+    }
+
+    fn record<T>(
+        &self,
+        kind: RequestKind,
+        serial: u64,
+        path: &str,
+        seq_no: Option<u32>,
+        res: &Result<T, ClientError>,
+    ) {
+        if let Some(history) = self.history.as_ref() {
+            history.push(kind, serial, path, seq_no, res);
+        }
+    }
+
+    /// Return [`ClientError::Unsupported`] if the server is known to not support `feature`
+    fn require_feature(&self, feature: &str) -> Result<(), ClientError> {
+        match self.server_capabilities() {
+            Some(caps) if !caps.supports(feature) => Err(ClientError::Unsupported(format!(
+                "Server does not support '{feature}'"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    async fn rate_limit(&self, serial: u64) -> Result<(), ClientError> {
+        match &self.rate_limiter {
+            Some(limiter) => limiter.acquire(serial).await,
+            None => Ok(()),
+        }
+    }
+
     pub async fn get_devices(&self) -> Result<Vec<DeviceData>, ClientError> {
-        Ok(self.client.send_resp::<GetDevicesEndpoint>(&()).await?)
+        Ok(self.send_resp::<GetDevicesEndpoint>(&()).await?)
+    }
+
+    /// Get the serials of all known devices, connected or not
+    ///
+    /// This transfers less than [`Self::get_devices`] when only the serials are needed,
+    /// e.g. to check that a serial is known. With servers that do not support
+    /// [`features::DEVICE_SERIALS`], the serials are taken from [`Self::get_devices`].
+    pub async fn get_device_serials(&self) -> Result<Vec<u64>, ClientError> {
+        match self.server_capabilities() {
+            Some(caps) if caps.supports(features::DEVICE_SERIALS) => {
+                Ok(self.send_resp::<GetDeviceSerialsEndpoint>(&()).await?)
+            }
+            _ => Ok(self
+                .get_devices()
+                .await?
+                .into_iter()
+                .map(|d| d.serial)
+                .collect()),
+        }
+    }
+
+    /// Get all devices with the given tag
+    pub async fn get_devices_by_tag(&self, tag: &str) -> Result<Vec<DeviceData>, ClientError> {
+        Ok(self
+            .send_resp::<GetDevicesByTagEndpoint>(&tag.to_string())
+            .await?)
+    }
+
+    /// Get the tags of a device
+    pub async fn get_device_tags(
+        &self,
+        serial: u64,
+    ) -> Result<DeviceResult<Vec<String>>, ClientError> {
+        let res = self.send_resp::<GetDeviceTagsEndpoint>(&serial).await?;
+        Ok(res.into())
+    }
+
+    /// Get the USB descriptor details of a device
+    pub async fn get_device_usb_info(
+        &self,
+        serial: u64,
+    ) -> Result<DeviceResult<UsbInfo>, ClientError> {
+        let res = self.send_resp::<GetDeviceUsbInfoEndpoint>(&serial).await?;
+        Ok(res.into())
+    }
+
+    /// Get the disk space used by the database of the server, and by each device's history
+    pub async fn get_storage_report(&self) -> Result<StorageReport, ClientError> {
+        self.require_feature(features::STORAGE_REPORT)?;
+        Ok(self.send_resp::<GetStorageReportEndpoint>(&()).await?)
+    }
+
+    /// The difference between the server's clock and the local clock, in milliseconds
+    ///
+    /// This is positive if the server's clock is ahead. Timestamps taken by the server,
+    /// such as those in log and topic message UUIDs, can be converted to the local
+    /// clock by subtracting the offset. The server is assumed to read its clock halfway
+    /// through the round trip, so the offset is accurate to within half the round trip
+    /// time.
+    pub async fn server_time_offset(&self) -> Result<i64, ClientError> {
+        self.require_feature(features::SERVER_TIME)?;
+        let start = Instant::now();
+        let sent = SystemTime::now();
+        let server_ms = self.send_resp::<GetServerTimeEndpoint>(&()).await?;
+        let local = sent + start.elapsed() / 2;
+        let local_ms = local
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        Ok(server_ms as i64 - local_ms as i64)
+    }
+
+    /// Replace the tags of a device
+    pub async fn set_device_tags(&self, serial: u64, tags: Vec<String>) -> Result<(), ClientError> {
+        let known = self
+            .send_resp::<SetDeviceTagsEndpoint>(&SetTagsRequest::new(serial, tags))
+            .await?;
+        if !known {
+            return Err(ClientError::Server("No Device Known".into()));
+        }
+        Ok(())
+    }
+
+    /// Rename a device
+    pub async fn set_device_name(&self, serial: u64, name: String) -> Result<(), ClientError> {
+        let mut res = self.set_device_names(vec![(serial, name)]).await?;
+        match res.pop() {
+            Some((_, res)) => res,
+            None => Err(ClientError::Protocol),
+        }
+    }
+
+    /// Rename several devices with a single request
+    ///
+    /// Each device is renamed independently, so some renames may fail while others
+    /// succeed. Returns the outcome of every rename, in the order of `names`.
+    pub async fn set_device_names(
+        &self,
+        names: Vec<(u64, String)>,
+    ) -> Result<Vec<(u64, Result<(), ClientError>)>, ClientError> {
+        self.require_feature(features::DEVICE_NAMES)?;
+        let names = names
+            .into_iter()
+            .map(|(serial, name)| DeviceName { serial, name })
+            .collect();
+        let res = self
+            .send_resp::<SetDeviceNamesEndpoint>(&SetNamesRequest::new(names))
+            .await?;
+        Ok(res
+            .into_iter()
+            .map(|r| {
+                let outcome = match r.outcome {
+                    SetNameOutcome::Renamed => Ok(()),
+                    SetNameOutcome::NoDeviceKnown => {
+                        Err(ClientError::Server("No Device Known".into()))
+                    }
+                    SetNameOutcome::InvalidName(e) => {
+                        Err(ClientError::Server(format!("Invalid name: {e}")))
+                    }
+                };
+                (r.serial, outcome)
+            })
+            .collect())
     }
 
+    /// Get the schema reported by a device
+    ///
+    /// The report can be converted to the JSON friendly
+    /// [`icd::rest::foreign::SchemaReport`] used by the REST API with `.into()`,
+    /// or [`SchemaReportExt::to_rest`].
+    ///
+    /// Concurrent calls for the same device, e.g. from tasks started together, share
+    /// a single request to the server.
     pub async fn get_device_schemas(
         &self,
         serial: u64,
-    ) -> Result<Option<SchemaReport>, ClientError> {
-        let res = self.client.send_resp::<GetSchemasEndpoint>(&serial).await?;
-        Ok(res)
+    ) -> Result<DeviceResult<SchemaReport>, ClientError> {
+        let lead = match self.schema_fetches.start(serial) {
+            Fetch::Lead(lead) => Some(lead),
+            Fetch::Join(rx) => match coalesce::join(rx).await {
+                Some(res) => return Ok(res.into()),
+                // The shared request failed, make our own
+                None => None,
+            },
+        };
+        let res = self.send_resp::<GetSchemasEndpoint>(&serial).await?;
+        if let Some(lead) = lead {
+            lead.finish(&res);
+        }
+        Ok(res.into())
+    }
+
+    /// Get the schemas of multiple devices at once
+    ///
+    /// Unknown devices are returned as [`DeviceResult::Unknown`]. Requests for more than
+    /// [`MAX_SCHEMAS_PER_REQUEST`] devices are split into multiple requests.
+    pub async fn get_device_schemas_multi(
+        &self,
+        serials: &[u64],
+    ) -> Result<HashMap<u64, DeviceResult<SchemaReport>>, ClientError> {
+        self.require_feature(features::SCHEMAS_MULTI)?;
+
+        let mut out = HashMap::new();
+        for chunk in serials.chunks(MAX_SCHEMAS_PER_REQUEST) {
+            let res = self
+                .send_resp::<GetSchemasMultiEndpoint>(&chunk.to_vec())
+                .await?;
+            out.extend(res.into_iter().map(|s| (s.serial, s.schema.into())));
+        }
+        Ok(out)
+    }
+
+    /// Get the paths of all topics and endpoints offered by a device
+    ///
+    /// This is a lighter weight alternative to [`Self::get_device_schemas`] when only
+    /// the paths are needed, and not the schemas of their types.
+    pub async fn get_device_topic_paths(
+        &self,
+        serial: u64,
+    ) -> Result<DeviceResult<TopicPaths>, ClientError> {
+        let res = self.send_resp::<GetTopicPathsEndpoint>(&serial).await?;
+        Ok(res.into())
+    }
+
+    /// Does the device have an endpoint matching the path and keys of `E`?
+    ///
+    /// This checks the schema without making a request to the device, e.g. to check
+    /// all interfaces an application needs up front. Returns an error if the device
+    /// is not known.
+    pub async fn supports_endpoint<E: Endpoint>(&self, serial: u64) -> Result<bool, ClientError> {
+        let schemas = self.known_schemas(serial).await?;
+        Ok(schemas.endpoint_for::<E>().is_some())
+    }
+
+    /// Does the device have a "topic-out" path matching the path and key of `T`?
+    ///
+    /// This is the check made by [`Self::stream_topic`], see [`Self::supports_endpoint`].
+    pub async fn supports_topic<T: Topic>(&self, serial: u64) -> Result<bool, ClientError> {
+        let schemas = self.known_schemas(serial).await?;
+        Ok(schemas.topic_out_for::<T>().is_some())
+    }
+
+    /// Does the device have a "topic-in" path matching the path and key of `T`?
+    ///
+    /// This is the check made by [`Self::publish_topic`], see [`Self::supports_endpoint`].
+    pub async fn supports_topic_in<T: Topic>(&self, serial: u64) -> Result<bool, ClientError> {
+        let schemas = self.known_schemas(serial).await?;
+        Ok(schemas.topic_in_for::<T>().is_some())
+    }
+
+    async fn known_schemas(&self, serial: u64) -> Result<SchemaReport, ClientError> {
+        match self.get_device_schemas(serial).await? {
+            DeviceResult::Known(schemas) => Ok(schemas),
+            DeviceResult::Unknown => Err(ClientError::Server("No Device Known".into())),
+        }
+    }
+
+    /// Get the "topic-out" paths of a device that have stored messages
+    ///
+    /// Where [`Self::get_device_topic_paths`] lists the topics a device declares, this
+    /// lists the topics poststation has actually received data on, with the number of
+    /// stored messages and the ID of the most recent one.
+    pub async fn get_active_topics(
+        &self,
+        serial: u64,
+    ) -> Result<DeviceResult<Vec<ActiveTopic>>, ClientError> {
+        self.require_feature(features::ACTIVE_TOPICS)?;
+        let res = self.send_resp::<GetActiveTopicsEndpoint>(&serial).await?;
+        Ok(res.into())
+    }
+
+    /// Get the serials of all known devices that offer the given endpoint path
+    ///
+    /// The paths of up to [`FIND_DEVICES_CONCURRENCY`] devices are requested at once.
+    /// This includes devices that are currently disconnected.
+    pub async fn find_devices_with_endpoint(&self, path: &str) -> Result<Vec<u64>, ClientError> {
+        self.find_devices_with(path, |paths| &paths.endpoints).await
+    }
+
+    /// Get the serials of all known devices that offer the given "topic-out" path
+    ///
+    /// See [`Self::find_devices_with_endpoint`] for details.
+    pub async fn find_devices_with_topic_out(&self, path: &str) -> Result<Vec<u64>, ClientError> {
+        self.find_devices_with(path, |paths| &paths.topics_out)
+            .await
+    }
+
+    async fn find_devices_with(
+        &self,
+        path: &str,
+        list: fn(&TopicPaths) -> &Vec<String>,
+    ) -> Result<Vec<u64>, ClientError> {
+        let permits = Arc::new(Semaphore::new(FIND_DEVICES_CONCURRENCY));
+        let mut tasks = JoinSet::new();
+        for serial in self.get_device_serials().await? {
+            let client = self.clone();
+            let permits = permits.clone();
+            tasks.spawn(async move {
+                let _permit = permits.acquire_owned().await;
+                let paths = client.get_device_topic_paths(serial).await?;
+                Ok::<_, ClientError>((serial, paths))
+            });
+        }
+
+        let mut serials = vec![];
+        while let Some(res) = tasks.join_next().await {
+            let (serial, paths) = res.map_err(|_| ClientError::ConnectionClosed)??;
+            if paths
+                .known()
+                .is_some_and(|p| list(&p).iter().any(|p| p == path))
+            {
+                serials.push(serial);
+            }
+        }
+        serials.sort_unstable();
+        Ok(serials)
+    }
+
+    /// Get all endpoint paths of a device matching the given partial path
+    ///
+    /// Paths starting with `partial` are listed first, followed by paths that
+    /// contain `partial` elsewhere. Returns an empty list if the device is not known.
+    pub async fn complete_endpoint_path(
+        &self,
+        serial: u64,
+        partial: &str,
+    ) -> Result<Vec<String>, ClientError> {
+        let paths = self.get_device_topic_paths(serial).await?;
+        Ok(paths
+            .known()
+            .map(|p| complete_path(p.endpoints, partial))
+            .unwrap_or_default())
+    }
+
+    /// Get all topic-in paths of a device matching the given partial path
+    ///
+    /// See [`Self::complete_endpoint_path`] for the ordering of the results.
+    pub async fn complete_topic_in_path(
+        &self,
+        serial: u64,
+        partial: &str,
+    ) -> Result<Vec<String>, ClientError> {
+        let paths = self.get_device_topic_paths(serial).await?;
+        Ok(paths
+            .known()
+            .map(|p| complete_path(p.topics_in, partial))
+            .unwrap_or_default())
+    }
+
+    /// Get all topic-out paths of a device matching the given partial path
+    ///
+    /// See [`Self::complete_endpoint_path`] for the ordering of the results.
+    pub async fn complete_topic_out_path(
+        &self,
+        serial: u64,
+        partial: &str,
+    ) -> Result<Vec<String>, ClientError> {
+        let paths = self.get_device_topic_paths(serial).await?;
+        Ok(paths
+            .known()
+            .map(|p| complete_path(p.topics_out, partial))
+            .unwrap_or_default())
     }
 
     pub async fn get_device_logs(
         &self,
         serial: u64,
         count: u32,
-    ) -> Result<Option<Vec<Log>>, ClientError> {
-        Ok(self
-            .client
-            .send_resp::<GetLogsEndpoint>(&LogRequest { serial, count })
-            .await?)
+    ) -> Result<DeviceResult<Vec<Log>>, ClientError> {
+        let res = self
+            .send_resp::<GetLogsEndpoint>(&LogRequest::new(serial, count))
+            .await?;
+        Ok(res.into())
     }
 
     pub async fn get_device_logs_range(
@@ -117,15 +857,82 @@ impl PoststationClient {
         count: u32,
         dir: Direction,
         anchor: Anchor,
-    ) -> Result<Option<Vec<Log>>, ClientError> {
+    ) -> Result<DeviceResult<Vec<Log>>, ClientError> {
+        let res = self
+            .send_resp::<GetLogsRangeEndpoint>(&LogRangeRequest::new(serial, anchor, dir, count))
+            .await?;
+        Ok(res.into())
+    }
+
+    /// Get a single log of a device by its UUID
+    ///
+    /// Returns `Known(None)` if the device has no log with this UUID.
+    pub async fn get_device_log(
+        &self,
+        serial: u64,
+        uuid: Uuidv7,
+    ) -> Result<DeviceResult<Option<Log>>, ClientError> {
+        // Ranges exclude their anchor, so search the logs from just before the
+        // timestamp of the UUID
+        let logs = self
+            .get_device_logs_range(
+                serial,
+                LOG_LOOKUP_COUNT,
+                Direction::After,
+                Anchor::UnixMsTs(uuid.unix_ms().saturating_sub(1)),
+            )
+            .await?;
+        Ok(logs.map(|logs| logs.into_iter().find(|l| l.uuidv7 == uuid)))
+    }
+
+    /// Replay the stored logs of a device after `from`, at the pace they were created
+    ///
+    /// The time between logs is divided by `speed`, so `2.0` replays twice as fast,
+    /// and [`f32::INFINITY`] replays without waiting. A `speed` that is not greater
+    /// than zero, or NaN, also replays without waiting. This allows testing consumers
+    /// of live logs against recorded history.
+    pub fn replay_device_logs(&self, serial: u64, from: Anchor, speed: f32) -> LogReplay {
+        LogReplay::new(self.clone(), serial, from, speed)
+    }
+
+    /// Delete all logs of a device stored before the given anchor
+    ///
+    /// Returns the number of deleted logs.
+    pub async fn delete_device_logs_before(
+        &self,
+        serial: u64,
+        before: Anchor,
+    ) -> Result<DeviceResult<u64>, ClientError> {
+        let res = self
+            .send_resp::<DeleteLogsEndpoint>(&DeleteLogsRequest::new(serial, before))
+            .await?;
+        Ok(res.into())
+    }
+
+    /// Delete all stored messages of a device's topic-out path before the given anchor
+    ///
+    /// Returns the number of deleted messages, or `None` if the device or topic is not known.
+    pub async fn delete_device_topic_history_before(
+        &self,
+        serial: u64,
+        path: &str,
+        before: Anchor,
+    ) -> Result<Option<u64>, ClientError> {
+        let schemas = self.get_device_schemas(serial).await?;
+        let DeviceResult::Known(schemas) = schemas else {
+            return Ok(None);
+        };
+
+        let res = schemas.topic_out_by_path(path).map(|t| t.key);
+        let Some(key) = res else { return Ok(None) };
+
         Ok(self
-            .client
-            .send_resp::<GetLogsRangeEndpoint>(&LogRangeRequest {
+            .send_resp::<DeleteTopicsEndpoint>(&DeleteTopicsRequest::new(
                 serial,
-                count,
-                anchor,
-                direction: dir,
-            })
+                path.to_string(),
+                key,
+                before,
+            ))
             .await?)
     }
 
@@ -136,62 +943,90 @@ impl PoststationClient {
         count: u32,
     ) -> Result<Option<Vec<TopicMsg>>, ClientError> {
         let schemas = self.get_device_schemas(serial).await?;
-        let Some(schemas) = schemas else {
+        let DeviceResult::Known(schemas) = schemas else {
             return Ok(None);
         };
 
-        // find key
-        let res = schemas
-            .topics_out
-            .iter()
-            .find(|t| t.path.as_str() == path)
-            .map(|t| t.key);
+        let res = schemas.topic_out_by_path(path).map(|t| t.key);
         let Some(key) = res else { return Ok(None) };
 
         Ok(self
-            .client
-            .send_resp::<GetTopicsEndpoint>(&TopicRequest {
+            .send_resp::<GetTopicsEndpoint>(&TopicRequest::new(
                 serial,
-                count,
-                path: path.to_string(),
+                path.to_string(),
                 key,
-            })
+                count,
+            ))
             .await?)
     }
 
-    pub async fn get_device_topics_out_by_path_json(
+    /// Get aggregate statistics over the stored messages of a numeric topic-out path
+    ///
+    /// The statistics are calculated by the server, and are only available for topics
+    /// where the message is a single numeric value. Returns `None` if the device or
+    /// topic is not known.
+    pub async fn get_topic_stats(
         &self,
         serial: u64,
         path: &str,
-        count: u32,
-    ) -> Result<Option<Vec<(Uuidv7, Value)>>, ClientError> {
+        range: StatsRange,
+    ) -> Result<Option<TopicStats>, ClientError> {
         let schemas = self.get_device_schemas(serial).await?;
-        let Some(schemas) = schemas else {
+        let DeviceResult::Known(schemas) = schemas else {
             return Ok(None);
         };
 
-        // find key
-        let res = schemas.topics_out.iter().find(|t| t.path.as_str() == path);
-        let Some(schema) = res else { return Ok(None) };
+        let res = schemas.topic_out_by_path(path).map(|t| t.key);
+        let Some(key) = res else { return Ok(None) };
+
+        let res = self
+            .send_resp::<GetTopicStatsEndpoint>(&TopicStatsRequest::new(
+                serial,
+                path.to_string(),
+                key,
+                range,
+            ))
+            .await?;
+
+        match res {
+            TopicStatsResult::Stats(stats) => Ok(Some(stats)),
+            TopicStatsResult::NoDeviceKnown => Ok(None),
+            TopicStatsResult::NoSuchTopic => Ok(None),
+            TopicStatsResult::NotNumeric => Err(ClientError::Server("Topic is not numeric".into())),
+        }
+    }
+
+    pub async fn get_device_topics_out_by_path_json(
+        &self,
+        serial: u64,
+        path: &str,
+        count: u32,
+    ) -> Result<Option<Vec<(Uuidv7, Value)>>, ClientError> {
+        let schemas = self.get_device_schemas(serial).await?;
+        let DeviceResult::Known(schemas) = schemas else {
+            return Ok(None);
+        };
+
+        let res = schemas.topic_out_by_path(path);
+        let Some(schema) = res else { return Ok(None) };
 
         let raws = self
-            .client
-            .send_resp::<GetTopicsEndpoint>(&TopicRequest {
+            .send_resp::<GetTopicsEndpoint>(&TopicRequest::new(
                 serial,
+                path.to_string(),
+                schema.key,
                 count,
-                path: path.to_string(),
-                key: schema.key,
-            })
+            ))
             .await?;
         let Some(raws) = raws else {
             return Ok(None);
         };
 
+        let decoder = DynDecoder::new(&schema.ty);
         let res = raws
             .into_iter()
             .map(|tm| {
-                let msg = postcard_dyn::from_slice_dyn(&schema.ty, &tm.msg)
-                    .map_err(|_| ClientError::Encoding)?;
+                let msg = decoder.decode(&tm.msg)?;
                 Result::<_, ClientError>::Ok((tm.uuidv7, msg))
             })
             .collect::<Result<Vec<_>, _>>()?;
@@ -199,6 +1034,61 @@ impl PoststationClient {
         Ok(Some(res))
     }
 
+    /// Like [`Self::get_device_topics_out_by_path_json`], but with runs of identical
+    /// consecutive messages collapsed by the server
+    ///
+    /// This saves bandwidth for topics that rarely change, such as status values. Use
+    /// [`expand_runs`] to get one value per message again.
+    pub async fn get_device_topics_out_runs(
+        &self,
+        serial: u64,
+        path: &str,
+        count: u32,
+    ) -> Result<Option<Vec<TopicValueRun>>, ClientError> {
+        self.require_feature(features::TOPIC_RUNS)?;
+        let schemas = self.get_device_schemas(serial).await?;
+        let DeviceResult::Known(schemas) = schemas else {
+            return Ok(None);
+        };
+
+        let res = schemas.topic_out_by_path(path);
+        let Some(schema) = res else { return Ok(None) };
+
+        let runs = self
+            .send_resp::<GetTopicRunsEndpoint>(&TopicRequest::new(
+                serial,
+                path.to_string(),
+                schema.key,
+                count,
+            ))
+            .await?;
+        let Some(runs) = runs else {
+            return Ok(None);
+        };
+
+        let decoder = DynDecoder::new(&schema.ty);
+        let res = runs
+            .into_iter()
+            .map(|run| {
+                Ok(TopicValueRun {
+                    value: decoder.decode(&run.msg)?,
+                    count: run.count,
+                    first_uuid: run.first_uuid,
+                    last_uuid: run.last_uuid,
+                })
+            })
+            .collect::<Result<Vec<_>, ClientError>>()?;
+
+        Ok(Some(res))
+    }
+
+    /// Send a request to an endpoint of a device, and wait for its response
+    ///
+    /// Unless [trust mode](Self::set_trust_mode) is enabled, the device's schema is
+    /// checked first. If the device has an endpoint at `E::PATH` with different keys,
+    /// this returns [`ClientError::SchemaMismatch`] right away. The schema is not
+    /// re-fetched and retried: this client keeps no schema cache, so every check
+    /// already uses the report poststation has for the device's current firmware.
     pub async fn proxy_endpoint<E>(
         &self,
         serial: u64,
@@ -210,52 +1100,195 @@ impl PoststationClient {
         E::Request: Serialize,
         E::Response: DeserializeOwned,
     {
-        let Some(schemas) = self.get_device_schemas(serial).await? else {
-            return Err(ClientError::Server("endpoint not found".into()));
-        };
+        let (resp, _raw, _timing) = self
+            .proxy_endpoint_inner::<E>(serial, seq_no, body, None)
+            .await?;
+        Ok(resp)
+    }
 
-        // find key
-        // TODO: Don't compare the types because the names don't match even though we've
-        // type-punned
-        let res = schemas.endpoints.iter().find(|e| {
-            e.path.as_str() == E::PATH && e.req_key == E::REQ_KEY && e.resp_key == E::RESP_KEY
-        });
-        let Some(schema) = res else {
-            return Err(ClientError::Server("endpoint not found".into()));
-        };
+    /// Like [`Self::proxy_endpoint`], but uses the next sequence number of the device
+    ///
+    /// Sequence numbers are counted separately for each device, starting at zero, and
+    /// shared by all clones of this client. This keeps them increasing for each device,
+    /// even when requests to many devices are interleaved.
+    pub async fn proxy_endpoint_seq<E>(
+        &self,
+        serial: u64,
+        body: &E::Request,
+    ) -> Result<E::Response, ClientError>
+    where
+        E: Endpoint,
+        E::Request: Serialize,
+        E::Response: DeserializeOwned,
+    {
+        let seq_no = self.next_seq_no(serial);
+        self.proxy_endpoint::<E>(serial, seq_no, body).await
+    }
+
+    fn next_seq_no(&self, serial: u64) -> u32 {
+        let mut seq_nos = self.seq_nos.lock().unwrap();
+        let seq_no = seq_nos.entry(serial).or_insert(0);
+        let next = *seq_no;
+        *seq_no = seq_no.wrapping_add(1);
+        next
+    }
+
+    /// Like [`Self::proxy_endpoint`], but also returns how long the request took
+    ///
+    /// See [`ProxyTiming`] for details.
+    pub async fn proxy_endpoint_timed<E>(
+        &self,
+        serial: u64,
+        seq_no: u32,
+        body: &E::Request,
+    ) -> Result<(E::Response, ProxyTiming), ClientError>
+    where
+        E: Endpoint,
+        E::Request: Serialize,
+        E::Response: DeserializeOwned,
+    {
+        let (resp, _raw, timing) = self
+            .proxy_endpoint_inner::<E>(serial, seq_no, body, None)
+            .await?;
+        Ok((resp, timing))
+    }
+
+    /// Like [`Self::proxy_endpoint`], but also returns the raw postcard encoded
+    /// bytes of the response, e.g. to record them for later replay
+    pub async fn proxy_endpoint_with_raw<E>(
+        &self,
+        serial: u64,
+        seq_no: u32,
+        body: &E::Request,
+    ) -> Result<(E::Response, Vec<u8>), ClientError>
+    where
+        E: Endpoint,
+        E::Request: Serialize,
+        E::Response: DeserializeOwned,
+    {
+        let (resp, raw, _timing) = self
+            .proxy_endpoint_inner::<E>(serial, seq_no, body, None)
+            .await?;
+        Ok((resp, raw))
+    }
+
+    /// Like [`Self::proxy_endpoint`], but the server cancels the request to the device
+    /// if it has not responded within `timeout`, returning [`ClientError::Timeout`]
+    ///
+    /// Timeouts are sent with millisecond precision, saturating at `u32::MAX` ms.
+    pub async fn proxy_endpoint_with_timeout<E>(
+        &self,
+        serial: u64,
+        seq_no: u32,
+        body: &E::Request,
+        timeout: Duration,
+    ) -> Result<E::Response, ClientError>
+    where
+        E: Endpoint,
+        E::Request: Serialize,
+        E::Response: DeserializeOwned,
+    {
+        let deadline_ms = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+        let (resp, _raw, _timing) = self
+            .proxy_endpoint_inner::<E>(serial, seq_no, body, Some(deadline_ms))
+            .await?;
+        Ok(resp)
+    }
+
+    async fn proxy_endpoint_inner<E>(
+        &self,
+        serial: u64,
+        seq_no: u32,
+        body: &E::Request,
+        deadline_ms: Option<u32>,
+    ) -> Result<(E::Response, Vec<u8>, ProxyTiming), ClientError>
+    where
+        E: Endpoint,
+        E::Request: Serialize,
+        E::Response: DeserializeOwned,
+    {
+        let res = self
+            .proxy_endpoint_typed_inner::<E>(serial, seq_no, body, deadline_ms)
+            .await;
+        self.record(RequestKind::Proxy, serial, E::PATH, Some(seq_no), &res);
+        res
+    }
+
+    /// Check the schema and send the request, see [`Self::proxy_endpoint`] for why a
+    /// mismatch is not retried
+    async fn proxy_endpoint_typed_inner<E>(
+        &self,
+        serial: u64,
+        seq_no: u32,
+        body: &E::Request,
+        deadline_ms: Option<u32>,
+    ) -> Result<(E::Response, Vec<u8>, ProxyTiming), ClientError>
+    where
+        E: Endpoint,
+        E::Request: Serialize,
+        E::Response: DeserializeOwned,
+    {
+        self.rate_limit(serial).await?;
+        if !self.trust_mode() {
+            let DeviceResult::Known(schemas) = self.get_device_schemas(serial).await? else {
+                return Err(ClientError::Server("endpoint not found".into()));
+            };
+
+            if schemas.endpoint_for::<E>().is_none() {
+                if schemas.endpoint_by_path(E::PATH).is_some() {
+                    return Err(ClientError::SchemaMismatch {
+                        path: E::PATH.to_string(),
+                    });
+                }
+                return Err(ClientError::Server("endpoint not found".into()));
+            }
+        }
         let Ok(body) = postcard::to_stdvec(body) else {
             return Err(ClientError::Encoding);
         };
 
-        let req = ProxyRequest {
+        // The keys of the schema are the same as the ones of `E`
+        let mut req = ProxyRequest::new(
             serial,
-            path: schema.path.clone(),
-            req_key: schema.req_key,
-            resp_key: schema.resp_key,
+            E::PATH.to_string(),
+            E::REQ_KEY,
+            E::RESP_KEY,
             seq_no,
-            req_body: body,
-        };
+            body,
+        );
+        req.deadline_ms = deadline_ms;
 
-        let resp = self.client.send_resp::<ProxyEndpoint>(&req).await;
+        let start = Instant::now();
+        let resp = self.send_resp::<ProxyEndpoint>(&req).await;
+        let round_trip = start.elapsed();
 
         // client to poststation comms
         let resp = resp?;
 
         // poststation to remote comms
-        let resp = match resp {
-            ProxyResponse::Ok { body, .. } => body,
-            ProxyResponse::WireErr { body, .. } => {
-                return Err(ClientError::Remote(format!("WireErr: {body:?}")))
-            }
+        let (resp, device_latency_us) = match resp {
+            ProxyResponse::Ok {
+                body,
+                device_latency_us,
+                ..
+            } => (body, device_latency_us),
+            ProxyResponse::WireErr { body, .. } => return Err(ClientError::RemoteWire(body)),
             ProxyResponse::OtherErr(e) => {
                 return Err(ClientError::Remote(format!("Other Server Err: '{e}'")))
             }
+            ProxyResponse::Timeout => return Err(ClientError::Timeout),
         };
 
-        let resp = postcard::from_bytes::<E::Response>(&resp);
+        self.record_response(&req, &resp);
+        let decoded = postcard::from_bytes::<E::Response>(&resp);
 
-        match resp {
-            Ok(v) => Ok(v),
+        let timing = ProxyTiming {
+            round_trip,
+            device: device_latency_us.map(|us| Duration::from_micros(us.into())),
+        };
+
+        match decoded {
+            Ok(v) => Ok((v, resp, timing)),
             Err(_e) => Err(ClientError::Encoding),
         }
     }
@@ -267,52 +1300,182 @@ impl PoststationClient {
         seq_no: u32,
         body: Value,
     ) -> Result<Value, ClientError> {
-        let Ok(Some(schemas)) = self.get_device_schemas(serial).await else {
+        let res = self
+            .proxy_endpoint_dyn(serial, path, seq_no, body, |ty, resp| {
+                DynDecoder::new(ty)
+                    .decode_inner(resp)
+                    .map_err(|e| ClientError::Dynamic(format!("Decode error: '{e:?}'")))
+            })
+            .await;
+        self.record(RequestKind::Proxy, serial, path, Some(seq_no), &res);
+        res
+    }
+
+    /// Like [`Self::proxy_endpoint_json`], but returns the response encoded in the
+    /// given format
+    ///
+    /// The response is encoded directly from the postcard encoded message of the
+    /// device, see [`encode_topic_raw`].
+    pub async fn proxy_endpoint_encoded(
+        &self,
+        serial: u64,
+        path: &str,
+        seq_no: u32,
+        body: Value,
+        format: ValueFormat,
+    ) -> Result<Vec<u8>, ClientError> {
+        let res = self
+            .proxy_endpoint_dyn(serial, path, seq_no, body, |ty, resp| {
+                encode_topic_raw(ty, resp, format)
+            })
+            .await;
+        self.record(RequestKind::Proxy, serial, path, Some(seq_no), &res);
+        res
+    }
+
+    /// Proxy a JSON request, converting the raw response with `decode`
+    async fn proxy_endpoint_dyn<T>(
+        &self,
+        serial: u64,
+        path: &str,
+        seq_no: u32,
+        body: Value,
+        decode: impl FnOnce(&OwnedNamedType, &[u8]) -> Result<T, ClientError>,
+    ) -> Result<T, ClientError> {
+        self.rate_limit(serial).await?;
+        let DeviceResult::Known(schemas) = self.get_device_schemas(serial).await? else {
             return Err(ClientError::Server("endpoint not found".into()));
         };
-
-        // find key
-        let res = schemas.endpoints.iter().find(|e| e.path.as_str() == path);
-        let Some(schema) = res else {
+        let Some(schema) = schemas.endpoint_by_path(path) else {
             return Err(ClientError::Server("endpoint not found".into()));
         };
+        let req = endpoint_request(schema, serial, seq_no, &body)?;
+        let resp = self.send_proxy_request(&req).await?;
+        decode(&schema.resp_ty, &resp)
+    }
 
-        let Ok(body) = postcard_dyn::to_stdvec_dyn(&schema.req_ty, &body) else {
-            return Err(ClientError::Dynamic(
-                "provided JSON does not match the expected schema for this endpoint".into(),
-            ));
-        };
-        let req = ProxyRequest {
-            serial,
-            path: schema.path.clone(),
-            req_key: schema.req_key,
-            resp_key: schema.resp_key,
-            seq_no,
-            req_body: body,
-        };
+    /// Send a request built with [`build_proxy_request`], returning the raw,
+    /// postcard encoded response
+    pub async fn proxy_raw(&self, req: &ProxyRequest) -> Result<Vec<u8>, ClientError> {
+        let res = async {
+            self.rate_limit(req.serial).await?;
+            self.send_proxy_request(req).await
+        }
+        .await;
+        self.record(
+            RequestKind::Proxy,
+            req.serial,
+            &req.path,
+            Some(req.seq_no),
+            &res,
+        );
+        res
+    }
 
-        let resp = self.client.send_resp::<ProxyEndpoint>(&req).await;
+    async fn send_proxy_request(&self, req: &ProxyRequest) -> Result<Vec<u8>, ClientError> {
+        let resp = self.send_resp::<ProxyEndpoint>(req).await;
 
         // client to poststation comms
         let resp = resp?;
 
         // poststation to remote comms
-        let resp = match resp {
-            ProxyResponse::Ok { body, .. } => body,
-            ProxyResponse::WireErr { body, .. } => {
-                return Err(ClientError::Remote(format!("WireErr: {body:?}")))
+        match resp {
+            ProxyResponse::Ok { body, .. } => {
+                self.record_response(req, &body);
+                Ok(body)
             }
+            ProxyResponse::WireErr { body, .. } => Err(ClientError::RemoteWire(body)),
             ProxyResponse::OtherErr(e) => {
-                return Err(ClientError::Remote(format!("Other Server Err: '{e}'")))
+                Err(ClientError::Remote(format!("Other Server Err: '{e}'")))
             }
+            ProxyResponse::Timeout => Err(ClientError::Timeout),
+        }
+    }
+
+    /// Proxy a request to a device, receiving the response in chunks
+    ///
+    /// Unlike [`Self::proxy_endpoint`], the response is not limited to the size of
+    /// a single frame, which makes this suitable for endpoints with large responses.
+    pub async fn proxy_endpoint_streaming<E>(
+        &self,
+        serial: u64,
+        seq_no: u32,
+        body: &E::Request,
+    ) -> Result<ProxyStreamListener<E>, ClientError>
+    where
+        E: Endpoint,
+        E::Request: Serialize,
+        E::Response: DeserializeOwned,
+    {
+        let res = self
+            .proxy_endpoint_streaming_inner::<E>(serial, seq_no, body)
+            .await;
+        self.record(RequestKind::Proxy, serial, E::PATH, Some(seq_no), &res);
+        res
+    }
+
+    async fn proxy_endpoint_streaming_inner<E>(
+        &self,
+        serial: u64,
+        seq_no: u32,
+        body: &E::Request,
+    ) -> Result<ProxyStreamListener<E>, ClientError>
+    where
+        E: Endpoint,
+        E::Request: Serialize,
+        E::Response: DeserializeOwned,
+    {
+        self.require_feature(features::PROXY_STREAM)?;
+        self.rate_limit(serial).await?;
+
+        let DeviceResult::Known(schemas) = self.get_device_schemas(serial).await? else {
+            return Err(ClientError::Server("endpoint not found".into()));
         };
 
-        let resp = postcard_dyn::from_slice_dyn(&schema.resp_ty, &resp);
+        let res = schemas.endpoint_for::<E>();
+        let Some(schema) = res else {
+            return Err(ClientError::Server("endpoint not found".into()));
+        };
+        let Ok(body) = postcard::to_stdvec(body) else {
+            return Err(ClientError::Encoding);
+        };
 
-        match resp {
-            Ok(v) => Ok(v),
-            Err(e) => Err(ClientError::Dynamic(format!("Decode error: '{e:?}'"))),
-        }
+        let sub = self
+            .client
+            .subscribe_multi::<ProxyChunkTopic>(64)
+            .await
+            .map_err(|_| ClientError::ConnectionClosed)?;
+
+        let req = ProxyRequest::new(
+            serial,
+            schema.path.clone(),
+            schema.req_key,
+            schema.resp_key,
+            seq_no,
+            body,
+        );
+        let res = self.send_resp::<ProxyStreamEndpoint>(&req).await;
+
+        let stream_id = match res? {
+            ProxyStreamResult::Started(id) => id,
+            ProxyStreamResult::DeviceDisconnected => {
+                return Err(ClientError::Server("Device Disconnected".into()))
+            }
+            ProxyStreamResult::NoDeviceKnown => {
+                return Err(ClientError::Server("No Device Known".into()))
+            }
+            ProxyStreamResult::NoSuchEndpoint => {
+                return Err(ClientError::Server("No Such Endpoint".into()))
+            }
+        };
+
+        Ok(ProxyStreamListener {
+            stream_id,
+            sub,
+            next_index: 0,
+            done: false,
+            _pd: PhantomData,
+        })
     }
 
     pub async fn publish_topic_json(
@@ -322,30 +1485,47 @@ impl PoststationClient {
         seq_no: u32,
         body: Value,
     ) -> Result<(), ClientError> {
-        let Ok(Some(schemas)) = self.get_device_schemas(serial).await else {
-            return Err(ClientError::Server("topic not found".into()));
-        };
+        let res = self
+            .publish_topic_json_inner(serial, path, seq_no, body)
+            .await;
+        self.record(RequestKind::Publish, serial, path, Some(seq_no), &res);
+        res
+    }
 
-        // find key
-        let res = schemas.topics_in.iter().find(|e| e.path.as_str() == path);
-        let Some(schema) = res else {
+    async fn publish_topic_json_inner(
+        &self,
+        serial: u64,
+        path: &str,
+        seq_no: u32,
+        body: Value,
+    ) -> Result<(), ClientError> {
+        self.rate_limit(serial).await?;
+        let DeviceResult::Known(schemas) = self.get_device_schemas(serial).await? else {
             return Err(ClientError::Server("topic not found".into()));
         };
+        let req = build_publish_request(&schemas, serial, path, seq_no, &body)?;
+        self.send_publish_request(&req).await
+    }
 
-        let Ok(body) = postcard_dyn::to_stdvec_dyn(&schema.ty, &body) else {
-            return Err(ClientError::Dynamic(
-                "provided JSON does not match the schema for this topic".into(),
-            ));
-        };
-        let req = PublishRequest {
-            serial,
-            path: schema.path.clone(),
-            topic_key: schema.key,
-            seq_no,
-            topic_body: body,
-        };
+    /// Send a publish built with [`build_publish_request`]
+    pub async fn publish_raw(&self, req: &PublishRequest) -> Result<(), ClientError> {
+        let res = async {
+            self.rate_limit(req.serial).await?;
+            self.send_publish_request(req).await
+        }
+        .await;
+        self.record(
+            RequestKind::Publish,
+            req.serial,
+            &req.path,
+            Some(req.seq_no),
+            &res,
+        );
+        res
+    }
 
-        let resp = self.client.send_resp::<PublishEndpoint>(&req).await;
+    async fn send_publish_request(&self, req: &PublishRequest) -> Result<(), ClientError> {
+        let resp = self.send_resp::<PublishEndpoint>(req).await;
 
         let resp = resp?;
 
@@ -365,40 +1545,206 @@ impl PoststationClient {
         T: Topic,
         T::Message: Serialize,
     {
-        let Ok(Some(schemas)) = self.get_device_schemas(serial).await else {
-            return Err(ClientError::Server("topic not found".into()));
-        };
-
-        // find key
-        // TODO: Don't compare the types because the names don't match even though we've
-        // type-punned
-        let res = schemas
-            .topics_in
-            .iter()
-            .find(|t| t.path.as_str() == T::PATH && t.key == T::TOPIC_KEY);
-        let Some(schema) = res else {
-            return Err(ClientError::Server("topic not found".into()));
-        };
+        let res = self.publish_topic_inner::<T>(serial, seq_no, body).await;
+        self.record(RequestKind::Publish, serial, T::PATH, Some(seq_no), &res);
+        res
+    }
 
-        let Ok(body) = postcard::to_stdvec(body) else {
-            return Err(ClientError::Encoding);
-        };
-        let req = PublishRequest {
-            serial,
-            path: schema.path.clone(),
-            topic_key: schema.key,
-            seq_no,
-            topic_body: body,
+    async fn publish_topic_inner<T>(
+        &self,
+        serial: u64,
+        seq_no: u32,
+        body: &T::Message,
+    ) -> Result<(), ClientError>
+    where
+        T: Topic,
+        T::Message: Serialize,
+    {
+        self.rate_limit(serial).await?;
+        if !self.trust_mode() {
+            let DeviceResult::Known(schemas) = self.get_device_schemas(serial).await? else {
+                return Err(ClientError::Server("topic not found".into()));
+            };
+
+            if schemas.topic_in_for::<T>().is_none() {
+                return Err(ClientError::Server("topic not found".into()));
+            }
+        }
+
+        let Ok(body) = postcard::to_stdvec(body) else {
+            return Err(ClientError::Encoding);
         };
+        // The key of the schema is the same as the one of `T`
+        let req = PublishRequest::new(serial, T::PATH.to_string(), T::TOPIC_KEY, seq_no, body);
+        self.send_publish_request(&req).await
+    }
 
-        let resp = self.client.send_resp::<PublishEndpoint>(&req).await;
+    /// Listen for changes to the schema of a given device
+    ///
+    /// Poststation re-queries the schema of a device whenever it reconnects, and notifies
+    /// clients when it has changed, for example after the device was flashed with new firmware.
+    pub async fn subscribe_schema_changes(
+        &self,
+        serial: u64,
+    ) -> Result<SchemaChangeListener, ClientError> {
+        let sub = self
+            .client
+            .subscribe_multi::<SchemaChangedTopic>(8)
+            .await
+            .map_err(|_| ClientError::ConnectionClosed)?;
 
-        let resp = resp?;
+        Ok(SchemaChangeListener { serial, sub })
+    }
 
-        match resp {
-            PublishResponse::Sent => Ok(()),
-            PublishResponse::OtherErr(e) => Err(ClientError::Server(e)),
+    /// Listen for devices connecting to and disconnecting from poststation
+    pub async fn subscribe_device_events(&self) -> Result<DeviceEventListener, ClientError> {
+        self.require_feature(features::DEVICE_EVENTS)?;
+        let sub = self
+            .client
+            .subscribe_multi::<DeviceEventTopic>(16)
+            .await
+            .map_err(|_| ClientError::ConnectionClosed)?;
+
+        Ok(DeviceEventListener::all(sub, None))
+    }
+
+    /// Like [`Self::subscribe_device_events`], only receiving the events of devices
+    /// matching `filter`
+    ///
+    /// The filter is applied by the server if it supports it. Otherwise all events are
+    /// received and filtered here, and the `tags` are resolved to the devices that have
+    /// them when subscribing, so devices tagged later are not included.
+    pub async fn subscribe_device_events_filtered(
+        &self,
+        filter: DeviceEventFilter,
+    ) -> Result<DeviceEventListener, ClientError> {
+        if self.require_feature(features::DEVICE_EVENT_FILTER).is_err() {
+            self.require_feature(features::DEVICE_EVENTS)?;
+            let serials = self.resolve_event_filter(filter).await?;
+            let sub = self
+                .client
+                .subscribe_multi::<DeviceEventTopic>(16)
+                .await
+                .map_err(|_| ClientError::ConnectionClosed)?;
+            return Ok(DeviceEventListener::all(sub, serials));
+        }
+
+        let sub = self
+            .client
+            .subscribe_multi::<DeviceEventStreamTopic>(16)
+            .await
+            .map_err(|_| ClientError::ConnectionClosed)?;
+        self.stream_stopper.reserve(1)?;
+        let stream_id = match self
+            .send_resp::<StartDeviceEventStreamEndpoint>(&filter)
+            .await
+        {
+            Ok(id) => id,
+            Err(e) => {
+                self.stream_stopper.release(1);
+                return Err(e.into());
+            }
+        };
+
+        Ok(DeviceEventListener::stream(
+            sub,
+            stream_id,
+            self.stream_stopper.guard(stream_id),
+        ))
+    }
+
+    /// The serials matching a filter, or None if it matches all devices
+    async fn resolve_event_filter(
+        &self,
+        filter: DeviceEventFilter,
+    ) -> Result<Option<HashSet<u64>>, ClientError> {
+        let Some(tags) = filter.tags else {
+            return Ok(filter.serials);
+        };
+        let mut tagged = HashSet::new();
+        for tag in &tags {
+            let devices = self.get_devices_by_tag(tag).await?;
+            tagged.extend(devices.into_iter().map(|d| d.serial));
         }
+        Ok(Some(match filter.serials {
+            Some(serials) => &serials & &tagged,
+            None => tagged,
+        }))
+    }
+
+    /// Call `f` with the details of every device that connects to poststation
+    ///
+    /// The callback is called from a background task, until the returned guard is
+    /// dropped or the connection is closed.
+    pub async fn on_device_connected<F>(&self, f: F) -> Result<CallbackGuard, ClientError>
+    where
+        F: Fn(DeviceData) + Send + Sync + 'static,
+    {
+        let mut events = self.subscribe_device_events().await?;
+        Ok(CallbackGuard::spawn(async move {
+            while let Some(ev) = events.recv().await {
+                if let DeviceEvent::Connected(dev) = ev {
+                    f(dev);
+                }
+            }
+        }))
+    }
+
+    /// Call `f` with the serial of every device that disconnects from poststation
+    ///
+    /// The callback is called from a background task, until the returned guard is
+    /// dropped or the connection is closed.
+    pub async fn on_device_disconnected<F>(&self, f: F) -> Result<CallbackGuard, ClientError>
+    where
+        F: Fn(u64) + Send + Sync + 'static,
+    {
+        let mut events = self.subscribe_device_events().await?;
+        Ok(CallbackGuard::spawn(async move {
+            while let Some(ev) = events.recv().await {
+                if let DeviceEvent::Disconnected(serial) = ev {
+                    f(serial);
+                }
+            }
+        }))
+    }
+
+    /// Wait until the given device is connected to poststation
+    ///
+    /// Resolves immediately if the device is already connected, and returns
+    /// [`ClientError::Timeout`] if it has not connected within `timeout`.
+    ///
+    /// Waiting relies on device events, so if the device is not connected yet, this
+    /// returns [`ClientError::Unsupported`] if the server does not support them.
+    pub async fn wait_for_device(
+        &self,
+        serial: u64,
+        timeout: Duration,
+    ) -> Result<DeviceData, ClientError> {
+        let connected = |devices: Vec<DeviceData>| {
+            devices
+                .into_iter()
+                .find(|d| d.serial == serial && d.is_connected)
+        };
+        let wait = async {
+            if let Some(dev) = connected(self.get_devices().await?) {
+                return Ok(dev);
+            }
+            let mut events = self.subscribe_device_events().await?;
+            // Check again, in case the device connected before the subscription started
+            if let Some(dev) = connected(self.get_devices().await?) {
+                return Ok(dev);
+            }
+            while let Some(ev) = events.recv().await {
+                match ev {
+                    DeviceEvent::Connected(dev) if dev.serial == serial => return Ok(dev),
+                    _ => {}
+                }
+            }
+            Err(ClientError::ConnectionClosed)
+        };
+        tokio::time::timeout(timeout, wait)
+            .await
+            .unwrap_or(Err(ClientError::Timeout))
     }
 
     /// Listen to a given topic path, receiving a subscription that yields live messages
@@ -407,16 +1753,22 @@ impl PoststationClient {
         serial: u64,
         path: &str,
     ) -> Result<JsonStreamListener, ClientError> {
-        let Ok(Some(schemas)) = self.get_device_schemas(serial).await else {
+        let res = self.stream_topic_json_inner(serial, path).await;
+        self.record(RequestKind::StreamStart, serial, path, None, &res);
+        res
+    }
+
+    async fn stream_topic_json_inner(
+        &self,
+        serial: u64,
+        path: &str,
+    ) -> Result<JsonStreamListener, ClientError> {
+        let DeviceResult::Known(schemas) = self.get_device_schemas(serial).await? else {
             return Err(ClientError::Server("topic not found".into()));
         };
 
-        // find key
-        let res = schemas
-            .topics_out
-            .iter()
-            .find(|e| e.path.as_str() == path)
-            .cloned();
+        // find key, taking the report out of the schemas rather than cloning it
+        let res = schemas.topics_out.into_iter().find(|t| t.path == path);
         let Some(schema) = res else {
             return Err(ClientError::Server("topic not found".into()));
         };
@@ -427,52 +1779,163 @@ impl PoststationClient {
             .await
             .map_err(|_| ClientError::ConnectionClosed)?;
 
-        let res = self
-            .client
-            .send_resp::<StartStreamEndpoint>(&TopicStreamRequest {
-                serial,
-                path: path.to_string(),
-                key: schema.key,
-            })
-            .await;
-
-        let stream_id = match res? {
-            TopicStreamResult::Started(id) => id,
-            TopicStreamResult::DeviceDisconnected => {
-                return Err(ClientError::Server("Device Disconnected".into()))
-            }
-            TopicStreamResult::NoDeviceKnown => {
-                return Err(ClientError::Server("No Device Known".into()))
-            }
-            TopicStreamResult::NoSuchTopic => {
-                return Err(ClientError::Server("No Such Topic".into()))
-            }
-        };
+        let stream_id = self.start_stream(serial, path, schema.key).await?;
 
         Ok(JsonStreamListener {
+            decoder: DynDecoder::new(&schema.ty),
             schema,
             sub,
             stream_id,
+            _stop: self.stream_stopper.guard(stream_id),
+            last_uuid: None,
+            undecodable: 0,
+            snapshot: None,
+            snapshot_uuid: None,
         })
     }
 
+    /// Like [`Self::stream_topic_json`], but first yields the most recently stored
+    /// message of the topic, if there is one
+    ///
+    /// This gives consumers of slow or rarely changing topics a value right away,
+    /// instead of waiting for the next message to be published.
+    pub async fn stream_topic_json_with_last(
+        &self,
+        serial: u64,
+        path: &str,
+    ) -> Result<JsonStreamListener, ClientError> {
+        let mut sub = self.stream_topic_json(serial, path).await?;
+
+        // Fetched after starting the stream so that no message is missed. Live messages
+        // received while fetching that are not newer than it are skipped, so a message
+        // received both ways is only yielded once, and nothing is yielded out of order.
+        let req = TopicRequest::new(serial, path.to_string(), sub.schema.key, 1);
+        let last = self.send_resp::<GetTopicsEndpoint>(&req).await?;
+        sub.snapshot = last.and_then(|mut msgs| msgs.pop());
+        sub.snapshot_uuid = sub.snapshot.as_ref().map(|m| m.uuidv7);
+        Ok(sub)
+    }
+
+    /// Listen to all "topic-out" paths of a device, receiving a single subscription that
+    /// yields live messages tagged with their path
+    ///
+    /// If `follow_changes` is set, paths added by a later change to the device's
+    /// schema are added to the subscription.
+    pub async fn stream_all_topics(
+        &self,
+        serial: u64,
+        follow_changes: bool,
+    ) -> Result<DeviceTopicsListener, ClientError> {
+        DeviceTopicsListener::start(self.clone(), serial, follow_changes, None).await
+    }
+
+    /// Listen to the given "topic-out" paths of a device, receiving a single subscription
+    /// that yields live messages tagged with their path
+    ///
+    /// If `follow_changes` is set, streams are restarted when a later change to the
+    /// device's schema changes the key of one of the paths.
+    pub async fn stream_topics(
+        &self,
+        serial: u64,
+        paths: &[&str],
+        follow_changes: bool,
+    ) -> Result<DeviceTopicsListener, ClientError> {
+        let only = paths.iter().map(|p| p.to_string()).collect();
+        DeviceTopicsListener::start(self.clone(), serial, follow_changes, Some(only)).await
+    }
+
+    /// Listen to all "topic-out" paths of a device that match `pattern`, receiving
+    /// a single subscription that yields live messages tagged with their path
+    ///
+    /// The pattern is matched by the server, see [`icd::postsock::topic_pattern_matches`]
+    /// for the syntax. For example, `sensors/*/temperature` streams the temperature
+    /// of every sensor. Paths added by a later change to the device's schema are not
+    /// streamed.
+    pub async fn stream_topic_pattern(
+        &self,
+        serial: u64,
+        pattern: &str,
+    ) -> Result<DeviceTopicsListener, ClientError> {
+        self.require_feature(features::STREAM_PATTERNS)?;
+        DeviceTopicsListener::start_pattern(self.clone(), serial, pattern).await
+    }
+
+    /// Forward every message of a topic-out path of one device to a topic-in path
+    /// of another device
+    ///
+    /// `from` and `to` are `(serial, path)` pairs. Messages are forwarded as JSON,
+    /// so the message types of the two topics only need to be compatible, not
+    /// identical. See [`Self::bridge_topic_with`] to modify messages on the way.
+    ///
+    /// Messages that fail to publish are skipped. If the server supports device
+    /// events, the stream is restarted when the source device reconnects.
+    pub async fn bridge_topic(
+        &self,
+        from: (u64, &str),
+        to: (u64, &str),
+    ) -> Result<BridgeHandle, ClientError> {
+        self.bridge_topic_with(from, to, Some).await
+    }
+
+    /// Like [`Self::bridge_topic`], passing each message through `transform`
+    ///
+    /// Messages for which `transform` returns `None` are not forwarded.
+    pub async fn bridge_topic_with<F>(
+        &self,
+        from: (u64, &str),
+        to: (u64, &str),
+        transform: F,
+    ) -> Result<BridgeHandle, ClientError>
+    where
+        F: Fn(Value) -> Option<Value> + Send + Sync + 'static,
+    {
+        let bridge = bridge::Bridge {
+            client: self.clone(),
+            from: (from.0, from.1.to_string()),
+            to: (to.0, to.1.to_string()),
+            transform,
+        };
+        bridge.spawn().await
+    }
+
+    /// Listen to a given topic path on all connected devices that offer it, receiving
+    /// a single subscription that yields live messages tagged with their device's serial
+    ///
+    /// If `follow_new` is set, devices that connect later, or reconnect, and offer
+    /// the path are added to the subscription, and devices that disconnect are
+    /// removed from it. This requires the server to support device events, otherwise
+    /// [`ClientError::Unsupported`] is returned.
+    pub async fn stream_topic_all(
+        &self,
+        path: &str,
+        follow_new: bool,
+    ) -> Result<MultiStreamListener, ClientError> {
+        MultiStreamListener::start(self.clone(), path, follow_new).await
+    }
+
     /// Listen to a given topic path, receiving a subscription that yields live messages
     pub async fn stream_topic<T>(&self, serial: u64) -> Result<StreamListener<T>, ClientError>
     where
         T: Topic,
         T::Message: DeserializeOwned,
     {
-        let Ok(Some(schemas)) = self.get_device_schemas(serial).await else {
+        let res = self.stream_topic_inner::<T>(serial).await;
+        self.record(RequestKind::StreamStart, serial, T::PATH, None, &res);
+        res
+    }
+
+    async fn stream_topic_inner<T>(&self, serial: u64) -> Result<StreamListener<T>, ClientError>
+    where
+        T: Topic,
+        T::Message: DeserializeOwned,
+    {
+        let DeviceResult::Known(schemas) = self.get_device_schemas(serial).await? else {
             return Err(ClientError::Server("topic not found".into()));
         };
 
-        // find key
-        let res = schemas
-            .topics_out
-            .iter()
-            .find(|e| e.path.as_str() == T::PATH && e.key == T::TOPIC_KEY)
-            .cloned();
-        let Some(schema) = res else {
+        // find key, only the key is needed as messages are decoded with `T`
+        let res = schemas.topic_out_for::<T>().map(|t| t.key);
+        let Some(key) = res else {
             return Err(ClientError::Server("topic not found".into()));
         };
 
@@ -482,47 +1945,246 @@ impl PoststationClient {
             .await
             .map_err(|_| ClientError::ConnectionClosed)?;
 
+        let stream_id = self.start_stream(serial, T::PATH, key).await?;
+
+        Ok(StreamListener {
+            sub,
+            stream_id,
+            _stop: self.stream_stopper.guard(stream_id),
+            last_uuid: None,
+            _pd: PhantomData,
+        })
+    }
+
+    /// The number of topic streams currently open, see [`ConnectOptions::max_streams`]
+    ///
+    /// Streams are counted from when they are started until their listener is
+    /// dropped, across all clones of this client.
+    pub fn active_stream_count(&self) -> usize {
+        self.stream_stopper.active()
+    }
+
+    /// Ask the server to stop a stream in the background
+    pub(crate) fn stop_stream(&self, stream_id: Uuidv7) {
+        self.stream_stopper.stop(stream_id);
+    }
+
+    /// Ask the server to start streaming a topic of a device, returning the stream's ID
+    ///
+    /// Messages of the stream are received through [`SubscribeTopic`], which should be
+    /// subscribed to before starting the stream, so no messages are missed. The caller
+    /// is responsible for stopping the stream, see [`Self::stop_stream`].
+    pub(crate) async fn start_stream(
+        &self,
+        serial: u64,
+        path: &str,
+        key: Key,
+    ) -> Result<Uuidv7, ClientError> {
+        self.stream_stopper.reserve(1)?;
+        let res = self.start_stream_inner(serial, path, key).await;
+        if res.is_err() {
+            self.stream_stopper.release(1);
+        }
+        res
+    }
+
+    async fn start_stream_inner(
+        &self,
+        serial: u64,
+        path: &str,
+        key: Key,
+    ) -> Result<Uuidv7, ClientError> {
         let res = self
-            .client
-            .send_resp::<StartStreamEndpoint>(&TopicStreamRequest {
+            .send_resp::<StartStreamEndpoint>(&TopicStreamRequest::new(
                 serial,
-                path: T::PATH.to_string(),
-                key: schema.key,
-            })
+                path.to_string(),
+                key,
+            ))
             .await;
 
-        let stream_id = match res? {
-            TopicStreamResult::Started(id) => id,
+        match res? {
+            TopicStreamResult::Started(id) => Ok(id),
             TopicStreamResult::DeviceDisconnected => {
-                return Err(ClientError::Server("Device Disconnected".into()))
-            }
-            TopicStreamResult::NoDeviceKnown => {
-                return Err(ClientError::Server("No Device Known".into()))
+                Err(ClientError::Server("Device Disconnected".into()))
             }
-            TopicStreamResult::NoSuchTopic => {
-                return Err(ClientError::Server("No Such Topic".into()))
-            }
-        };
+            TopicStreamResult::NoDeviceKnown => Err(ClientError::Server("No Device Known".into())),
+            TopicStreamResult::NoSuchTopic => Err(ClientError::Server("No Such Topic".into())),
+        }
+    }
+}
 
-        Ok(StreamListener {
-            sub,
-            stream_id,
-            _pd: PhantomData,
-        })
+pub struct SchemaChangeListener {
+    serial: u64,
+    sub: MultiSubscription<SchemaChange>,
+}
+
+impl SchemaChangeListener {
+    /// Receive the next changed schema of this device
+    ///
+    /// Returns None if the connection has been closed
+    pub async fn recv(&mut self) -> Option<SchemaReport> {
+        loop {
+            let msg = match self.sub.recv().await {
+                Ok(m) => m,
+                Err(MultiSubRxError::IoClosed) => return None,
+                Err(MultiSubRxError::Lagged(n)) => {
+                    tracing::warn!(serial = self.serial, lags = n, "Schema changes lagged");
+                    continue;
+                }
+            };
+
+            if msg.serial != self.serial {
+                continue;
+            }
+            return Some(msg.schema);
+        }
     }
 }
 
+/// A message received on a stream that could not be decoded using the topic's schema
+///
+/// This usually means that the schema of the device has changed since the stream
+/// was started, see [`JsonStreamListener::update_schema`].
+#[derive(Debug)]
+pub struct UndecodableMessage {
+    /// The ID of the message
+    pub uuidv7: Uuidv7,
+    /// The raw, postcard encoded message
+    pub raw: Vec<u8>,
+    /// A description of the decoding error
+    pub error: String,
+}
+
 pub struct JsonStreamListener {
     stream_id: Uuidv7,
+    _stop: StopOnDrop,
     schema: TopicReport,
+    /// Prepared from `schema`, and replaced along with it
+    decoder: DynDecoder,
     sub: MultiSubscription<TopicStreamMsg>,
+    last_uuid: Option<Uuidv7>,
+    undecodable: u64,
+    /// A stored message to yield before any live ones
+    snapshot: Option<TopicMsg>,
+    /// The ID of the snapshot, live messages up to and including it are skipped
+    snapshot_uuid: Option<Uuidv7>,
 }
 
 impl JsonStreamListener {
+    /// The ID of the stream started by the server for this listener
+    pub fn stream_id(&self) -> Uuidv7 {
+        self.stream_id
+    }
+
+    /// The "topic-out" path this listener receives messages of
+    pub fn path(&self) -> &str {
+        &self.schema.path
+    }
+
+    /// The schema used to decode messages, see [`Self::update_schema`]
+    pub fn schema(&self) -> &TopicReport {
+        &self.schema
+    }
+
+    /// The ID of the most recent message received by this listener, including
+    /// messages that could not be decoded
+    ///
+    /// This can be persisted by consumers that checkpoint their progress, and
+    /// is `None` until the first message has been received.
+    pub fn last_uuid(&self) -> Option<Uuidv7> {
+        self.last_uuid
+    }
+
+    /// The number of messages received so far that could not be decoded
+    pub fn undecodable_count(&self) -> u64 {
+        self.undecodable
+    }
+
+    /// Replace the schema used to decode messages with the one from the given report
+    ///
+    /// This is intended to be used with [`PoststationClient::subscribe_schema_changes`].
+    /// Returns `false`, leaving the current schema in place, if the report no longer
+    /// contains this topic.
+    pub fn update_schema(&mut self, report: &SchemaReport) -> bool {
+        let res = report.topic_out_by_path(&self.schema.path);
+        let Some(schema) = res else {
+            return false;
+        };
+        self.schema = schema.clone();
+        self.decoder = DynDecoder::new(&schema.ty);
+        true
+    }
+
     /// Receive a single message from this subscription
     ///
+    /// Messages that can not be decoded are skipped, use [`Self::recv_checked`] to
+    /// receive them instead.
+    ///
     /// Returns None if the connection has been closed
     pub async fn recv(&mut self) -> Option<Value> {
+        loop {
+            match self.recv_checked().await? {
+                Ok(msg) => return Some(msg),
+                Err(e) => {
+                    tracing::warn!(
+                        stream_id = ?self.stream_id,
+                        error = e.error,
+                        "Discarding undecodable message"
+                    );
+                    continue;
+                }
+            }
+        }
+    }
+
+    /// Like [`Self::recv`], but gives up if no message is received within `dur`
+    ///
+    /// No message is lost when the timeout elapses. Returns `Ok(None)` if the
+    /// connection has been closed.
+    pub async fn recv_timeout(&mut self, dur: Duration) -> Result<Option<Value>, Elapsed> {
+        tokio::time::timeout(dur, self.recv()).await
+    }
+
+    /// Receive a single message from this subscription, or the raw message if it could
+    /// not be decoded
+    ///
+    /// Returns None if the connection has been closed
+    pub async fn recv_checked(&mut self) -> Option<Result<Value, UndecodableMessage>> {
+        let (uuidv7, msg) = self.recv_raw().await?;
+        Some(self.decode(uuidv7, msg))
+    }
+
+    /// Like [`Self::recv`], but returns the message encoded in the given format
+    ///
+    /// Messages are encoded directly from their postcard encoding, see
+    /// [`encode_topic_raw`]. Messages that can not be encoded are skipped, and counted
+    /// as undecodable.
+    ///
+    /// Returns None if the connection has been closed
+    pub async fn recv_encoded(&mut self, format: ValueFormat) -> Option<Vec<u8>> {
+        loop {
+            let (uuidv7, msg) = self.recv_raw().await?;
+            self.last_uuid = Some(uuidv7);
+            match self.decoder.encode(&msg, format) {
+                Ok(out) => return Some(out),
+                Err(e) => {
+                    self.undecodable += 1;
+                    tracing::warn!(
+                        stream_id = ?self.stream_id,
+                        error = ?e,
+                        "Discarding undecodable message"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Receive the ID and raw bytes of the next message of this stream
+    async fn recv_raw(&mut self) -> Option<(Uuidv7, Vec<u8>)> {
+        if let Some(TopicMsg { uuidv7, msg }) = self.snapshot.take() {
+            return Some((uuidv7, msg));
+        }
+
         loop {
             let msg = match self.sub.recv().await {
                 Ok(m) => m,
@@ -533,15 +2195,39 @@ impl JsonStreamListener {
                 }
             };
 
-            let TopicStreamMsg { stream_id, msg } = msg;
+            let TopicStreamMsg {
+                stream_id,
+                uuidv7,
+                msg,
+            } = msg;
             if stream_id != self.stream_id {
                 continue;
             }
+            // Skip the live copy of the snapshot, and anything older. UUIDv7s sort by
+            // the time they were created in, compared as bytes.
+            if let Some(snapshot) = self.snapshot_uuid {
+                if uuidv7.0 <= snapshot.0 {
+                    continue;
+                }
+                self.snapshot_uuid = None;
+            }
 
-            let Ok(msg) = postcard_dyn::from_slice_dyn(&self.schema.ty, &msg) else {
-                continue;
-            };
-            return Some(msg);
+            return Some((uuidv7, msg));
+        }
+    }
+
+    fn decode(&mut self, uuidv7: Uuidv7, msg: Vec<u8>) -> Result<Value, UndecodableMessage> {
+        self.last_uuid = Some(uuidv7);
+        match self.decoder.decode_inner(&msg) {
+            Ok(val) => Ok(val),
+            Err(e) => {
+                self.undecodable += 1;
+                Err(UndecodableMessage {
+                    uuidv7,
+                    raw: msg,
+                    error: format!("{e:?}"),
+                })
+            }
         }
     }
 }
@@ -552,7 +2238,9 @@ where
     T::Message: DeserializeOwned,
 {
     stream_id: Uuidv7,
+    _stop: StopOnDrop,
     sub: MultiSubscription<TopicStreamMsg>,
+    last_uuid: Option<Uuidv7>,
     _pd: PhantomData<fn() -> T>,
 }
 
@@ -561,6 +2249,23 @@ where
     T: Topic,
     T::Message: DeserializeOwned,
 {
+    /// The ID of the stream started by the server for this listener
+    pub fn stream_id(&self) -> Uuidv7 {
+        self.stream_id
+    }
+
+    /// The "topic-out" path this listener receives messages of
+    pub fn path(&self) -> &'static str {
+        T::PATH
+    }
+
+    /// The ID of the most recent message returned by [`Self::recv`]
+    ///
+    /// This is `None` until the first message has been received.
+    pub fn last_uuid(&self) -> Option<Uuidv7> {
+        self.last_uuid
+    }
+
     /// Receive a single message from this subscription
     ///
     /// Returns None if the connection has been closed
@@ -568,24 +2273,166 @@ where
         loop {
             let msg = match self.sub.recv().await {
                 Ok(m) => m,
-                Err(MultiSubRxError::IoClosed) => return None,
+                Err(MultiSubRxError::IoClosed) => return None,
+                Err(MultiSubRxError::Lagged(n)) => {
+                    tracing::warn!(stream_id = ?self.stream_id, lags = n, "Stream lagged");
+                    continue;
+                }
+            };
+
+            let TopicStreamMsg {
+                stream_id,
+                uuidv7,
+                msg,
+            } = msg;
+            if stream_id != self.stream_id {
+                continue;
+            }
+
+            let Ok(msg) = postcard::from_bytes(&msg) else {
+                continue;
+            };
+            self.last_uuid = Some(uuidv7);
+            return Some(msg);
+        }
+    }
+
+    /// Like [`Self::recv`], but gives up if no message is received within `dur`
+    ///
+    /// No message is lost when the timeout elapses. Returns `Ok(None)` if the
+    /// connection has been closed.
+    pub async fn recv_timeout(&mut self, dur: Duration) -> Result<Option<T::Message>, Elapsed> {
+        tokio::time::timeout(dur, self.recv()).await
+    }
+}
+
+/// How long a request made with [`PoststationClient::proxy_endpoint_timed`] took
+///
+/// The difference between `round_trip` and `device` is the time spent between
+/// the client and poststation, and within poststation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProxyTiming {
+    /// The time between sending the request and receiving the response, as
+    /// measured by the client
+    pub round_trip: Duration,
+    /// The time between poststation sending the request to the device and
+    /// receiving its response, if reported by the server
+    pub device: Option<Duration>,
+}
+
+/// A run of identical consecutive topic messages, see [`PoststationClient::get_device_topics_out_runs`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicValueRun {
+    pub value: Value,
+    /// The number of messages in the run
+    pub count: u32,
+    pub first_uuid: Uuidv7,
+    pub last_uuid: Uuidv7,
+}
+
+/// Expand runs of messages back to one value per message, in the same order
+///
+/// The UUIDs of messages within a run are not known, only those of the first and
+/// last message of each run.
+pub fn expand_runs(runs: &[TopicValueRun]) -> Vec<Value> {
+    runs.iter()
+        .flat_map(|run| std::iter::repeat_n(&run.value, run.count as usize))
+        .cloned()
+        .collect()
+}
+
+/// The chunked response to a request made with [`PoststationClient::proxy_endpoint_streaming`]
+pub struct ProxyStreamListener<E>
+where
+    E: Endpoint,
+    E::Response: DeserializeOwned,
+{
+    stream_id: Uuidv7,
+    sub: MultiSubscription<ProxyChunk>,
+    next_index: u32,
+    done: bool,
+    _pd: PhantomData<fn() -> E>,
+}
+
+impl<E> ProxyStreamListener<E>
+where
+    E: Endpoint,
+    E::Response: DeserializeOwned,
+{
+    /// Receive the next chunk of the raw, postcard encoded, response body
+    ///
+    /// Returns None once the response is complete. If an error is returned, no
+    /// further chunks will be received.
+    pub async fn recv(&mut self) -> Option<Result<Vec<u8>, ClientError>> {
+        if self.done {
+            return None;
+        }
+        let res = self.recv_inner().await;
+        if !matches!(res, Some(Ok(_))) {
+            self.done = true;
+        }
+        res
+    }
+
+    async fn recv_inner(&mut self) -> Option<Result<Vec<u8>, ClientError>> {
+        loop {
+            let msg = match self.sub.recv().await {
+                Ok(m) => m,
+                Err(MultiSubRxError::IoClosed) => return Some(Err(ClientError::ConnectionClosed)),
                 Err(MultiSubRxError::Lagged(n)) => {
-                    tracing::warn!(stream_id = ?self.stream_id, lags = n, "Stream lagged");
+                    tracing::warn!(stream_id = ?self.stream_id, lags = n, "Proxy stream lagged");
                     continue;
                 }
             };
-
-            let TopicStreamMsg { stream_id, msg } = msg;
-            if stream_id != self.stream_id {
+            if msg.stream_id != self.stream_id {
                 continue;
             }
 
-            let Ok(msg) = postcard::from_bytes(&msg) else {
-                continue;
+            // Chunks are only useful in order and without gaps
+            if msg.index != self.next_index {
+                return Some(Err(ClientError::Server(
+                    "Missed proxy stream chunks".into(),
+                )));
+            }
+            self.next_index += 1;
+
+            // poststation to remote comms
+            return match msg.kind {
+                ProxyChunkKind::Data(data) => Some(Ok(data)),
+                ProxyChunkKind::Done => None,
+                ProxyChunkKind::WireErr(body) => Some(Err(ClientError::RemoteWire(body))),
+                ProxyChunkKind::OtherErr(e) => {
+                    Some(Err(ClientError::Remote(format!("Other Server Err: '{e}'"))))
+                }
+                ProxyChunkKind::Timeout => Some(Err(ClientError::Timeout)),
             };
-            return Some(msg);
         }
     }
+
+    /// Receive all remaining chunks, returning the reassembled raw response body
+    pub async fn collect_raw(mut self) -> Result<Vec<u8>, ClientError> {
+        let mut body = vec![];
+        while let Some(chunk) = self.recv().await {
+            body.extend_from_slice(&chunk?);
+        }
+        Ok(body)
+    }
+
+    /// Receive all remaining chunks, and decode the reassembled response
+    pub async fn collect(self) -> Result<E::Response, ClientError> {
+        let body = self.collect_raw().await?;
+        postcard::from_bytes(&body).map_err(|_| ClientError::Encoding)
+    }
+}
+
+/// The default address of a poststation server on the local machine
+pub const DEFAULT_SERVER_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 51837);
+
+/// Connect to a poststation server running on the local machine with the default settings
+///
+/// This is equivalent to calling [`connect`] with [`DEFAULT_SERVER_ADDR`].
+pub async fn connect_localhost() -> Result<PoststationClient, ConnectError> {
+    ConnectOptions::default().connect_localhost().await
 }
 
 /// Connect to a server configured in "insecure" mode
@@ -593,132 +2440,580 @@ where
 /// "Insecure" is not the default setting. Your poststation server must
 /// be configured to "insecure" to allow this.
 pub async fn connect_insecure(port: u16) -> Result<PoststationClient, ConnectError> {
-    // Insecure can only be located on localhost
-    let socket = TcpStream::connect(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port))
-        .await
-        .map_err(|_| ConnectError::Connection)?;
-    let addr = socket.peer_addr().map_err(|_| ConnectError::Connection)?;
-    socket
-        .set_nodelay(true)
-        .map_err(|_| ConnectError::Connection)?;
-    let (rx, tx) = split(socket);
-
-    let client = HostClient::<WireError>::new_with_wire(
-        TcpCommsTx { tx },
-        TcpCommsRx {
-            rx,
-            addr,
-            buf: vec![],
-        },
-        TcpSpawn,
-        postcard_rpc::header::VarSeqKind::Seq4,
-        ERROR_PATH,
-        64,
-    );
-
-    let res = client
-        .send_resp::<PingEndpoint>(&42)
-        .await
-        .map_err(|_| ConnectError::Protocol)?;
+    ConnectOptions::default().connect_insecure(port).await
+}
 
-    if res != 42 {
-        return Err(ConnectError::Protocol);
-    }
+/// Connect to a server, trying TLS first, then "insecure" mode for local servers
+///
+/// This is a convenience for local development, when it is not known how the server
+/// is configured. If `addr` is a loopback address, and the TLS handshake fails, does
+/// not finish within two seconds, or no CA certificate can be loaded, an "insecure"
+/// connection to the same port is attempted. If both fail, the error of the TLS
+/// attempt is returned. Other errors, such as a refused connection, and all errors
+/// for other addresses, are returned without trying "insecure" mode.
+///
+/// Use [`ConnectOptions::attempt_timeout`] to change the time allowed for each attempt.
+/// Prefer [`connect`] or [`connect_insecure`] when the mode of the server is known.
+pub async fn connect_auto(
+    addr: SocketAddr,
+) -> Result<(PoststationClient, ConnectMode), ConnectError> {
+    ConnectOptions::default().connect_auto(addr).await
+}
+
+/// Connect to the first reachable server of a list, using the given TLS CA certificate
+///
+/// The addresses are tried in order, each for at most two seconds. Returns the
+/// client and the address of the server it is connected to. If no server could be
+/// connected to, the error of the last attempt is returned.
+///
+/// Use [`ConnectOptions::attempt_timeout`] to change the time allowed for each attempt.
+pub async fn connect_any(
+    addrs: &[SocketAddr],
+    ca_path: &Path,
+) -> Result<(PoststationClient, SocketAddr), ConnectError> {
+    ConnectOptions::default().connect_any(addrs, ca_path).await
+}
+
+/// The kind of connection made by [`connect_auto`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectMode {
+    /// Connected with TLS, like [`connect`]
+    Tls,
+    /// Connected without TLS, like [`connect_insecure`]
+    Insecure,
+}
 
-    Ok(PoststationClient { client })
+/// The state of the connection between a client and the poststation server
+///
+/// See [`PoststationClient::connection_state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    /// The connection was lost, and is being re-established
+    ///
+    /// Clients created by the `connect` functions do not reconnect, and go
+    /// straight to [`ConnectionState::Closed`].
+    Reconnecting,
+    Closed,
 }
 
 #[non_exhaustive]
 #[derive(Debug)]
 pub enum ConnectError {
-    // Failed to load CA Certificate for server
-    CaCertificate,
-    // Failed to connect to poststation server
-    Connection,
-    // Protocol check failed
+    /// Failed to load CA Certificate for server
+    CaCertificate(Box<dyn Error + Send + Sync>),
+    /// Failed to connect to poststation server, e.g. because the connection was refused
+    /// or timed out
+    Connection(Box<dyn Error + Send + Sync>),
+    /// The TLS handshake with the server failed
+    ///
+    /// This usually means the server's certificate is not signed by the given CA
+    /// certificate, for example because the `ca-cert.pem` of another server was used.
+    TlsHandshake(String),
+    /// Protocol check failed
     Protocol,
 }
 
 impl Display for ConnectError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        <Self as Debug>::fmt(self, f)
+        match self {
+            ConnectError::CaCertificate(e) => write!(f, "Failed to load CA certificate: {e}"),
+            ConnectError::Connection(e) => write!(f, "Failed to connect: {e}"),
+            ConnectError::TlsHandshake(e) => write!(f, "TLS handshake failed: {e}"),
+            ConnectError::Protocol => f.write_str("Server did not respond as a poststation server"),
+        }
+    }
+}
+
+impl Error for ConnectError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConnectError::CaCertificate(e) | ConnectError::Connection(e) => Some(e.as_ref()),
+            ConnectError::TlsHandshake(_) | ConnectError::Protocol => None,
+        }
+    }
+}
+
+impl ConnectError {
+    fn connection(e: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        ConnectError::Connection(e.into())
+    }
+
+    fn ca_certificate(e: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        ConnectError::CaCertificate(e.into())
     }
 }
 
-impl Error for ConnectError {}
+/// The environment variable that [`connect`] reads the path of the CA certificate from
+pub const CA_CERT_ENV: &str = "POSTSTATION_CA_CERT";
 
 /// Connect to a server configured with Self Signed TLS certificates (default)
 ///
-/// This function can only be used on the same device as the poststation server.
+/// The CA certificate is loaded from the path in the [`CA_CERT_ENV`] environment
+/// variable, if set. Otherwise, it is loaded from the data folder of poststation,
+/// in which case this function can only be used on the same device as the
+/// poststation server.
 ///
 /// If you are connecting to a remote device, you will need to obtain the CA cert
-/// from the poststation server, and use [`connect_with_ca_pem`] to connect
+/// from the poststation server, e.g. with [`fetch_ca_cert`], and either set
+/// [`CA_CERT_ENV`] or use [`connect_with_ca_pem`] or [`connect_with_ca_bytes`]
+/// to connect
 pub async fn connect<T: tokio::net::ToSocketAddrs>(
     addr: T,
 ) -> Result<PoststationClient, ConnectError> {
-    // If we are on the same machine as the Poststation server, we can load the CA cert from the
-    // working folder of poststation
-    let Some(dirs) = ProjectDirs::from("com.onevariable", "onevariable", "poststation") else {
-        return Err(ConnectError::CaCertificate);
-    };
-    let data_dir = dirs.data_dir();
-    let mut pem_path = PathBuf::from(data_dir);
-    pem_path.push("ca-cert.pem");
-    connect_with_ca_pem(addr, &pem_path).await
+    ConnectOptions::default().connect(addr).await
 }
 
 /// Connect to a server with the given TLS CA certificate
+///
+/// This builds a default [`rustls::ClientConfig`] trusting only the given CA certificate,
+/// and connects using [`connect_with_rustls_config`].
 pub async fn connect_with_ca_pem<T: tokio::net::ToSocketAddrs>(
     addr: T,
     ca_path: &Path,
 ) -> Result<PoststationClient, ConnectError> {
-    let mut root_cert_store = RootCertStore::empty();
-    root_cert_store
-        .add(CertificateDer::from_pem_file(ca_path).map_err(|_| ConnectError::CaCertificate)?)
-        .map_err(|_| ConnectError::CaCertificate)?;
-    let config = rustls::ClientConfig::builder()
-        .with_root_certificates(root_cert_store)
-        .with_no_client_auth();
-    let connector = TlsConnector::from(Arc::new(config));
-    let stream = TcpStream::connect(addr)
+    ConnectOptions::default()
+        .connect_with_ca_pem(addr, ca_path)
         .await
-        .map_err(|_| ConnectError::Connection)?;
-    stream
-        .set_nodelay(false)
-        .map_err(|_| ConnectError::Connection)?;
-    let addr = stream.peer_addr().map_err(|_| ConnectError::Connection)?;
-    let stream = connector
-        .connect(ServerName::IpAddress(addr.ip().into()), stream)
+}
+
+/// Connect to a server with the given PEM encoded TLS CA certificate
+///
+/// Like [`connect_with_ca_pem`], for a certificate that is already in memory, e.g.
+/// one returned by [`fetch_ca_cert`].
+pub async fn connect_with_ca_bytes<T: tokio::net::ToSocketAddrs>(
+    addr: T,
+    pem: &[u8],
+) -> Result<PoststationClient, ConnectError> {
+    ConnectOptions::default()
+        .connect_with_ca_bytes(addr, pem)
         .await
-        .map_err(|_| ConnectError::Connection)?;
-    let (rx, tx) = split(stream);
+}
 
-    let client = HostClient::<WireError>::new_with_wire(
-        TcpCommsTx { tx },
-        TcpCommsRx {
-            rx,
-            addr,
-            buf: vec![],
-        },
-        TcpSpawn,
-        postcard_rpc::header::VarSeqKind::Seq4,
-        ERROR_PATH,
-        64,
-    );
-
-    let res = client
-        .send_resp::<PingEndpoint>(&42)
+/// Download the PEM encoded CA certificate of a server over its insecure port
+///
+/// This is a trust-on-first-use bootstrap for connecting to remote servers: the
+/// connection is not encrypted or authenticated, so the certificate can not be
+/// verified, and anyone able to intercept the connection can substitute their own.
+/// Fetch the certificate once over a trusted network, save it, and use it with
+/// [`connect_with_ca_bytes`] or [`connect_with_ca_pem`] from then on.
+///
+/// Unlike [`connect_insecure`], this connects to any address. Returns
+/// [`ConnectError::Protocol`] if the server does not offer its certificate.
+pub async fn fetch_ca_cert(addr: SocketAddr) -> Result<Vec<u8>, ConnectError> {
+    ConnectOptions::default().fetch_ca_cert(addr).await
+}
+
+/// Connect to a server using a fully constructed TLS client configuration
+///
+/// This allows for control over all TLS settings, and for sharing a single config
+/// across many connections.
+///
+/// If `server_name` is `None`, the IP address of the server is used as the name
+/// the server's certificate is verified against, which matches the certificates
+/// generated by poststation.
+pub async fn connect_with_rustls_config<T: tokio::net::ToSocketAddrs>(
+    addr: T,
+    server_name: Option<ServerName<'static>>,
+    config: Arc<rustls::ClientConfig>,
+) -> Result<PoststationClient, ConnectError> {
+    ConnectOptions::default()
+        .connect_with_rustls_config(addr, server_name, config)
         .await
-        .map_err(|_| ConnectError::Protocol)?;
+}
+
+/// Options used when connecting to a poststation server
+///
+/// The `connect*` functions of this crate use the default options. To customize
+/// them, construct a `ConnectOptions` and use its `connect*` methods instead:
+///
+/// ```rust,no_run
+/// # async fn example() -> Result<(), poststation_sdk::ConnectError> {
+/// use poststation_sdk::ConnectOptions;
+///
+/// let client = ConnectOptions::new()
+///     .rx_chunk_size(16 * 1024)
+///     .connect_localhost()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    rx_chunk_size: usize,
+    request_history: Option<usize>,
+    record_responses: bool,
+    attempt_timeout: Duration,
+    ping_retries: u32,
+    ping_retry_delay: Duration,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    seq_kind: VarSeqKind,
+    max_streams: Option<usize>,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            rx_chunk_size: 1024,
+            request_history: None,
+            record_responses: false,
+            attempt_timeout: Duration::from_secs(2),
+            ping_retries: 2,
+            ping_retry_delay: Duration::from_millis(100),
+            rate_limiter: None,
+            seq_kind: VarSeqKind::Seq4,
+            max_streams: None,
+        }
+    }
+}
+
+impl ConnectOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The maximum number of bytes read from the connection at once, default 1024
+    ///
+    /// Larger values reduce the number of reads needed when receiving many or
+    /// large messages, for example when streaming topics at a high rate.
+    pub fn rx_chunk_size(mut self, size: usize) -> Self {
+        self.rx_chunk_size = size.max(1);
+        self
+    }
+
+    /// Record the last `capacity` requests made by the client, disabled by default
+    ///
+    /// See [`PoststationClient::request_history`].
+    pub fn request_history(mut self, capacity: usize) -> Self {
+        self.request_history = Some(capacity);
+        self
+    }
+
+    /// Record the raw response to each distinct proxied request, disabled by default
+    ///
+    /// Only the last response to each request is kept, but every distinct request
+    /// body is kept, so this is meant for capturing test sessions rather than for
+    /// long running clients. See [`PoststationClient::last_response_for`].
+    pub fn record_responses(mut self, enabled: bool) -> Self {
+        self.record_responses = enabled;
+        self
+    }
+
+    /// The time allowed for each connection attempt of [`Self::connect_any`] and
+    /// [`Self::connect_auto`], default 2s
+    pub fn attempt_timeout(mut self, timeout: Duration) -> Self {
+        self.attempt_timeout = timeout;
+        self
+    }
+
+    /// The number of times the initial ping is retried if it fails, default 2
+    ///
+    /// The ping is not retried if the connection was closed, or if the server
+    /// responded with the wrong value.
+    pub fn ping_retries(mut self, retries: u32) -> Self {
+        self.ping_retries = retries;
+        self
+    }
+
+    /// The delay before retrying a failed initial ping, default 100ms
+    pub fn ping_retry_delay(mut self, delay: Duration) -> Self {
+        self.ping_retry_delay = delay;
+        self
+    }
+
+    /// Limit the rate of proxy and publish requests to each device, disabled by default
+    ///
+    /// The limiter is shared by all clones of the client, and its limits can be
+    /// changed while connected.
+    pub fn rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// The size of the sequence numbers of sent requests, default [`VarSeqKind::Seq4`]
+    ///
+    /// The size does not need to match the server, as every message header carries
+    /// the size of its own sequence number, and the server answers with the sequence
+    /// number of the request. Smaller sizes save a few bytes per request, but wrap
+    /// around sooner, so fewer requests can be in flight before a sequence number is
+    /// reused.
+    pub fn seq_kind(mut self, kind: VarSeqKind) -> Self {
+        self.seq_kind = kind;
+        self
+    }
+
+    /// Limit the number of topic streams open at once, unlimited by default
+    ///
+    /// Starting a stream beyond the limit fails with [`ClientError::TooManyStreams`].
+    /// A stream counts from when it is started until its listener is dropped, and
+    /// the limit is shared by all clones of the client, see
+    /// [`PoststationClient::active_stream_count`].
+    pub fn max_streams(mut self, max: usize) -> Self {
+        self.max_streams = Some(max);
+        self
+    }
+
+    /// Like [`connect_localhost`], using these options
+    pub async fn connect_localhost(&self) -> Result<PoststationClient, ConnectError> {
+        self.connect(DEFAULT_SERVER_ADDR).await
+    }
+
+    /// Like [`connect_insecure`], using these options
+    pub async fn connect_insecure(&self, port: u16) -> Result<PoststationClient, ConnectError> {
+        // Insecure can only be located on localhost
+        let socket = TcpStream::connect(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port))
+            .await
+            .map_err(ConnectError::connection)?;
+        let addr = socket.peer_addr().map_err(ConnectError::connection)?;
+        socket.set_nodelay(true).map_err(ConnectError::connection)?;
+        self.start_client(socket, addr).await
+    }
+
+    /// Like [`connect_any`], using these options
+    pub async fn connect_any(
+        &self,
+        addrs: &[SocketAddr],
+        ca_path: &Path,
+    ) -> Result<(PoststationClient, SocketAddr), ConnectError> {
+        let mut last_err = ConnectError::connection("no addresses given");
+        for addr in addrs {
+            let attempt = self.connect_with_ca_pem(*addr, ca_path);
+            match tokio::time::timeout(self.attempt_timeout, attempt).await {
+                Ok(Ok(client)) => return Ok((client, *addr)),
+                Ok(Err(e)) => last_err = e,
+                Err(_) => {
+                    last_err =
+                        ConnectError::connection(std::io::Error::from(std::io::ErrorKind::TimedOut))
+                }
+            }
+            tracing::warn!(?addr, error = ?last_err, "Failed to connect");
+        }
+        Err(last_err)
+    }
+
+    /// Like [`connect_auto`], using these options
+    pub async fn connect_auto(
+        &self,
+        addr: SocketAddr,
+    ) -> Result<(PoststationClient, ConnectMode), ConnectError> {
+        let timed_out =
+            || ConnectError::connection(std::io::Error::from(std::io::ErrorKind::TimedOut));
+        let tls_err = match tokio::time::timeout(self.attempt_timeout, self.connect(addr)).await {
+            Ok(Ok(client)) => return Ok((client, ConnectMode::Tls)),
+            // A server without TLS fails the handshake, either by closing the
+            // connection, by answering with something that is not TLS, or by
+            // waiting for more data after the ClientHello. A local development
+            // machine may also have no CA certificate at all.
+            Ok(Err(e @ (ConnectError::TlsHandshake(_) | ConnectError::CaCertificate(_)))) => e,
+            Err(_) => timed_out(),
+            Ok(Err(e)) => return Err(e),
+        };
+        // Insecure can only be located on localhost
+        if !addr.ip().is_loopback() {
+            return Err(tls_err);
+        }
+        match tokio::time::timeout(self.attempt_timeout, self.connect_insecure(addr.port())).await {
+            Ok(Ok(client)) => Ok((client, ConnectMode::Insecure)),
+            Ok(Err(_)) | Err(_) => Err(tls_err),
+        }
+    }
+
+    /// Like [`connect`], using these options
+    pub async fn connect<T: tokio::net::ToSocketAddrs>(
+        &self,
+        addr: T,
+    ) -> Result<PoststationClient, ConnectError> {
+        // An explicitly configured CA cert takes precedence
+        if let Some(pem_path) = std::env::var_os(CA_CERT_ENV) {
+            return self.connect_with_ca_pem(addr, Path::new(&pem_path)).await;
+        }
+
+        // If we are on the same machine as the Poststation server, we can load the CA cert from the
+        // working folder of poststation
+        let Some(dirs) = ProjectDirs::from("com.onevariable", "onevariable", "poststation") else {
+            return Err(ConnectError::ca_certificate(format!(
+                "no data folder of poststation found, set {CA_CERT_ENV}"
+            )));
+        };
+        let data_dir = dirs.data_dir();
+        let mut pem_path = PathBuf::from(data_dir);
+        pem_path.push("ca-cert.pem");
+        self.connect_with_ca_pem(addr, &pem_path).await
+    }
+
+    /// Like [`connect_with_ca_pem`], using these options
+    pub async fn connect_with_ca_pem<T: tokio::net::ToSocketAddrs>(
+        &self,
+        addr: T,
+        ca_path: &Path,
+    ) -> Result<PoststationClient, ConnectError> {
+        let cert = CertificateDer::from_pem_file(ca_path).map_err(ConnectError::ca_certificate)?;
+        self.connect_with_ca(addr, cert).await
+    }
+
+    /// Like [`connect_with_ca_bytes`], using these options
+    pub async fn connect_with_ca_bytes<T: tokio::net::ToSocketAddrs>(
+        &self,
+        addr: T,
+        pem: &[u8],
+    ) -> Result<PoststationClient, ConnectError> {
+        let cert = CertificateDer::from_pem_slice(pem).map_err(ConnectError::ca_certificate)?;
+        self.connect_with_ca(addr, cert).await
+    }
+
+    async fn connect_with_ca<T: tokio::net::ToSocketAddrs>(
+        &self,
+        addr: T,
+        cert: CertificateDer<'static>,
+    ) -> Result<PoststationClient, ConnectError> {
+        let mut root_cert_store = RootCertStore::empty();
+        root_cert_store
+            .add(cert)
+            .map_err(ConnectError::ca_certificate)?;
+        let config = rustls::ClientConfig::builder()
+            .with_root_certificates(root_cert_store)
+            .with_no_client_auth();
+        self.connect_with_rustls_config(addr, None, Arc::new(config))
+            .await
+    }
+
+    /// Like [`fetch_ca_cert`], using these options
+    pub async fn fetch_ca_cert(&self, addr: SocketAddr) -> Result<Vec<u8>, ConnectError> {
+        let socket = TcpStream::connect(addr)
+            .await
+            .map_err(ConnectError::connection)?;
+        socket.set_nodelay(true).map_err(ConnectError::connection)?;
+        let client = self.start_client(socket, addr).await?;
+        if client.require_feature(features::CA_CERT).is_err() {
+            return Err(ConnectError::Protocol);
+        }
+        client
+            .send_resp::<GetCaCertEndpoint>(&())
+            .await
+            .map_err(|_| ConnectError::Protocol)
+    }
+
+    /// Like [`connect_with_rustls_config`], using these options
+    pub async fn connect_with_rustls_config<T: tokio::net::ToSocketAddrs>(
+        &self,
+        addr: T,
+        server_name: Option<ServerName<'static>>,
+        config: Arc<rustls::ClientConfig>,
+    ) -> Result<PoststationClient, ConnectError> {
+        let connector = TlsConnector::from(config);
+        let stream = TcpStream::connect(addr)
+            .await
+            .map_err(ConnectError::connection)?;
+        stream
+            .set_nodelay(false)
+            .map_err(ConnectError::connection)?;
+        let addr = stream.peer_addr().map_err(ConnectError::connection)?;
+        let server_name = server_name.unwrap_or(ServerName::IpAddress(addr.ip().into()));
+        let stream = connector
+            .connect(server_name, stream)
+            .await
+            .map_err(|e| ConnectError::TlsHandshake(e.to_string()))?;
+
+        self.start_client(stream, addr).await
+    }
+
+    /// Start the I/O worker for an established connection, and verify the server responds
+    async fn start_client<S>(
+        &self,
+        stream: S,
+        addr: SocketAddr,
+    ) -> Result<PoststationClient, ConnectError>
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (client, state, in_flight) = self.new_host_client(stream, addr);
+        self.ping(&client).await?;
+
+        // Servers that don't know the endpoint predate capabilities
+        let capabilities = match client.send_resp::<GetCapabilitiesEndpoint>(&()).await {
+            Ok(caps) => caps,
+            Err(HostErr::Wire(_)) => Capabilities::default(),
+            Err(_) => return Err(ConnectError::Protocol),
+        };
+
+        Ok(PoststationClient {
+            stream_stopper: StreamStopper::new(client.clone(), self.max_streams),
+            client,
+            capabilities: Some(Arc::new(capabilities)),
+            history: self
+                .request_history
+                .map(|cap| Arc::new(RequestHistory::new(cap))),
+            responses: self.record_responses.then(Default::default),
+            seq_nos: Default::default(),
+            rate_limiter: self.rate_limiter.clone(),
+            state,
+            in_flight,
+            schema_fetches: Default::default(),
+            trust_mode: Default::default(),
+        })
+    }
 
-    if res != 42 {
-        return Err(ConnectError::Protocol);
+    /// Check that the server responds to pings, retrying transient failures
+    async fn ping(&self, client: &HostClient<WireError>) -> Result<(), ConnectError> {
+        let mut retries = self.ping_retries;
+        loop {
+            match client.send_resp::<PingEndpoint>(&42).await {
+                Ok(42) => return Ok(()),
+                Ok(_) | Err(HostErr::Closed) => return Err(ConnectError::Protocol),
+                Err(e) if retries == 0 => {
+                    tracing::warn!(error = ?e, "Ping failed");
+                    return Err(ConnectError::Protocol);
+                }
+                Err(e) => {
+                    tracing::debug!(error = ?e, retries, "Ping failed, retrying");
+                    retries -= 1;
+                    tokio::time::sleep(self.ping_retry_delay).await;
+                }
+            }
+        }
     }
 
-    Ok(PoststationClient { client })
+    /// Create a client that communicates over the given stream, using COBS framing
+    ///
+    /// Also returns the state of the connection, which is closed once the receiving
+    /// side of the stream is shut down.
+    fn new_host_client<S>(
+        &self,
+        stream: S,
+        addr: SocketAddr,
+    ) -> (
+        HostClient<WireError>,
+        watch::Receiver<ConnectionState>,
+        Arc<InFlight>,
+    )
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (rx, tx) = split(stream);
+        let (state_tx, state_rx) = watch::channel(ConnectionState::Connected);
+        let in_flight = Arc::<InFlight>::default();
+        let mut rx = TcpCommsRx::new(rx, addr, self.rx_chunk_size);
+        rx.state = Some(ClosedOnDrop {
+            state: state_tx,
+            in_flight: in_flight.clone(),
+        });
+
+        let client = HostClient::<WireError>::new_with_wire(
+            TcpCommsTx { tx },
+            rx,
+            TcpSpawn,
+            self.seq_kind,
+            ERROR_PATH,
+            64,
+        );
+        (client, state_rx, in_flight)
+    }
 }
 
 pub enum TcpCommsRxError {
+    /// No longer returned: oversized frames are discarded, and the receiver
+    /// resynchronizes at the next frame instead of closing the connection
+    #[deprecated(note = "oversized frames are discarded instead of closing the connection")]
     RxOverflow,
     ConnError,
 }
@@ -737,40 +3032,126 @@ impl Display for TcpCommsRxError {
 
 impl Error for TcpCommsRxError {}
 
+/// The largest frame we are willing to collect before discarding it
+const MAX_FRAME_SIZE: usize = 1024 * 1024;
+
 struct TcpCommsRx<T: AsyncRead + Send + 'static> {
     addr: SocketAddr,
+    /// Received bytes, of which the first `start` have already been processed
     buf: Vec<u8>,
+    start: usize,
+    /// The number of unprocessed bytes already known to contain no zero
+    searched: usize,
+    /// The number of bytes to make room for before each read
+    chunk_size: usize,
+    /// Are we dropping the remainder of an oversized frame?
+    discarding: bool,
+    /// Reused to decode each frame, so frames that turn out to carry no message
+    /// need no allocation
+    scratch: Vec<u8>,
     rx: ReadHalf<T>,
+    /// Marks the connection as closed when the receiver is dropped
+    state: Option<ClosedOnDrop>,
+}
+
+/// Sets the connection state to closed when dropped
+///
+/// This is dropped when the I/O worker shuts down, which is also where we warn
+/// about requests that were still awaiting a response.
+struct ClosedOnDrop {
+    state: watch::Sender<ConnectionState>,
+    in_flight: Arc<InFlight>,
+}
+
+impl Drop for ClosedOnDrop {
+    fn drop(&mut self) {
+        let count = self.in_flight.count.load(Ordering::Acquire);
+        if count != 0 {
+            tracing::warn!(count, "Connection closed with requests still in flight");
+        }
+        self.state.send_replace(ConnectionState::Closed);
+    }
 }
 
 impl<T: AsyncRead + Send + 'static> TcpCommsRx<T> {
+    fn new(rx: ReadHalf<T>, addr: SocketAddr, chunk_size: usize) -> Self {
+        Self {
+            addr,
+            buf: Vec::with_capacity(chunk_size),
+            start: 0,
+            searched: 0,
+            chunk_size,
+            discarding: false,
+            scratch: Vec::new(),
+            rx,
+            state: None,
+        }
+    }
+
     async fn receive_inner(&mut self) -> Result<Vec<u8>, TcpCommsRxError> {
-        let mut rx_buf = [0u8; 1024];
         'frame: loop {
-            if self.buf.len() > (1024 * 1024) {
-                tracing::warn!(?self.addr, "Refusing to collect >1MiB, terminating");
-                self.buf.clear();
-                return Err(TcpCommsRxError::RxOverflow);
-            }
-
             // Do we have a message already?
-            if let Some(pos) = self.buf.iter().position(|b| *b == 0) {
-                // we found the end of a message, attempt to decode it
-                let mut split = self.buf.split_off(pos + 1);
-                core::mem::swap(&mut self.buf, &mut split);
-
-                // Can we decode the cobs?
-                let res = cobs::decode_vec(&split);
-                let Ok(msg) = res else {
-                    tracing::warn!(?self.addr, discarded = split.len(), "Discarding bad message (cobs)");
+            if let Some(range) = self.take_frame() {
+                let frame = &self.buf[range];
+
+                // This is the tail of an oversized frame, we are back in sync now
+                if self.discarding {
+                    self.discarding = false;
+                    tracing::warn!(?self.addr, discarded = frame.len(), "Resynchronized after oversized message");
+                    continue 'frame;
+                }
+
+                // The end of the frame may arrive in the same read that takes it over
+                // the limit, so it is complete, but still too large
+                if frame.len() > MAX_FRAME_SIZE {
+                    tracing::warn!(?self.addr, discarded = frame.len(), "Discarding oversized message");
+                    continue 'frame;
+                }
+
+                // An empty frame (a lone zero) carries no message, skip it. This makes a
+                // bare zero byte a no-op, which peers may send as a heartbeat.
+                if frame.len() == 1 {
+                    continue 'frame;
+                }
+
+                // Can we decode the cobs? The decoded message is always shorter
+                // than the encoded frame.
+                self.scratch.clear();
+                self.scratch.resize(frame.len(), 0);
+                let res = cobs::decode(frame, &mut self.scratch);
+                let Ok(used) = res else {
+                    tracing::warn!(?self.addr, discarded = frame.len(), "Discarding bad message (cobs)");
                     continue 'frame;
                 };
 
-                return Ok(msg);
+                // A frame that decodes to nothing is no message either, rather than
+                // one that fails to deserialize further up
+                if used == 0 {
+                    continue 'frame;
+                }
+
+                // The receiver needs an owned message, copy out just the decoded bytes
+                return Ok(self.scratch[..used].to_vec());
+            }
+
+            // All complete frames have been taken, so the buffer only holds the start
+            // of a single frame. Move it to the front, reusing the buffer.
+            self.buf.drain(..self.start);
+            self.start = 0;
+
+            // If it is too large, drop it and everything up to the next zero, rather
+            // than collecting it forever.
+            if self.buf.len() > MAX_FRAME_SIZE {
+                tracing::warn!(?self.addr, "Refusing to collect >1MiB, discarding message");
+                self.buf.clear();
+                self.searched = 0;
+                self.discarding = true;
             }
 
-            // No message yet, let's try and receive some data
-            let Ok(used) = self.rx.read(&mut rx_buf).await else {
+            // No message yet, let's try and receive some data directly into the buffer
+            self.buf.reserve(self.chunk_size);
+            let mut rx = (&mut self.rx).take(self.chunk_size as u64);
+            let Ok(used) = rx.read_buf(&mut self.buf).await else {
                 tracing::warn!(?self.addr, "Closing");
                 return Err(TcpCommsRxError::ConnError);
             };
@@ -778,9 +3159,27 @@ impl<T: AsyncRead + Send + 'static> TcpCommsRx<T> {
                 tracing::warn!(?self.addr, "Closing");
                 return Err(TcpCommsRxError::ConnError);
             }
-            self.buf.extend_from_slice(&rx_buf[..used]);
         }
     }
+
+    /// Find the first complete unprocessed frame in the buffer, including its
+    /// terminating zero, and mark it as processed
+    ///
+    /// Any bytes after the frame, which may be further complete or partial frames
+    /// received in the same read, are kept in the buffer for the next call.
+    fn take_frame(&mut self) -> Option<Range<usize>> {
+        // Only search the bytes received since the last call, so collecting a large
+        // frame over many reads doesn't search its start over and over
+        let from = self.start + self.searched;
+        let Some(pos) = self.buf[from..].iter().position(|b| *b == 0) else {
+            self.searched = self.buf.len() - self.start;
+            return None;
+        };
+        let frame = self.start..(from + pos + 1);
+        self.start = frame.end;
+        self.searched = 0;
+        Some(frame)
+    }
 }
 
 impl<T: AsyncRead + Send + 'static> WireRx for TcpCommsRx<T> {
@@ -822,6 +3221,11 @@ impl<T: AsyncWrite + Send + 'static> TcpCommsTx<T> {
         self.tx
             .write_all(&data)
             .await
+            .map_err(|_| TcpCommsTxError::CommsError)?;
+        // Don't leave the frame sitting in a buffer, e.g. of the TLS stream
+        self.tx
+            .flush()
+            .await
             .map_err(|_| TcpCommsTxError::CommsError)
     }
 }
@@ -843,3 +3247,174 @@ impl WireSpawn for TcpSpawn {
         tokio::spawn(fut);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{duplex, AsyncWriteExt, DuplexStream};
+
+    use super::*;
+    use crate::test_util::{in_memory_pair, InMemoryServer};
+
+    /// A server, and the client end of its pipe to write raw bytes to
+    fn raw_server() -> (InMemoryServer, DuplexStream) {
+        let (client_end, server_end) = duplex(64 * 1024);
+        (InMemoryServer::new(server_end), client_end)
+    }
+
+    /// COBS encode a message, including its terminating zero
+    fn frame(msg: &[u8]) -> Vec<u8> {
+        let mut out = cobs::encode_vec(msg);
+        out.push(0);
+        out
+    }
+
+    fn device(serial: u64, is_connected: bool) -> DeviceData {
+        DeviceData {
+            serial,
+            name: "dev".into(),
+            is_connected,
+            manufacturer: None,
+            product: None,
+            last_seen: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn wait_for_connected_device() {
+        let (mut client, mut server) = in_memory_pair();
+        // A server without device events
+        client.capabilities = Some(Arc::new(Capabilities {
+            protocol_version: 0,
+            features: vec![],
+        }));
+        tokio::spawn(async move {
+            for _ in 0..2 {
+                let (hdr, ()) = server.recv_request::<GetDevicesEndpoint>().await.unwrap();
+                let devices = vec![device(1, false), device(2, true)];
+                server.respond::<GetDevicesEndpoint>(hdr, &devices).await;
+            }
+            server.recv_frame().await;
+        });
+
+        let dev = client.wait_for_device(2, Duration::from_secs(1)).await;
+        assert_eq!(dev.unwrap().serial, 2);
+        // Waiting for a device that is not connected needs device events
+        let res = client.wait_for_device(1, Duration::from_secs(1)).await;
+        assert!(matches!(res, Err(ClientError::Unsupported(_))));
+    }
+
+    #[tokio::test]
+    async fn wait_for_device_to_connect() {
+        let (client, mut server) = in_memory_pair();
+        tokio::spawn(async move {
+            for _ in 0..2 {
+                let (hdr, ()) = server.recv_request::<GetDevicesEndpoint>().await.unwrap();
+                server
+                    .respond::<GetDevicesEndpoint>(hdr, &vec![device(1, false)])
+                    .await;
+            }
+            let ev = DeviceEvent::Connected(device(1, true));
+            server.publish::<DeviceEventTopic>(0, &ev).await;
+            server.recv_frame().await;
+        });
+
+        let dev = client.wait_for_device(1, Duration::from_secs(1)).await;
+        assert_eq!(dev.unwrap().serial, 1);
+    }
+
+    #[tokio::test]
+    async fn request_round_trip() {
+        let (client, mut server) = in_memory_pair();
+        let req =
+            tokio::spawn(async move { client.raw_client().send_resp::<PingEndpoint>(&7).await });
+
+        let (hdr, body) = server.recv_request::<PingEndpoint>().await.unwrap();
+        assert_eq!(body, 7);
+        assert!(server.respond::<PingEndpoint>(hdr, &body).await);
+        assert!(matches!(req.await.unwrap(), Ok(7)));
+    }
+
+    #[tokio::test]
+    async fn bad_cobs_is_discarded() {
+        let (mut server, mut end) = raw_server();
+        // The code byte claims four data bytes, but the frame ends after one
+        let mut bytes = vec![0x05, 0x01, 0x00];
+        assert!(cobs::decode(&bytes, &mut [0; 8]).is_err());
+        bytes.extend(frame(b"good"));
+        end.write_all(&bytes).await.unwrap();
+
+        assert_eq!(server.recv_frame().await.unwrap(), b"good");
+    }
+
+    #[tokio::test]
+    async fn two_frames_in_one_read() {
+        let (mut server, mut end) = raw_server();
+        let bytes = [frame(b"one"), frame(b"two")].concat();
+        end.write_all(&bytes).await.unwrap();
+
+        assert_eq!(server.recv_frame().await.unwrap(), b"one");
+        assert_eq!(server.recv_frame().await.unwrap(), b"two");
+    }
+
+    #[tokio::test]
+    async fn three_frames_in_one_read() {
+        let (mut server, mut end) = raw_server();
+        let bytes = [frame(b"one"), frame(&[0, 1, 0]), frame(b"three")].concat();
+        end.write_all(&bytes).await.unwrap();
+
+        assert_eq!(server.recv_frame().await.unwrap(), b"one");
+        assert_eq!(server.recv_frame().await.unwrap(), [0, 1, 0]);
+        assert_eq!(server.recv_frame().await.unwrap(), b"three");
+    }
+
+    #[tokio::test]
+    async fn frame_split_across_reads() {
+        let (mut server, mut end) = raw_server();
+        let bytes = [frame(b"first"), frame(b"second")].concat();
+        let (now, later) = bytes.split_at(8);
+        end.write_all(now).await.unwrap();
+
+        assert_eq!(server.recv_frame().await.unwrap(), b"first");
+        // The start of the second frame is kept until the rest arrives
+        let res = tokio::time::timeout(Duration::from_millis(20), server.recv_frame()).await;
+        assert!(res.is_err());
+        end.write_all(later).await.unwrap();
+        assert_eq!(server.recv_frame().await.unwrap(), b"second");
+    }
+
+    #[tokio::test]
+    async fn empty_frames_are_skipped() {
+        let (mut server, mut end) = raw_server();
+        let bytes = [&[0][..], &frame(b""), &[0, 0], &frame(b"msg")].concat();
+        end.write_all(&bytes).await.unwrap();
+
+        assert_eq!(server.recv_frame().await.unwrap(), b"msg");
+    }
+
+    #[tokio::test]
+    async fn oversized_frame_is_discarded() {
+        // Just over the limit, and long enough to be dropped before its end arrives
+        for len in [MAX_FRAME_SIZE + 100, 2 * MAX_FRAME_SIZE] {
+            let (mut server, mut end) = raw_server();
+            let mut bytes = frame(b"before");
+            bytes.extend(std::iter::repeat_n(0x01, len));
+            bytes.push(0);
+            bytes.extend(frame(b"after"));
+            // The pipe is smaller than the data, so write it while receiving
+            let writer = tokio::spawn(async move { end.write_all(&bytes).await.map(|()| end) });
+
+            // Complete frames before the oversized one are kept, and only the frame
+            // after it comes out next
+            assert_eq!(server.recv_frame().await.unwrap(), b"before");
+            assert_eq!(server.recv_frame().await.unwrap(), b"after");
+            writer.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn closed_pipe_ends_receiving() {
+        let (mut server, end) = raw_server();
+        drop(end);
+        assert!(server.recv_frame().await.is_none());
+    }
+}
@@ -0,0 +1,285 @@
+//! Best-effort extraction of values from device log messages
+//!
+//! Device logs are usually formatted strings, such as
+//! `"Uptime: Duration { ticks: 1347000000 } freq: 125000000"`. [`parse_log_kv`]
+//! picks the recognizable `key: value` fragments out of these, so that numeric
+//! values can be charted without changes to the firmware.
+
+use std::{collections::BTreeMap, iter::Peekable, str::CharIndices};
+
+/// A value extracted from a log message
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    /// Any other single word or quoted string
+    Text(String),
+}
+
+impl LogValue {
+    /// The value as a float, if it is numeric
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            LogValue::Int(i) => Some(*i as f64),
+            LogValue::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+}
+
+/// Extract the `key: value` and `key = value` fragments of a log message
+///
+/// Fields of struct-like values, such as `key: Name { field: value }`, are
+/// flattened into `key.field`. A struct without a key, such as
+/// `Name { field: value }`, uses its name as the key. Anything that is not
+/// recognized is skipped.
+///
+/// For example, `"Uptime: Duration { ticks: 1347000000 } freq: 125000000"` results
+/// in `Uptime.ticks` = `Int(1347000000)` and `freq` = `Int(125000000)`.
+pub fn parse_log_kv(msg: &str) -> BTreeMap<String, LogValue> {
+    let tokens = tokenize(msg);
+    let mut out = BTreeMap::new();
+    let mut pos = 0;
+    while pos < tokens.len() {
+        pos = match (&tokens[pos], tokens.get(pos + 1)) {
+            (Token::Word(key), Some(Token::Sep)) => parse_value(&tokens, pos + 2, key, &mut out),
+            (Token::Word(key), Some(Token::Open)) => parse_fields(&tokens, pos + 2, key, &mut out),
+            _ => pos + 1,
+        };
+    }
+    out
+}
+
+enum Token {
+    Word(String),
+    Value(LogValue),
+    /// `:` or `=`
+    Sep,
+    Open,
+    Close,
+    Other,
+}
+
+/// Parse the value of `key` starting at `pos`, returning the position after it
+fn parse_value(
+    tokens: &[Token],
+    pos: usize,
+    key: &str,
+    out: &mut BTreeMap<String, LogValue>,
+) -> usize {
+    match (tokens.get(pos), tokens.get(pos + 1)) {
+        // `key: Name { ... }`
+        (Some(Token::Word(_)), Some(Token::Open)) => parse_fields(tokens, pos + 2, key, out),
+        // `key: { ... }`
+        (Some(Token::Open), _) => parse_fields(tokens, pos + 1, key, out),
+        // `key: Name` followed by another `key:`, the name is a key rather than a value
+        (Some(Token::Word(_)), Some(Token::Sep)) => pos,
+        (Some(Token::Word(w)), _) => {
+            let val = match w.as_str() {
+                "true" => LogValue::Bool(true),
+                "false" => LogValue::Bool(false),
+                _ => LogValue::Text(w.clone()),
+            };
+            out.insert(key.to_string(), val);
+            pos + 1
+        }
+        (Some(Token::Value(v)), _) => {
+            out.insert(key.to_string(), v.clone());
+            pos + 1
+        }
+        _ => pos,
+    }
+}
+
+/// Parse the fields of a struct after its `{`, returning the position after its `}`
+fn parse_fields(
+    tokens: &[Token],
+    mut pos: usize,
+    prefix: &str,
+    out: &mut BTreeMap<String, LogValue>,
+) -> usize {
+    while pos < tokens.len() {
+        pos = match (&tokens[pos], tokens.get(pos + 1)) {
+            (Token::Close, _) => return pos + 1,
+            (Token::Word(field), Some(Token::Sep)) => {
+                parse_value(tokens, pos + 2, &format!("{prefix}.{field}"), out)
+            }
+            _ => pos + 1,
+        };
+    }
+    pos
+}
+
+fn tokenize(msg: &str) -> Vec<Token> {
+    let mut chars = msg.char_indices().peekable();
+    let mut tokens = vec![];
+    while let Some(&(_, c)) = chars.peek() {
+        let tok = match c {
+            ':' | '=' => Token::Sep,
+            '{' => Token::Open,
+            '}' => Token::Close,
+            '"' => {
+                chars.next();
+                let text = take_while(&mut chars, msg, |c| c != '"');
+                chars.next();
+                tokens.push(Token::Value(LogValue::Text(text.to_string())));
+                continue;
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let text = take_while(&mut chars, msg, |c| {
+                    c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+' | '_')
+                });
+                tokens.push(parse_number(text).map_or(Token::Other, Token::Value));
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let word = take_while(&mut chars, msg, |c| c.is_alphanumeric() || c == '_');
+                tokens.push(Token::Word(word.to_string()));
+                continue;
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            _ => Token::Other,
+        };
+        chars.next();
+        tokens.push(tok);
+    }
+    tokens
+}
+
+/// Consume characters while `f` holds, returning them as a slice of `msg`
+fn take_while<'a>(
+    chars: &mut Peekable<CharIndices<'_>>,
+    msg: &'a str,
+    f: impl Fn(char) -> bool,
+) -> &'a str {
+    let start = chars.peek().map_or(msg.len(), |(i, _)| *i);
+    let mut end = start;
+    while let Some(&(i, c)) = chars.peek() {
+        if !f(c) {
+            break;
+        }
+        end = i + c.len_utf8();
+        chars.next();
+    }
+    &msg[start..end]
+}
+
+/// Parse a number, ignoring a trailing unit such as `ms`
+fn parse_number(text: &str) -> Option<LogValue> {
+    if let Some(hex) = text.strip_prefix("0x") {
+        return i64::from_str_radix(hex, 16).ok().map(LogValue::Int);
+    }
+    let end = text
+        .char_indices()
+        .find(|&(i, c)| {
+            c.is_alphabetic() && !(matches!(c, 'e' | 'E') && i > 0 && is_exponent(text, i))
+        })
+        .map_or(text.len(), |(i, _)| i);
+    let num = text[..end].trim_end_matches('.');
+    if let Ok(i) = num.parse::<i64>() {
+        return Some(LogValue::Int(i));
+    }
+    num.parse::<f64>().ok().map(LogValue::Float)
+}
+
+/// Is the `e` at `idx` the start of an exponent, like in `1.5e3`?
+fn is_exponent(text: &str, idx: usize) -> bool {
+    let rest = &text[idx + 1..];
+    let rest = rest.strip_prefix(['-', '+']).unwrap_or(rest);
+    rest.starts_with(|c: char| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kv(msg: &str) -> Vec<(String, LogValue)> {
+        parse_log_kv(msg).into_iter().collect()
+    }
+
+    fn pairs(pairs: &[(&str, LogValue)]) -> Vec<(String, LogValue)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn empty_input() {
+        assert!(kv("").is_empty());
+        assert!(kv("   ").is_empty());
+        assert!(kv("just some words").is_empty());
+    }
+
+    #[test]
+    fn typed_values() {
+        let res = kv("a: 12 b = -3 c: 1.5 d: 2e3 e: 0x1F f: true g: false h: idle i: 40ms");
+        let exp = pairs(&[
+            ("a", LogValue::Int(12)),
+            ("b", LogValue::Int(-3)),
+            ("c", LogValue::Float(1.5)),
+            ("d", LogValue::Float(2000.0)),
+            ("e", LogValue::Int(31)),
+            ("f", LogValue::Bool(true)),
+            ("g", LogValue::Bool(false)),
+            ("h", LogValue::Text("idle".into())),
+            ("i", LogValue::Int(40)),
+        ]);
+        assert_eq!(res, exp);
+    }
+
+    #[test]
+    fn quoted_values() {
+        let res = kv(r#"name: "hello world" empty: "" after: 1"#);
+        let exp = pairs(&[
+            ("after", LogValue::Int(1)),
+            ("empty", LogValue::Text("".into())),
+            ("name", LogValue::Text("hello world".into())),
+        ]);
+        assert_eq!(res, exp);
+    }
+
+    #[test]
+    fn separators_inside_values() {
+        // Quoted values are kept whole, even if they contain `=` or `:`
+        let res = kv(r#"cmd = "set x=1" url: "a:b" n=2"#);
+        let exp = pairs(&[
+            ("cmd", LogValue::Text("set x=1".into())),
+            ("n", LogValue::Int(2)),
+            ("url", LogValue::Text("a:b".into())),
+        ]);
+        assert_eq!(res, exp);
+
+        // Unquoted, the word before the `=` is taken as the next key
+        let res = kv("opt: x=1");
+        assert_eq!(res, pairs(&[("x", LogValue::Int(1))]));
+    }
+
+    #[test]
+    fn structs_are_flattened() {
+        let res = kv("Uptime: Duration { ticks: 1347000000 } freq: 125000000");
+        let exp = pairs(&[
+            ("Uptime.ticks", LogValue::Int(1347000000)),
+            ("freq", LogValue::Int(125000000)),
+        ]);
+        assert_eq!(res, exp);
+
+        let res = kv("Temp { c: 21.5, raw: { adc: 812 } }");
+        let exp = pairs(&[
+            ("Temp.c", LogValue::Float(21.5)),
+            ("Temp.raw.adc", LogValue::Int(812)),
+        ]);
+        assert_eq!(res, exp);
+    }
+
+    #[test]
+    fn unrecognized_parts_are_skipped() {
+        let res = kv("!! a: 1 ?? b: 1.2.3 c: 4");
+        let exp = pairs(&[("a", LogValue::Int(1)), ("c", LogValue::Int(4))]);
+        assert_eq!(res, exp);
+    }
+}
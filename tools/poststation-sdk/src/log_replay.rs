@@ -0,0 +1,145 @@
+//! Replaying the stored logs of a device at the pace they were created
+
+use std::{collections::VecDeque, time::Duration};
+
+use poststation_api_icd::postsock::{Anchor, Direction, Log};
+use tokio::time::Instant;
+
+use crate::{ClientError, PoststationClient};
+
+/// The number of logs requested at once by [`LogReplay`]
+const PAGE_SIZE: u32 = 64;
+
+/// The stored logs of a device, returned at the pace they were created
+///
+/// Created by [`PoststationClient::replay_device_logs`](crate::PoststationClient::replay_device_logs).
+pub struct LogReplay {
+    client: PoststationClient,
+    serial: u64,
+    /// The last log requested so far
+    anchor: Anchor,
+    speed: f32,
+    page: VecDeque<Log>,
+    /// When the first log was created, and when it was returned
+    start: Option<(u64, Instant)>,
+    done: bool,
+}
+
+impl LogReplay {
+    pub(crate) fn new(client: PoststationClient, serial: u64, from: Anchor, speed: f32) -> Self {
+        Self {
+            client,
+            serial,
+            anchor: from,
+            // Also catches NaN
+            speed: if speed > 0.0 { speed } else { f32::INFINITY },
+            page: VecDeque::new(),
+            start: None,
+            done: false,
+        }
+    }
+
+    /// Receive the next log
+    ///
+    /// Returns None once the end of the stored logs is reached. If an error is
+    /// returned, no further logs will be received. The next log is not lost if this
+    /// is cancelled while waiting, for example in a `tokio::select!`.
+    pub async fn recv(&mut self) -> Option<Result<Log, ClientError>> {
+        if self.done {
+            return None;
+        }
+        if self.page.is_empty() {
+            if let Err(e) = self.next_page().await {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+        // Only take the log after waiting, so it isn't lost if this is cancelled
+        let Some(log) = self.page.front() else {
+            self.done = true;
+            return None;
+        };
+
+        // Wait relative to the first log, so delays don't add up over the replay
+        let created = log.uuidv7.unix_ms();
+        match self.start {
+            None => self.start = Some((created, Instant::now())),
+            Some((first, started)) if self.speed.is_finite() => {
+                let secs = created.saturating_sub(first) as f64 / 1000.0 / f64::from(self.speed);
+                let until = Duration::try_from_secs_f64(secs)
+                    .ok()
+                    .and_then(|d| started.checked_add(d));
+                if let Some(until) = until {
+                    tokio::time::sleep_until(until).await;
+                }
+            }
+            Some(_) => {}
+        }
+        self.page.pop_front().map(Ok)
+    }
+
+    async fn next_page(&mut self) -> Result<(), ClientError> {
+        let mut logs = self
+            .client
+            .get_device_logs_range(self.serial, PAGE_SIZE, Direction::After, self.anchor)
+            .await?
+            .ok_or_else(|| ClientError::Server("No Device Known".into()))?;
+        logs.sort_by_key(|l| l.uuidv7.0);
+        if let Some(last) = logs.last() {
+            self.anchor = Anchor::Uuid(last.uuidv7);
+        }
+        self.page.extend(logs);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poststation_api_icd::postsock::{GetLogsRangeEndpoint, Uuidv7};
+
+    use super::*;
+    use crate::test_util::in_memory_pair;
+
+    /// A log created `ms` milliseconds after the unix epoch
+    fn log(ms: u64, msg: &str) -> Log {
+        let mut uuid = [0; 16];
+        uuid[..6].copy_from_slice(&ms.to_be_bytes()[2..]);
+        Log {
+            uuidv7: Uuidv7(uuid),
+            msg: msg.into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn cancelled_recv_keeps_the_log() {
+        let (client, mut server) = in_memory_pair();
+        tokio::spawn(async move {
+            let pages = [vec![log(1_000, "first"), log(2_000, "second")], vec![]];
+            for page in pages {
+                let (hdr, _req) = server.recv_request::<GetLogsRangeEndpoint>().await.unwrap();
+                server
+                    .respond::<GetLogsRangeEndpoint>(hdr, &Some(page))
+                    .await;
+            }
+            // Stay connected until the client is done
+            server.recv_frame().await;
+        });
+
+        // The second log is due 100ms after the first
+        let mut replay = LogReplay::new(client, 1, Anchor::EARLIEST, 10.0);
+        assert_eq!(replay.recv().await.unwrap().unwrap().msg, "first");
+        let res = tokio::time::timeout(Duration::from_millis(20), replay.recv()).await;
+        assert!(res.is_err());
+        assert_eq!(replay.recv().await.unwrap().unwrap().msg, "second");
+        assert!(replay.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn invalid_speeds_do_not_wait() {
+        for speed in [0.0, -1.0, f32::NAN] {
+            let (client, _server) = in_memory_pair();
+            let replay = LogReplay::new(client, 1, Anchor::EARLIEST, speed);
+            assert_eq!(replay.speed, f32::INFINITY);
+        }
+    }
+}
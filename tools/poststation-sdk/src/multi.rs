@@ -0,0 +1,155 @@
+//! Streaming a topic from many devices at once
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use postcard_dyn::Value;
+use poststation_api_icd::postsock::DeviceEvent;
+use tokio::{sync::mpsc, task::AbortHandle};
+
+use crate::{ClientError, DeviceEventListener, DeviceResult, PoststationClient};
+
+/// Shared between the listener and its background tasks
+struct State {
+    client: PoststationClient,
+    path: String,
+    tx: mpsc::Sender<(u64, Value)>,
+    /// The task streaming each device, by serial
+    devices: Mutex<HashMap<u64, AbortHandle>>,
+    /// The task following device events, if any
+    events: Mutex<Option<AbortHandle>>,
+}
+
+/// A merged stream of one "topic-out" path of many devices
+///
+/// Created by [`PoststationClient::stream_topic_all`]. All background tasks are
+/// stopped when the listener is dropped.
+pub struct MultiStreamListener {
+    state: Arc<State>,
+    rx: mpsc::Receiver<(u64, Value)>,
+}
+
+impl MultiStreamListener {
+    pub(crate) async fn start(
+        client: PoststationClient,
+        path: &str,
+        follow_new: bool,
+    ) -> Result<Self, ClientError> {
+        let (tx, rx) = mpsc::channel(64);
+        let state = Arc::new(State {
+            client,
+            path: path.to_string(),
+            tx,
+            devices: Mutex::new(HashMap::new()),
+            events: Mutex::new(None),
+        });
+
+        // Subscribe before listing devices, so no new device can be missed
+        let events = if follow_new {
+            Some(state.client.subscribe_device_events().await?)
+        } else {
+            None
+        };
+
+        for dev in state.client.get_devices().await? {
+            if dev.is_connected {
+                State::start_device(&state, dev.serial).await?;
+            }
+        }
+
+        if let Some(events) = events {
+            let hdl = tokio::spawn(State::follow(state.clone(), events));
+            *state.events.lock().unwrap() = Some(hdl.abort_handle());
+        }
+
+        Ok(Self { state, rx })
+    }
+
+    /// Receive a single message, along with the serial of the device that sent it
+    ///
+    /// Returns None if the connection has been closed
+    pub async fn recv(&mut self) -> Option<(u64, Value)> {
+        // The background tasks keep the channel open, so also watch the connection
+        tokio::select! {
+            msg = self.rx.recv() => msg,
+            _ = self.state.client.raw_client().wait_closed() => None,
+        }
+    }
+
+    /// The serials of the devices currently being streamed
+    pub fn serials(&self) -> Vec<u64> {
+        self.state.devices.lock().unwrap().keys().copied().collect()
+    }
+}
+
+impl State {
+    /// Start or restart streaming from a device, if it offers the path
+    async fn start_device(state: &Arc<Self>, serial: u64) -> Result<(), ClientError> {
+        let DeviceResult::Known(paths) = state.client.get_device_topic_paths(serial).await? else {
+            return Ok(());
+        };
+        if !paths.topics_out.contains(&state.path) {
+            return Ok(());
+        }
+        let mut sub = match state.client.stream_topic_json(serial, &state.path).await {
+            Ok(sub) => sub,
+            Err(e) => {
+                tracing::warn!(serial, path = state.path, error = ?e, "Failed to start stream");
+                return Ok(());
+            }
+        };
+
+        let task_state = state.clone();
+        let hdl = tokio::spawn(async move {
+            while let Some(val) = sub.recv().await {
+                if task_state.tx.send((serial, val)).await.is_err() {
+                    return;
+                }
+            }
+        });
+        // A device that reconnects has a new stream, replacing the old one
+        let old = state
+            .devices
+            .lock()
+            .unwrap()
+            .insert(serial, hdl.abort_handle());
+        if let Some(old) = old {
+            old.abort();
+        }
+        Ok(())
+    }
+
+    /// Stop streaming from a device
+    fn stop_device(&self, serial: u64) {
+        if let Some(hdl) = self.devices.lock().unwrap().remove(&serial) {
+            hdl.abort();
+        }
+    }
+
+    /// Add devices as they connect, and remove them as they disconnect
+    async fn follow(state: Arc<Self>, mut events: DeviceEventListener) {
+        while let Some(ev) = events.recv().await {
+            match ev {
+                DeviceEvent::Connected(dev) => {
+                    if let Err(e) = State::start_device(&state, dev.serial).await {
+                        tracing::warn!(serial = dev.serial, path = state.path, error = ?e, "Failed to start stream");
+                    }
+                }
+                DeviceEvent::Disconnected(serial) => state.stop_device(serial),
+            }
+        }
+    }
+}
+
+impl Drop for MultiStreamListener {
+    fn drop(&mut self) {
+        if let Some(hdl) = self.state.events.lock().unwrap().take() {
+            hdl.abort();
+        }
+        for hdl in self.state.devices.lock().unwrap().values() {
+            hdl.abort();
+        }
+    }
+}
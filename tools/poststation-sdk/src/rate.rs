@@ -0,0 +1,97 @@
+//! Limiting the rate of requests made to each device
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::ClientError;
+
+/// What to do with a request that exceeds the rate limit of its device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitMode {
+    /// Wait until the request is allowed
+    Delay,
+    /// Fail the request with [`ClientError::RateLimited`]
+    Reject,
+}
+
+/// A token bucket rate limiter for requests to each device
+///
+/// Proxy and publish requests made by a client configured with
+/// [`ConnectOptions::rate_limiter`](crate::ConnectOptions::rate_limiter) consult
+/// the limiter before being sent. Devices without a limit are not limited. Up to
+/// one second worth of requests may be sent in a burst.
+#[derive(Debug)]
+pub struct RateLimiter {
+    mode: RateLimitMode,
+    buckets: Mutex<HashMap<u64, Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    per_sec: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_sec).min(self.per_sec);
+        self.last = now;
+    }
+}
+
+impl RateLimiter {
+    pub fn new(mode: RateLimitMode) -> Self {
+        Self {
+            mode,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Limit the requests to a device to `per_sec` per second
+    ///
+    /// A limit of zero removes the limit of the device.
+    pub fn set_limit(&self, serial: u64, per_sec: u32) {
+        let mut buckets = self.buckets.lock().unwrap();
+        if per_sec == 0 {
+            buckets.remove(&serial);
+            return;
+        }
+        let per_sec = f64::from(per_sec);
+        buckets.insert(
+            serial,
+            Bucket {
+                per_sec,
+                tokens: per_sec,
+                last: Instant::now(),
+            },
+        );
+    }
+
+    /// Wait until a request to the device is allowed, or fail if rejecting
+    pub async fn acquire(&self, serial: u64) -> Result<(), ClientError> {
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().unwrap();
+                let Some(bucket) = buckets.get_mut(&serial) else {
+                    return Ok(());
+                };
+                bucket.refill();
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return Ok(());
+                }
+                if self.mode == RateLimitMode::Reject {
+                    return Err(ClientError::RateLimited);
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / bucket.per_sec)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! A record of the raw responses to proxied requests, for replaying them later
+
+use std::{collections::HashMap, sync::Mutex};
+
+/// The response of a device to a proxied request, as recorded by the client
+///
+/// See [`PoststationClient::recorded_responses`](crate::PoststationClient::recorded_responses).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedResponse {
+    pub serial: u64,
+    pub path: String,
+    /// The postcard encoded request body
+    pub request: Vec<u8>,
+    /// The postcard encoded response body
+    pub response: Vec<u8>,
+}
+
+type ResponseKey = (u64, String, Vec<u8>);
+
+/// The last response to each distinct request
+#[derive(Default)]
+pub(crate) struct ResponseRecorder {
+    responses: Mutex<HashMap<ResponseKey, Vec<u8>>>,
+}
+
+impl ResponseRecorder {
+    pub(crate) fn push(&self, serial: u64, path: &str, request: &[u8], response: &[u8]) {
+        let key = (serial, path.to_string(), request.to_vec());
+        let mut responses = self.responses.lock().unwrap();
+        responses.insert(key, response.to_vec());
+    }
+
+    pub(crate) fn get(&self, serial: u64, path: &str, request: &[u8]) -> Option<Vec<u8>> {
+        let key = (serial, path.to_string(), request.to_vec());
+        self.responses.lock().unwrap().get(&key).cloned()
+    }
+
+    pub(crate) fn records(&self) -> Vec<RecordedResponse> {
+        let responses = self.responses.lock().unwrap();
+        let mut records: Vec<_> = responses
+            .iter()
+            .map(|((serial, path, request), response)| RecordedResponse {
+                serial: *serial,
+                path: path.clone(),
+                request: request.clone(),
+                response: response.clone(),
+            })
+            .collect();
+        records
+            .sort_by(|a, b| (a.serial, &a.path, &a.request).cmp(&(b.serial, &b.path, &b.request)));
+        records
+    }
+}
@@ -0,0 +1,133 @@
+//! Convenience methods for inspecting a [`SchemaReport`]
+
+use postcard_rpc::{
+    host_client::{EndpointReport, SchemaReport, TopicReport},
+    Endpoint, Key, Topic,
+};
+use postcard_schema::schema::owned::OwnedNamedType;
+use poststation_api_icd::rest;
+
+/// Lookups and iterators over the contents of a [`SchemaReport`]
+pub trait SchemaReportExt {
+    /// Find an endpoint by path
+    fn endpoint_by_path(&self, path: &str) -> Option<&EndpointReport>;
+    /// Find an incoming (client to server) topic by path
+    fn topic_in_by_path(&self, path: &str) -> Option<&TopicReport>;
+    /// Find an outgoing (server to client) topic by path
+    fn topic_out_by_path(&self, path: &str) -> Option<&TopicReport>;
+
+    /// Find the endpoint matching the path and keys of `E`
+    fn endpoint_for<E: Endpoint>(&self) -> Option<&EndpointReport>;
+    /// Find the incoming topic matching the path and key of `T`
+    fn topic_in_for<T: Topic>(&self) -> Option<&TopicReport>;
+    /// Find the outgoing topic matching the path and key of `T`
+    fn topic_out_for<T: Topic>(&self) -> Option<&TopicReport>;
+
+    /// Iterate over the `(path, req_key, resp_key)` of all endpoints
+    fn endpoints_iter(&self) -> impl Iterator<Item = (&str, Key, Key)>;
+    /// Iterate over the `(path, key, ty)` of all incoming topics
+    fn topics_in_iter(&self) -> impl Iterator<Item = (&str, Key, &OwnedNamedType)>;
+    /// Iterate over the `(path, key, ty)` of all outgoing topics
+    fn topics_out_iter(&self) -> impl Iterator<Item = (&str, Key, &OwnedNamedType)>;
+
+    /// Convert to the JSON friendly report used by the REST API
+    ///
+    /// This is the same as `report.clone().into()`.
+    fn to_rest(&self) -> rest::foreign::SchemaReport;
+}
+
+impl SchemaReportExt for SchemaReport {
+    fn endpoint_by_path(&self, path: &str) -> Option<&EndpointReport> {
+        self.endpoints.iter().find(|e| e.path == path)
+    }
+
+    fn topic_in_by_path(&self, path: &str) -> Option<&TopicReport> {
+        self.topics_in.iter().find(|t| t.path == path)
+    }
+
+    fn topic_out_by_path(&self, path: &str) -> Option<&TopicReport> {
+        self.topics_out.iter().find(|t| t.path == path)
+    }
+
+    // TODO: Don't compare the types because the names don't match even though we've
+    // type-punned
+    fn endpoint_for<E: Endpoint>(&self) -> Option<&EndpointReport> {
+        self.endpoints
+            .iter()
+            .find(|e| e.path == E::PATH && e.req_key == E::REQ_KEY && e.resp_key == E::RESP_KEY)
+    }
+
+    fn topic_in_for<T: Topic>(&self) -> Option<&TopicReport> {
+        self.topics_in
+            .iter()
+            .find(|t| t.path == T::PATH && t.key == T::TOPIC_KEY)
+    }
+
+    fn topic_out_for<T: Topic>(&self) -> Option<&TopicReport> {
+        self.topics_out
+            .iter()
+            .find(|t| t.path == T::PATH && t.key == T::TOPIC_KEY)
+    }
+
+    fn endpoints_iter(&self) -> impl Iterator<Item = (&str, Key, Key)> {
+        self.endpoints
+            .iter()
+            .map(|e| (e.path.as_str(), e.req_key, e.resp_key))
+    }
+
+    fn topics_in_iter(&self) -> impl Iterator<Item = (&str, Key, &OwnedNamedType)> {
+        self.topics_in
+            .iter()
+            .map(|t| (t.path.as_str(), t.key, &t.ty))
+    }
+
+    fn topics_out_iter(&self) -> impl Iterator<Item = (&str, Key, &OwnedNamedType)> {
+        self.topics_out
+            .iter()
+            .map(|t| (t.path.as_str(), t.key, &t.ty))
+    }
+
+    fn to_rest(&self) -> rest::foreign::SchemaReport {
+        self.clone().into()
+    }
+}
+
+/// An order-independent fingerprint of a [`SchemaReport`]
+///
+/// Two reports containing the same types, endpoints, and topics have the same
+/// fingerprint, regardless of the order they are listed in. This is cheap to
+/// compare, and stable across runs and versions of this crate, so it can be stored
+/// as a baseline to detect when the interface of a device has changed.
+pub fn schema_fingerprint(report: &SchemaReport) -> u64 {
+    // Encode every entry, and sort them to remove any dependence on ordering
+    let mut entries = report
+        .types
+        .iter()
+        .map(|t| (0u8, postcard::to_stdvec(t)))
+        .chain(report.endpoints.iter().map(|e| (1, postcard::to_stdvec(e))))
+        .chain(report.topics_in.iter().map(|t| (2, postcard::to_stdvec(t))))
+        .chain(
+            report
+                .topics_out
+                .iter()
+                .map(|t| (3, postcard::to_stdvec(t))),
+        )
+        .map(|(kind, bytes)| (kind, bytes.unwrap_or_default()))
+        .collect::<Vec<_>>();
+    entries.sort_unstable();
+
+    // 64-bit FNV-1a, which unlike `std`'s hashers is guaranteed to stay the same
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    let mut write = |bytes: &[u8]| {
+        for b in bytes {
+            hash ^= u64::from(*b);
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    };
+    for (kind, bytes) in entries.iter() {
+        write(&[*kind]);
+        write(&(bytes.len() as u64).to_le_bytes());
+        write(bytes);
+    }
+    hash
+}
@@ -0,0 +1,285 @@
+//! A client for the REST flavored API of poststation
+//!
+//! This is an alternative to [`PoststationClient`](crate::PoststationClient) for
+//! environments where the REST port of poststation is reachable, but the socket
+//! port is not. The methods mirror the ones of the socket client where possible,
+//! but use the JSON friendly types of [`icd::rest`](crate::icd::rest).
+//!
+//! Requires the `rest-client` feature.
+
+use futures_util::StreamExt;
+use postcard_dyn::Value;
+use poststation_api_icd::rest::{
+    foreign::SchemaReport, ApiDescriptor, DeviceData, DeviceQuery, IntEncoding, Log, ProxyRequest,
+    ProxyResponseError, ProxyResponseOk, PublishRequest,
+};
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+use crate::{ClientError, DeviceResult};
+
+/// A client for the REST API of a poststation server
+#[derive(Clone)]
+pub struct RestClient {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl RestClient {
+    /// Create a new client for the server at the given base url, e.g. `http://localhost:4444`
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub async fn get_devices(&self) -> Result<Vec<DeviceData>, ClientError> {
+        let url = format!("{}/api/devices", self.base_url);
+        self.get_json(&url, &()).await
+    }
+
+    /// Like [`Self::get_devices`], with the list filtered and sorted by the server
+    pub async fn get_devices_filtered(
+        &self,
+        query: &DeviceQuery,
+    ) -> Result<Vec<DeviceData>, ClientError> {
+        let url = format!("{}/api/devices", self.base_url);
+        self.get_json(&url, query).await
+    }
+
+    /// Get the listing of all routes of the server's REST API
+    pub async fn get_api_schema(&self) -> Result<ApiDescriptor, ClientError> {
+        let url = format!("{}/api/schema", self.base_url);
+        self.get_json(&url, &()).await
+    }
+
+    pub async fn get_device_schemas(
+        &self,
+        serial: u64,
+    ) -> Result<DeviceResult<SchemaReport>, ClientError> {
+        let url = format!("{}/api/devices/{serial:016X}/schemas", self.base_url);
+        let res = self.get_json_opt(&url, &()).await?;
+        Ok(res.into())
+    }
+
+    pub async fn get_device_logs(
+        &self,
+        serial: u64,
+        count: u32,
+    ) -> Result<DeviceResult<Vec<Log>>, ClientError> {
+        let url = format!("{}/api/devices/{serial:016X}/logs", self.base_url);
+        let res = self
+            .get_json_opt(&url, &[("count", count.to_string())])
+            .await?;
+        Ok(res.into())
+    }
+
+    pub async fn proxy_endpoint_json(
+        &self,
+        serial: u64,
+        path: &str,
+        seq_no: u32,
+        body: Value,
+    ) -> Result<Value, ClientError> {
+        let DeviceResult::Known(schemas) = self.get_device_schemas(serial).await? else {
+            return Err(ClientError::Server("endpoint not found".into()));
+        };
+
+        // find key
+        let res = schemas.endpoints.iter().find(|e| e.path.as_str() == path);
+        let Some(schema) = res else {
+            return Err(ClientError::Server("endpoint not found".into()));
+        };
+
+        let url = format!("{}/api/devices/{serial:016X}/proxy", self.base_url);
+        let resp = self
+            .http
+            .post(&url)
+            .json(&ProxyRequest {
+                path: schema.path.clone(),
+                req_key: schema.req_key.clone(),
+                resp_key: schema.resp_key.clone(),
+                seq_no,
+                body,
+                deadline_ms: None,
+                int_encoding: IntEncoding::Number,
+            })
+            .send()
+            .await
+            .map_err(http_err)?;
+
+        if resp.status().is_success() {
+            let resp: ProxyResponseOk = resp.json().await.map_err(http_err)?;
+            return Ok(resp.body);
+        }
+
+        // poststation to remote comms
+        match resp.json::<ProxyResponseError>().await {
+            Ok(ProxyResponseError::WireErr { body, .. }) => {
+                Err(ClientError::RemoteWire(body.into()))
+            }
+            Ok(ProxyResponseError::OtherErr(e)) => {
+                Err(ClientError::Remote(format!("Other Server Err: '{e}'")))
+            }
+            Ok(ProxyResponseError::Timeout) => Err(ClientError::Timeout),
+            Err(e) => Err(http_err(e)),
+        }
+    }
+
+    pub async fn publish_topic_json(
+        &self,
+        serial: u64,
+        path: &str,
+        seq_no: u32,
+        body: Value,
+    ) -> Result<(), ClientError> {
+        let DeviceResult::Known(schemas) = self.get_device_schemas(serial).await? else {
+            return Err(ClientError::Server("topic not found".into()));
+        };
+
+        // find key
+        let res = schemas.topics_in.iter().find(|e| e.path.as_str() == path);
+        let Some(schema) = res else {
+            return Err(ClientError::Server("topic not found".into()));
+        };
+
+        let url = format!("{}/api/devices/{serial:016X}/publish", self.base_url);
+        let resp = self
+            .http
+            .post(&url)
+            .json(&PublishRequest {
+                path: schema.path.clone(),
+                topic_key: schema.key.clone(),
+                seq_no,
+                body,
+            })
+            .send()
+            .await
+            .map_err(http_err)?;
+
+        if !resp.status().is_success() {
+            let text = resp.text().await.map_err(http_err)?;
+            return Err(ClientError::Server(text));
+        }
+        Ok(())
+    }
+
+    /// Listen to a given topic path, receiving a subscription that yields live messages
+    pub async fn stream_topic_json(
+        &self,
+        serial: u64,
+        path: &str,
+    ) -> Result<RestStreamListener, ClientError> {
+        let DeviceResult::Known(schemas) = self.get_device_schemas(serial).await? else {
+            return Err(ClientError::Server("topic not found".into()));
+        };
+
+        // find key
+        let res = schemas.topics_out.iter().find(|e| e.path.as_str() == path);
+        let Some(schema) = res else {
+            return Err(ClientError::Server("topic not found".into()));
+        };
+
+        let base = if let Some(rest) = self.base_url.strip_prefix("https://") {
+            format!("wss://{rest}")
+        } else if let Some(rest) = self.base_url.strip_prefix("http://") {
+            format!("ws://{rest}")
+        } else {
+            self.base_url.clone()
+        };
+        let mut url = reqwest::Url::parse(&format!("{base}/api/devices/{serial:016X}/listen"))
+            .map_err(|e| ClientError::Server(format!("Invalid url: '{e}'")))?;
+        // The key is serialized as a plain hex string
+        let key = serde_json::to_value(&schema.key).map_err(|_| ClientError::Encoding)?;
+        url.query_pairs_mut()
+            .append_pair("path", path)
+            .append_pair("key", key.as_str().unwrap_or_default());
+
+        let (ws, _resp) = tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .map_err(|e| ClientError::Server(format!("WebSocket error: '{e}'")))?;
+
+        Ok(RestStreamListener { ws })
+    }
+
+    async fn get_json<T: DeserializeOwned, Q: Serialize + ?Sized>(
+        &self,
+        url: &str,
+        query: &Q,
+    ) -> Result<T, ClientError> {
+        let resp = self
+            .http
+            .get(url)
+            .query(query)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(http_err)?;
+        resp.json().await.map_err(http_err)
+    }
+
+    /// Like `get_json`, but a "Not Found" response is returned as `None`
+    async fn get_json_opt<T: DeserializeOwned, Q: Serialize + ?Sized>(
+        &self,
+        url: &str,
+        query: &Q,
+    ) -> Result<Option<T>, ClientError> {
+        let resp = self
+            .http
+            .get(url)
+            .query(query)
+            .send()
+            .await
+            .map_err(http_err)?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let resp = resp.error_for_status().map_err(http_err)?;
+        resp.json().await.map(Some).map_err(http_err)
+    }
+}
+
+fn http_err(e: reqwest::Error) -> ClientError {
+    if e.is_connect() {
+        ClientError::ConnectionClosed
+    } else if e.is_decode() {
+        ClientError::Encoding
+    } else {
+        ClientError::Server(e.to_string())
+    }
+}
+
+/// A live subscription to a topic over the REST API's WebSocket endpoint
+pub struct RestStreamListener {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl RestStreamListener {
+    /// Receive a single message from this subscription
+    ///
+    /// Returns None if the connection has been closed
+    pub async fn recv(&mut self) -> Option<Value> {
+        loop {
+            let msg = match self.ws.next().await? {
+                Ok(Message::Text(t)) => t,
+                Ok(Message::Close(_)) => return None,
+                Ok(_) => continue,
+                Err(e) => {
+                    tracing::warn!(error = ?e, "WebSocket closed");
+                    return None;
+                }
+            };
+
+            let Ok(mut msg) = serde_json::from_str::<Value>(&msg) else {
+                continue;
+            };
+            let Some(msg) = msg.get_mut("msg").map(Value::take) else {
+                continue;
+            };
+            return Some(msg);
+        }
+    }
+}
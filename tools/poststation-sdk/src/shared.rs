@@ -0,0 +1,110 @@
+//! Sharing a single topic subscription between multiple consumers
+
+use std::future::Future;
+
+use postcard_dyn::Value;
+use postcard_rpc::Topic;
+use serde::de::DeserializeOwned;
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    task::AbortHandle,
+};
+
+use crate::{JsonStreamListener, StreamListener};
+
+/// The number of messages buffered for each consumer of a [`SharedStream`]
+///
+/// Consumers that fall further behind than this miss messages.
+pub const SHARED_STREAM_CAPACITY: usize = 64;
+
+/// A single topic subscription, fanned out to any number of consumers
+///
+/// Created from a [`StreamListener`] or [`JsonStreamListener`] with `.into()`. Each
+/// consumer created with [`Self::subscribe`] receives its own copy of every message
+/// received after it subscribed, without opening another stream on the server.
+///
+/// The subscription is closed once the `SharedStream` is dropped, after which all
+/// consumers receive `None`.
+pub struct SharedStream<T> {
+    tx: broadcast::Sender<T>,
+    task: AbortHandle,
+}
+
+/// One consumer of a [`SharedStream`]
+pub struct SharedStreamReceiver<T> {
+    rx: broadcast::Receiver<T>,
+}
+
+impl<T: Clone + Send + 'static> SharedStream<T> {
+    fn spawn<F, Fut>(feed: F) -> Self
+    where
+        F: FnOnce(broadcast::Sender<T>) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (tx, _) = broadcast::channel(SHARED_STREAM_CAPACITY);
+        let task = tokio::spawn(feed(tx.clone())).abort_handle();
+        Self { tx, task }
+    }
+
+    /// Add a consumer, receiving all messages from now on
+    pub fn subscribe(&self) -> SharedStreamReceiver<T> {
+        SharedStreamReceiver {
+            rx: self.tx.subscribe(),
+        }
+    }
+
+    /// The number of consumers currently subscribed
+    pub fn receiver_count(&self) -> usize {
+        self.tx.receiver_count()
+    }
+}
+
+impl<T> Drop for SharedStream<T> {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl<T> From<StreamListener<T>> for SharedStream<T::Message>
+where
+    T: Topic + 'static,
+    T::Message: DeserializeOwned + Clone + Send + 'static,
+{
+    fn from(mut listener: StreamListener<T>) -> Self {
+        SharedStream::spawn(|tx| async move {
+            while let Some(msg) = listener.recv().await {
+                // Only fails if there are no consumers right now
+                let _ = tx.send(msg);
+            }
+        })
+    }
+}
+
+impl From<JsonStreamListener> for SharedStream<Value> {
+    fn from(mut listener: JsonStreamListener) -> Self {
+        SharedStream::spawn(|tx| async move {
+            while let Some(msg) = listener.recv().await {
+                // Only fails if there are no consumers right now
+                let _ = tx.send(msg);
+            }
+        })
+    }
+}
+
+impl<T: Clone> SharedStreamReceiver<T> {
+    /// Receive the next message
+    ///
+    /// Returns None if the subscription has been closed
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            match self.rx.recv().await {
+                Ok(msg) => return Some(msg),
+                Err(RecvError::Closed) => return None,
+                Err(RecvError::Lagged(n)) => {
+                    tracing::warn!(lags = n, "Shared stream lagged");
+                    continue;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Stopping the server side of topic streams once their listeners are dropped
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use postcard_rpc::{
+    host_client::{HostClient, HostErr},
+    standard_icd::WireError,
+};
+use poststation_api_icd::postsock::{StopStreamEndpoint, Uuidv7};
+use tokio::sync::mpsc;
+
+use crate::ClientError;
+
+/// Sends [`StopStreamEndpoint`] requests for the streams of dropped listeners
+///
+/// `Drop` can't be async, so listeners only queue the ID of their stream, and a
+/// background task sends the requests. The task ends once the client and all of
+/// its listeners have been dropped, or the connection is closed.
+///
+/// This also counts the streams that have been started and not yet stopped.
+#[derive(Clone)]
+pub(crate) struct StreamStopper {
+    tx: mpsc::UnboundedSender<Uuidv7>,
+    active: Arc<AtomicUsize>,
+    /// See [`ConnectOptions::max_streams`](crate::ConnectOptions::max_streams)
+    max: Option<usize>,
+}
+
+impl StreamStopper {
+    pub(crate) fn new(client: HostClient<WireError>, max: Option<usize>) -> Self {
+        let (tx, mut rx) = mpsc::unbounded_channel::<Uuidv7>();
+        tokio::spawn(async move {
+            while let Some(stream_id) = rx.recv().await {
+                match client.send_resp::<StopStreamEndpoint>(&stream_id).await {
+                    Ok(()) => {}
+                    Err(HostErr::Closed) => return,
+                    Err(e) => tracing::warn!(?stream_id, error = ?e, "Failed to stop stream"),
+                }
+            }
+        });
+        Self {
+            tx,
+            active: Default::default(),
+            max,
+        }
+    }
+
+    /// Count `n` streams about to be started, unless that would exceed the maximum
+    ///
+    /// Streams that then fail to start must be given back with [`Self::release`].
+    pub(crate) fn reserve(&self, n: usize) -> Result<(), ClientError> {
+        self.active
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |active| {
+                let active = active.checked_add(n)?;
+                self.max.is_none_or(|max| active <= max).then_some(active)
+            })
+            .map(|_| ())
+            .map_err(|_| ClientError::TooManyStreams)
+    }
+
+    /// Stop counting `n` streams
+    pub(crate) fn release(&self, n: usize) {
+        self.active.fetch_sub(n, Ordering::Relaxed);
+    }
+
+    /// The number of streams started and not yet stopped
+    pub(crate) fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Queue a request to stop the given stream
+    pub(crate) fn stop(&self, stream_id: Uuidv7) {
+        self.release(1);
+        self.stop_uncounted(stream_id);
+    }
+
+    /// Like [`Self::stop`], for a stream that was never counted
+    pub(crate) fn stop_uncounted(&self, stream_id: Uuidv7) {
+        // The connection is gone if the task has ended, which also ends the stream
+        let _ = self.tx.send(stream_id);
+    }
+
+    /// Stop the given stream once the returned guard is dropped
+    pub(crate) fn guard(&self, stream_id: Uuidv7) -> StopOnDrop {
+        StopOnDrop {
+            stopper: self.clone(),
+            stream_id,
+        }
+    }
+}
+
+/// Stops a stream when dropped, see [`StreamStopper::guard`]
+pub(crate) struct StopOnDrop {
+    stopper: StreamStopper,
+    stream_id: Uuidv7,
+}
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.stopper.stop(self.stream_id);
+    }
+}
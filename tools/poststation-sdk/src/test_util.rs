@@ -0,0 +1,151 @@
+//! An in-memory transport, for testing without a poststation server
+//!
+//! [`in_memory_pair`] returns a [`PoststationClient`] connected to an
+//! [`InMemoryServer`], which can be used to play the part of poststation in tests
+//! by receiving the client's requests and sending back responses.
+//!
+//! Requires the `test-util` feature.
+
+use std::net::{Ipv4Addr, SocketAddr};
+
+use postcard_rpc::{
+    header::{VarHeader, VarKey},
+    Endpoint, Topic,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::{duplex, split, DuplexStream};
+
+use crate::{stop::StreamStopper, ConnectOptions, PoststationClient, TcpCommsRx, TcpCommsTx};
+
+/// The size of the in-memory pipe in each direction
+const PIPE_SIZE: usize = 64 * 1024;
+
+/// Create a client connected to an in-memory fake server
+///
+/// Unlike the `connect` functions, this does not check that the server responds
+/// to pings, or query its capabilities. Must be called from within a tokio runtime.
+pub fn in_memory_pair() -> (PoststationClient, InMemoryServer) {
+    let (client_end, server_end) = duplex(PIPE_SIZE);
+    (
+        connect_in_memory(client_end),
+        InMemoryServer::new(server_end),
+    )
+}
+
+/// Create a client that communicates over one end of a [`tokio::io::duplex`] pipe
+///
+/// The other end must be served by something that speaks the poststation socket
+/// protocol, for example an [`InMemoryServer`]. Unlike the `connect` functions, this
+/// does not check that the server responds to pings, or query its capabilities. Must
+/// be called from within a tokio runtime.
+pub fn connect_in_memory(stream: DuplexStream) -> PoststationClient {
+    let (client, state, in_flight) =
+        ConnectOptions::default().new_host_client(stream, in_memory_addr());
+    PoststationClient {
+        stream_stopper: StreamStopper::new(client.clone(), None),
+        client,
+        capabilities: None,
+        history: None,
+        responses: None,
+        seq_nos: Default::default(),
+        rate_limiter: None,
+        state,
+        in_flight,
+        schema_fetches: Default::default(),
+        trust_mode: Default::default(),
+    }
+}
+
+/// The server side of an in-memory connection
+///
+/// Frames are COBS encoded and decoded exactly as they are on a TCP connection.
+pub struct InMemoryServer {
+    rx: TcpCommsRx<DuplexStream>,
+    tx: TcpCommsTx<DuplexStream>,
+}
+
+impl InMemoryServer {
+    /// Serve one end of a [`tokio::io::duplex`] pipe
+    pub fn new(stream: DuplexStream) -> Self {
+        let (rx, tx) = split(stream);
+        Self {
+            rx: TcpCommsRx::new(rx, in_memory_addr(), 1024),
+            tx: TcpCommsTx { tx },
+        }
+    }
+
+    /// Receive a single decoded frame, including its header
+    ///
+    /// Returns None if the client has disconnected
+    pub async fn recv_frame(&mut self) -> Option<Vec<u8>> {
+        self.rx.receive_inner().await.ok()
+    }
+
+    /// Send a single frame, including its header
+    ///
+    /// Returns false if the client has disconnected
+    pub async fn send_frame(&mut self, frame: Vec<u8>) -> bool {
+        self.tx.send_inner(frame).await.is_ok()
+    }
+
+    /// Receive a request for the endpoint `E`
+    ///
+    /// Returns None if the client has disconnected, or the next frame is not a
+    /// request for `E`.
+    pub async fn recv_request<E>(&mut self) -> Option<(VarHeader, E::Request)>
+    where
+        E: Endpoint,
+        E::Request: DeserializeOwned,
+    {
+        let frame = self.recv_frame().await?;
+        let (hdr, body) = VarHeader::take_from_slice(&frame)?;
+        if hdr.key != VarKey::Key8(E::REQ_KEY) {
+            return None;
+        }
+        let req = postcard::from_bytes(body).ok()?;
+        Some((hdr, req))
+    }
+
+    /// Respond to a request for the endpoint `E` received with the given header
+    ///
+    /// Returns false if the client has disconnected
+    pub async fn respond<E>(&mut self, req_hdr: VarHeader, resp: &E::Response) -> bool
+    where
+        E: Endpoint,
+        E::Response: Serialize,
+    {
+        let hdr = VarHeader {
+            key: VarKey::Key8(E::RESP_KEY),
+            seq_no: req_hdr.seq_no,
+        };
+        self.send_with_header(hdr, resp).await
+    }
+
+    /// Publish a message on the topic `T`
+    ///
+    /// Returns false if the client has disconnected
+    pub async fn publish<T>(&mut self, seq_no: u32, msg: &T::Message) -> bool
+    where
+        T: Topic,
+        T::Message: Serialize,
+    {
+        let hdr = VarHeader {
+            key: VarKey::Key8(T::TOPIC_KEY),
+            seq_no: seq_no.into(),
+        };
+        self.send_with_header(hdr, msg).await
+    }
+
+    async fn send_with_header<M: Serialize + ?Sized>(&mut self, hdr: VarHeader, msg: &M) -> bool {
+        let mut frame = hdr.write_to_vec();
+        let Ok(body) = postcard::to_stdvec(msg) else {
+            return false;
+        };
+        frame.extend_from_slice(&body);
+        self.send_frame(frame).await
+    }
+}
+
+fn in_memory_addr() -> SocketAddr {
+    SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0)
+}
@@ -0,0 +1,412 @@
+//! Encoding postcard messages directly to self-describing formats
+//!
+//! The message is walked along the schema prepared by a [`DynDecoder`], and fed to the
+//! serializer of the target format without building a [`Value`](postcard_dyn::Value)
+//! first.
+
+use std::cell::Cell;
+
+use postcard_schema::schema::owned::OwnedNamedType;
+use serde::{
+    ser::{Error as _, SerializeMap, SerializeSeq, SerializeTuple},
+    Serialize, Serializer,
+};
+
+use crate::{
+    decode::{
+        take_len, take_n, take_one, take_seq_len, take_str, take_varint, zig_zag, DecodeError, Node,
+    },
+    ClientError, DynDecoder,
+};
+
+/// A self-describing format messages can be encoded to, see [`encode_topic_raw`]
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueFormat {
+    Json,
+    /// Requires the `cbor` feature
+    #[cfg(feature = "cbor")]
+    Cbor,
+    /// Requires the `msgpack` feature
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+}
+
+/// Encode a raw, postcard encoded message to the given format, using the given type
+///
+/// The output has the shape of the values of [`decode_topic_raw`](crate::decode_topic_raw):
+/// unit variants become strings, other variants an object with one field, and single
+/// item tuples are not wrapped in an array. It is not identical, though:
+///
+/// * Struct fields are kept in declaration order, instead of being sorted by name
+/// * `f32`s are encoded as `f32`s, so `0.1f32` is `0.1` in JSON rather than
+///   `0.10000000149011612`
+/// * Floats JSON can't represent, like NaN, are `null` in JSON rather than an error
+/// * Integers wider than 64 bits, `char`s, and maps with non-string keys are encoded
+///   rather than rejected. In JSON, integer map keys become strings, and other
+///   non-string keys are an error
+/// * Byte arrays are encoded as bytes by formats that have them
+///
+/// Like [`decode_topic_raw`](crate::decode_topic_raw), this does not need a
+/// connection to the server. To encode many messages of the same type, use
+/// [`DynDecoder::encode`] instead.
+pub fn encode_topic_raw(
+    ty: &OwnedNamedType,
+    bytes: &[u8],
+    format: ValueFormat,
+) -> Result<Vec<u8>, ClientError> {
+    DynDecoder::new(ty).encode(bytes, format)
+}
+
+pub(crate) fn encode(
+    node: &Node,
+    bytes: &[u8],
+    format: ValueFormat,
+) -> Result<Vec<u8>, ClientError> {
+    let data = Cell::new(bytes);
+    let val = Dyn { node, data: &data };
+    let res = match format {
+        ValueFormat::Json => serde_json::to_vec(&val).map_err(|e| e.to_string()),
+        #[cfg(feature = "cbor")]
+        ValueFormat::Cbor => {
+            let mut out = vec![];
+            ciborium::into_writer(&val, &mut out)
+                .map(|()| out)
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(feature = "msgpack")]
+        ValueFormat::MessagePack => rmp_serde::to_vec(&val).map_err(|e| e.to_string()),
+    };
+    res.map_err(|e| ClientError::Dynamic(format!("Encode error: '{e}'")))
+}
+
+/// A value of the type of `node`, read from the front of `data` when serialized
+struct Dyn<'a, 'b> {
+    node: &'a Node,
+    data: &'b Cell<&'b [u8]>,
+}
+
+impl<'b> Dyn<'_, 'b> {
+    fn of<'a>(&self, node: &'a Node) -> Dyn<'a, 'b> {
+        Dyn {
+            node,
+            data: self.data,
+        }
+    }
+
+    /// Take a value from the front of the data with one of the decoder's helpers
+    fn take<T>(
+        &self,
+        f: impl FnOnce(&'b [u8]) -> Result<(T, &'b [u8]), DecodeError>,
+    ) -> Result<T, DecodeError> {
+        let (val, rest) = f(self.data.get())?;
+        self.data.set(rest);
+        Ok(val)
+    }
+}
+
+impl Serialize for Dyn<'_, '_> {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        let err = |e: DecodeError| S::Error::custom(format!("{e:?}"));
+        let mismatch = || err(DecodeError::SchemaMismatch);
+        match self.node {
+            Node::Bool => match self.take(take_one).map_err(err)? {
+                0 => ser.serialize_bool(false),
+                1 => ser.serialize_bool(true),
+                _ => Err(mismatch()),
+            },
+            Node::I8 => ser.serialize_i8(self.take(take_one).map_err(err)? as i8),
+            Node::U8 => ser.serialize_u8(self.take(take_one).map_err(err)?),
+            Node::I16 => {
+                let val = self.take(|d| take_varint(d, 16)).map_err(err)?;
+                ser.serialize_i16(zig_zag(val) as i16)
+            }
+            Node::I32 => {
+                let val = self.take(|d| take_varint(d, 32)).map_err(err)?;
+                ser.serialize_i32(zig_zag(val) as i32)
+            }
+            Node::I64 => {
+                let val = self.take(|d| take_varint(d, 64)).map_err(err)?;
+                ser.serialize_i64(zig_zag(val) as i64)
+            }
+            Node::Isize => {
+                let val = self.take(|d| take_varint(d, usize::BITS)).map_err(err)?;
+                ser.serialize_i64(zig_zag(val) as i64)
+            }
+            Node::I128 => {
+                let val = self.take(|d| take_varint(d, 128)).map_err(err)?;
+                ser.serialize_i128(zig_zag(val))
+            }
+            Node::U16 => {
+                let val = self.take(|d| take_varint(d, 16)).map_err(err)?;
+                ser.serialize_u16(val as u16)
+            }
+            Node::U32 => {
+                let val = self.take(|d| take_varint(d, 32)).map_err(err)?;
+                ser.serialize_u32(val as u32)
+            }
+            Node::U64 => {
+                let val = self.take(|d| take_varint(d, 64)).map_err(err)?;
+                ser.serialize_u64(val as u64)
+            }
+            Node::Usize => {
+                let val = self.take(|d| take_varint(d, usize::BITS)).map_err(err)?;
+                ser.serialize_u64(val as u64)
+            }
+            Node::U128 => {
+                let val = self.take(|d| take_varint(d, 128)).map_err(err)?;
+                ser.serialize_u128(val)
+            }
+            Node::F32 => {
+                let bytes = self.take(|d| take_n(d, 4)).map_err(err)?;
+                ser.serialize_f32(f32::from_le_bytes(bytes.try_into().unwrap()))
+            }
+            Node::F64 => {
+                let bytes = self.take(|d| take_n(d, 8)).map_err(err)?;
+                ser.serialize_f64(f64::from_le_bytes(bytes.try_into().unwrap()))
+            }
+            Node::Char => {
+                let s = self.take(take_str).map_err(err)?;
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => ser.serialize_char(c),
+                    _ => Err(mismatch()),
+                }
+            }
+            Node::String => ser.serialize_str(self.take(take_str).map_err(err)?),
+            Node::ByteArray => {
+                let len = self.take(take_len).map_err(err)?;
+                ser.serialize_bytes(self.take(|d| take_n(d, len)).map_err(err)?)
+            }
+            Node::Option(node) => match self.take(take_one).map_err(err)? {
+                0 => ser.serialize_none(),
+                1 => ser.serialize_some(&self.of(node)),
+                _ => Err(mismatch()),
+            },
+            Node::Unit => ser.serialize_unit(),
+            Node::Seq(node) => {
+                let len = self
+                    .take(|d| take_seq_len(d, node.min_size()))
+                    .map_err(err)?;
+                let mut seq = ser.serialize_seq(Some(len))?;
+                for _ in 0..len {
+                    seq.serialize_element(&self.of(node))?;
+                }
+                seq.end()
+            }
+            Node::Tuple(nodes) => {
+                let mut tup = ser.serialize_tuple(nodes.len())?;
+                for node in nodes {
+                    tup.serialize_element(&self.of(node))?;
+                }
+                tup.end()
+            }
+            Node::Map { key, val } => {
+                let item_size = key.min_size() + val.min_size();
+                let len = self.take(|d| take_seq_len(d, item_size)).map_err(err)?;
+                let mut map = ser.serialize_map(Some(len))?;
+                for _ in 0..len {
+                    map.serialize_entry(&self.of(key), &self.of(val))?;
+                }
+                map.end()
+            }
+            Node::Struct(fields) => {
+                let mut map = ser.serialize_map(Some(fields.len()))?;
+                for (name, node) in fields {
+                    map.serialize_entry(name, &self.of(node))?;
+                }
+                map.end()
+            }
+            Node::Enum(variants) => {
+                let idx = self.take(take_len).map_err(err)?;
+                let (name, node) = variants.get(idx).ok_or_else(mismatch)?;
+                // Unit variants become strings, all others an object with one field
+                let Some(node) = node else {
+                    return ser.serialize_str(name);
+                };
+                let mut map = ser.serialize_map(Some(1))?;
+                map.serialize_entry(name, &self.of(node))?;
+                map.end()
+            }
+            Node::Schema => Err(S::Error::custom("schemas are not supported")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use postcard_schema::{
+        schema::owned::{OwnedDataModelType, OwnedNamedType},
+        Schema,
+    };
+    use serde::Serialize;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    #[derive(Serialize, Schema)]
+    struct Reading {
+        name: String,
+        temps: Vec<f32>,
+        pos: (i32, i32),
+        kind: Kind,
+        extra: Option<(u8,)>,
+    }
+
+    #[derive(Serialize, Schema)]
+    enum Kind {
+        Unit,
+        Newtype(u8),
+        Tuple(u8, String),
+        Struct { a: i64, b: Option<u8> },
+    }
+
+    fn reading(kind: Kind) -> Reading {
+        Reading {
+            name: "probe".into(),
+            temps: vec![20.5, -3.0],
+            pos: (-1, 1),
+            kind,
+            extra: Some((7,)),
+        }
+    }
+
+    fn encode<T: Serialize + Schema>(val: &T, format: ValueFormat) -> Result<Vec<u8>, ClientError> {
+        let ty = T::SCHEMA.into();
+        encode_topic_raw(&ty, &postcard::to_stdvec(val).unwrap(), format)
+    }
+
+    fn json<T: Serialize + Schema>(val: &T) -> String {
+        String::from_utf8(encode(val, ValueFormat::Json).unwrap()).unwrap()
+    }
+
+    fn kinds() -> Vec<(Kind, Value)> {
+        vec![
+            (Kind::Unit, json!("Unit")),
+            (Kind::Newtype(4), json!({"Newtype": 4})),
+            (Kind::Tuple(4, "x".into()), json!({"Tuple": [4, "x"]})),
+            (
+                Kind::Struct { a: -9, b: None },
+                json!({"Struct": {"a": -9, "b": null}}),
+            ),
+        ]
+    }
+
+    #[test]
+    fn json_matches_decoded_values() {
+        let ty = Reading::SCHEMA.into();
+        for (kind, exp) in kinds() {
+            let bytes = postcard::to_stdvec(&reading(kind)).unwrap();
+            let out = encode_topic_raw(&ty, &bytes, ValueFormat::Json).unwrap();
+            let out: Value = serde_json::from_slice(&out).unwrap();
+            assert_eq!(out, DynDecoder::new(&ty).decode(&bytes).unwrap());
+            assert_eq!(out["kind"], exp);
+        }
+    }
+
+    #[test]
+    fn json_differences() {
+        // Declaration order, and f32s as written
+        assert_eq!(
+            json(&reading(Kind::Unit)),
+            r#"{"name":"probe","temps":[20.5,-3.0],"pos":[-1,1],"kind":"Unit","extra":7}"#,
+        );
+        assert_eq!(json(&0.1f32), "0.1");
+        assert_eq!(json(&f64::NAN), "null");
+        assert_eq!(json(&u128::MAX), u128::MAX.to_string());
+        assert_eq!(json(&i128::MIN), i128::MIN.to_string());
+        assert_eq!(json(&'x'), r#""x""#);
+
+        let mut map = BTreeMap::new();
+        map.insert(1u8, 2u8);
+        assert_eq!(json(&map), r#"{"1":2}"#);
+        let mut map = BTreeMap::new();
+        map.insert((1u8, 2u8), 3u8);
+        assert!(encode(&map, ValueFormat::Json).is_err());
+    }
+
+    #[test]
+    fn invalid_data() {
+        let ty = Reading::SCHEMA.into();
+        let bytes = postcard::to_stdvec(&reading(Kind::Unit)).unwrap();
+        for len in 0..bytes.len() {
+            assert!(encode_topic_raw(&ty, &bytes[..len], ValueFormat::Json).is_err());
+        }
+
+        // The last byte of a 128 bit varint only has two bits left
+        let ty: OwnedNamedType = u128::SCHEMA.into();
+        let mut bytes = vec![0xFF; 18];
+        bytes.push(0x03);
+        assert!(encode_topic_raw(&ty, &bytes, ValueFormat::Json).is_ok());
+        *bytes.last_mut().unwrap() = 0x04;
+        assert!(encode_topic_raw(&ty, &bytes, ValueFormat::Json).is_err());
+
+        // Lengths that the data, or the zero-sized item limit, can't back
+        let len = postcard::to_stdvec(&usize::MAX).unwrap();
+        let ty: OwnedNamedType = Vec::<u8>::SCHEMA.into();
+        assert!(encode_topic_raw(&ty, &len, ValueFormat::Json).is_err());
+        let ty: OwnedNamedType = BTreeMap::<(), ()>::SCHEMA.into();
+        assert!(encode_topic_raw(&ty, &len, ValueFormat::Json).is_err());
+    }
+
+    #[test]
+    fn byte_arrays() {
+        let ty = OwnedNamedType {
+            name: "bytes".into(),
+            ty: OwnedDataModelType::ByteArray,
+        };
+        let bytes = [3, 1, 2, 3];
+        let out = encode_topic_raw(&ty, &bytes, ValueFormat::Json).unwrap();
+        assert_eq!(out, b"[1,2,3]");
+
+        #[cfg(feature = "cbor")]
+        {
+            let out = encode_topic_raw(&ty, &bytes, ValueFormat::Cbor).unwrap();
+            let out: ciborium::Value = ciborium::from_reader(out.as_slice()).unwrap();
+            assert_eq!(out, ciborium::Value::Bytes(vec![1, 2, 3]));
+        }
+
+        #[cfg(feature = "msgpack")]
+        {
+            let out = encode_topic_raw(&ty, &bytes, ValueFormat::MessagePack).unwrap();
+            // bin 8, followed by the length
+            assert_eq!(out, [0xC4, 3, 1, 2, 3]);
+        }
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn cbor() {
+        for (kind, exp) in kinds() {
+            let out = encode(&reading(kind), ValueFormat::Cbor).unwrap();
+            let out: Value = ciborium::from_reader(out.as_slice()).unwrap();
+            assert_eq!(out["name"], "probe");
+            assert_eq!(out["temps"], json!([20.5, -3.0]));
+            assert_eq!(out["extra"], 7);
+            assert_eq!(out["kind"], exp);
+        }
+
+        let out = encode(&u128::MAX, ValueFormat::Cbor).unwrap();
+        let out: u128 = ciborium::from_reader(out.as_slice()).unwrap();
+        assert_eq!(out, u128::MAX);
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack() {
+        for (kind, exp) in kinds() {
+            let out = encode(&reading(kind), ValueFormat::MessagePack).unwrap();
+            let out: Value = rmp_serde::from_slice(&out).unwrap();
+            assert_eq!(out["name"], "probe");
+            assert_eq!(out["temps"], json!([20.5, -3.0]));
+            assert_eq!(out["extra"], 7);
+            assert_eq!(out["kind"], exp);
+        }
+
+        let out = encode(&-5i8, ValueFormat::MessagePack).unwrap();
+        // negative fixint
+        assert_eq!(out, [0xFB]);
+    }
+}